@@ -0,0 +1,68 @@
+// Timings of what runs in the MIDI callback for every message, to see
+// regressions in the hot path: `cargo bench`. Each one runs for about a
+// second after a warm-up and prints the average time per call. The mapper
+// is timed at the default level, with the line per value handed to the
+// printer thread as when running, and with -q.
+
+use midir::SendError;
+use pg1000cc::config::Config;
+use pg1000cc::logging::{self, Level};
+use pg1000cc::{parameters, Mapper, MidiSink, Pg1000SysExMessage};
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+
+const RUN_FOR: Duration = Duration::from_secs(1);
+
+struct NullSink;
+
+impl MidiSink for NullSink {
+    fn send(&mut self, _: &[u8]) -> Result<(), SendError> {
+        Ok(())
+    }
+}
+
+fn bench(name: &str, mut f: impl FnMut(usize)) {
+    for i in 0..10_000 {
+        f(i);
+    }
+    let start = Instant::now();
+    let mut calls = 0;
+    while start.elapsed() < RUN_FOR {
+        for _ in 0..1000 {
+            f(calls);
+            calls += 1;
+        }
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / calls as f64;
+    println!("{:<40} {:>10.1} ns", name, nanos);
+}
+
+fn main() {
+    // The lines go nowhere, rather than in between the timings
+    logging::start_printer(Some(Box::new(io::sink())));
+    let config = Config::default();
+    // A slider with a 0-100 range, swept up and down so that no value is
+    // dropped as the same as the last one
+    let id = parameters::id("Upper Common P-ENV Level 0");
+    let slider = match config.sliders.iter().find(|slider| Some(slider.sysex_id()) == id) {
+        Some(slider) => slider.clone(),
+        None => panic!("the built-in mapping has no P-ENV Level 0"),
+    };
+    let sweep: Vec<Vec<u8>> = (0..=100).chain((0..=100).rev()).map(|value| Pg1000SysExMessage::new(slider.sysex_id(), value).to_bytes()).collect();
+
+    bench("Pg1000SysExMessage::from_bytes", |i| {
+        let _ = black_box(Pg1000SysExMessage::from_bytes(black_box(&sweep[i % sweep.len()])));
+    });
+    bench("Slider::sysex_value_as_cc_value", |i| {
+        black_box(slider.sysex_value_as_cc_value(black_box(i as u16 % 101)));
+    });
+    let mut mapper = match Mapper::new(config, vec![("null".to_string(), Box::new(NullSink))]) {
+        Ok(mapper) => mapper,
+        Err(err) => panic!("the built-in mapping doesn't load: {}", err),
+    };
+    // Not on a terminal, it prints every value it sends
+    bench("Mapper::map", |i| mapper.map(black_box(&sweep[i % sweep.len()])));
+    logging::set_level(Level::Error);
+    bench("Mapper::map -q", |i| mapper.map(black_box(&sweep[i % sweep.len()])));
+}
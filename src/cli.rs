@@ -0,0 +1,306 @@
+// Command line options. Anything not given on the command line falls back
+// to the config file, or to the interactive prompts.
+
+use pg1000cc::device::Device;
+use pg1000cc::filter::Filter;
+use pg1000cc::logging::Level;
+use pg1000cc::remap::ChannelMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: pg1000cc [options]
+
+Options:
+  --input <index-or-name>  Input port where the PG-1000 is connected. Ports can be given
+                           by index, by name, or by a unique part of the name. Give it
+                           more than once to merge several inputs
+  --auto                   Find the input port by listening for the PG-1000 on all of them
+  --probe                  With --auto, also ask a D-50 on the same devices to answer
+  --no-reconnect           Don't wait for the input port to come back when it disappears
+  --output <index-or-name>
+                           Existing output port to send the CCs to, or 'virtual'
+                           for a virtual port named after --port-name (the default,
+                           unix only).
+                           Give it more than once to send to several outputs
+  --select-output          Prompt for an existing output port
+  --osc-only               Only send OSC to the osc_destination of the config, no MIDI
+  --bidirectional          Also convert CCs coming back from the DAW into SysEx for the D-50
+  --sysex-output <index-or-name>
+                           Output port where the D-50 is connected (bidirectional mode
+                           and syncing)
+  --sync-on-start          Ask the D-50 for its values at start and send them as CCs,
+                           through the --sysex-output port
+  --cc-input <index-or-name>
+                           Input port to read the CCs from in bidirectional mode, or
+                           'virtual' for a virtual port named after --port-name (the
+                           default, unix only)
+  --client-name <name>     Name of the MIDI clients, each with what it's for after it
+                           (default pg1000cc, e.g. pg1000cc forwarding input)
+  --port-name <name>       Name of the ports pg1000cc opens, virtual ones included
+                           (default pg1000cc)
+  --backend <alsa|jack>    Check that this build uses that MIDI backend, JACK needs a
+                           build with --features jack
+  --channel <1-16>         Output MIDI channel, overrides the config file
+  --rate-limit <ms>        Send each slider at most once per <ms> milliseconds, 0 for
+                           no limit (the default), overrides the config file
+  --filter <types>         Drop these instead of passing them through, e.g. clock,activesense,sysex
+                           (or notes, cc, program, pitchbend, pressure, polypressure, timecode,
+                           common, transport, reset, realtime), overrides the config file
+  --passthrough-channel <1-16 | from->to,...>
+                           Send the channel messages passed through on this channel, or
+                           map channels, e.g. 1->3,2->4, overrides the config file
+  --drop-unmapped-channels With a map, drop the channel messages on the other channels
+  --device <name>          The programmer sending the SysEx: pg1000 (the default), pg800
+                           (JX-8P) or pg300 (Alpha Juno), overrides the config file
+  --stats-interval <s>     Print the statistics every <s> seconds
+  --record <path>          Record the CCs sent into a MIDI file, written on exit
+  --record-passthrough     With --record, also record the messages passed through
+  --config <path>          Config file (default ~/.config/pg1000cc/config.yaml)
+  --profile <name>         Start with this profile of the config file instead of the default
+  --legacy-compat          Send what the first version of pg1000cc did, byte for byte,
+                           instead of the config file or the built-in mapping
+  migrate-legacy [path]    Write the mapping of --legacy-compat to a new config file
+                           (default ~/.config/pg1000cc/config.yaml) and print how it
+                           differs from the built-in one
+  --learn                  Map controls by moving them, saving them to the config file,
+                           then start forwarding
+  verify-device            Ask to move each mapped control in turn and check its address
+                           and range, printing a table of the ones to look at
+  --report <path>          With verify-device, also write the results as JSON
+  --calibrate              With verify-device, save the ranges short sliders reached as
+                           their sysex_range in the config file
+  --list-ports, list-ports List the MIDI input and output ports and exit
+  replay --in <path> [--out <path>]
+                           Translate a capture of PG-1000 messages from a file instead
+                           of a port, writing the output to a file (default stdout).
+                           The capture can be a MIDI file, and an output ending in
+                           .mid is one. convert is the same
+  --spacing <ms>           With replay, put the messages this far apart in the output
+                           MIDI file, instead of the times they have in the capture
+  examples [copy <name>]   List the bundled example configs, or print one to start a
+                           config file from
+  --list-parameters        List the D-50 parameter names and their SysEx addresses
+  --check-config           Check the config file and exit, without opening any ports
+  --dump-config [path]     Write the mapping in use (the config file with the options
+                           above, or the built-in one) as a config file and exit,
+                           to stdout without a path
+  -q, --quiet              Only print errors
+  -v, --verbose            Also print the bytes of every message
+  --json                   Print every event as a JSON object on stdout, everything
+                           else goes to stderr
+  --plain                  Print every message sent instead of the live status display
+  --non-interactive        Never prompt; fail instead if something is missing
+  --daemon, --no-stdin     Don't read stdin, run until SIGINT or SIGTERM (SIGHUP reloads
+                           the config). Implies --non-interactive and --plain
+  -h, --help               Print this help";
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub inputs: Vec<String>,
+    pub auto: bool,
+    pub probe: bool,
+    pub no_reconnect: bool,
+    pub outputs: Vec<String>,
+    pub select_output: bool,
+    pub osc_only: bool,
+    pub bidirectional: bool,
+    pub sysex_output: Option<String>,
+    pub sync_on_start: bool,
+    pub cc_input: Option<String>,
+    pub client_name: Option<String>,
+    pub port_name: Option<String>,
+    /// Output channel as sent on the wire, 0-15.
+    pub channel: Option<u8>,
+    /// Some(None) turns the rate limit off.
+    pub rate_limit: Option<Option<Duration>>,
+    pub filter: Option<Filter>,
+    pub passthrough_channels: Option<ChannelMap>,
+    pub drop_unmapped_channels: bool,
+    pub device: Option<Device>,
+    pub stats_interval: Option<Duration>,
+    pub record: Option<PathBuf>,
+    pub record_passthrough: bool,
+    pub config: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub legacy_compat: bool,
+    /// migrate-legacy, with the file to write or None for the default one
+    pub migrate_legacy: Option<Option<PathBuf>>,
+    pub check_config: bool,
+    /// --dump-config, with the file to write or None for stdout
+    pub dump_config: Option<Option<PathBuf>>,
+    pub list_parameters: bool,
+    pub list_ports: bool,
+    pub replay: bool,
+    pub replay_in: Option<PathBuf>,
+    pub replay_out: Option<PathBuf>,
+    pub replay_spacing: Option<Duration>,
+    pub examples: bool,
+    /// examples copy <name>
+    pub example: Option<String>,
+    pub learn: bool,
+    pub verify_device: bool,
+    pub verify_report: Option<PathBuf>,
+    pub calibrate: bool,
+    pub plain: bool,
+    pub level: Level,
+    pub json: bool,
+    pub non_interactive: bool,
+    pub daemon: bool,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, Box<dyn Error>> {
+        let mut options = Self::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            // Accept both "--flag value" and "--flag=value"
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = |name: &str| match inline.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => Err(format!("{} needs a value", name)),
+            };
+            match flag.as_str() {
+                "--input" => options.inputs.push(value("--input")?),
+                "--auto" => options.auto = true,
+                "--probe" => options.probe = true,
+                "--no-reconnect" => options.no_reconnect = true,
+                "--output" => options.outputs.push(value("--output")?),
+                "--select-output" => options.select_output = true,
+                "--osc-only" => options.osc_only = true,
+                "--bidirectional" => options.bidirectional = true,
+                "--sysex-output" => options.sysex_output = Some(value("--sysex-output")?),
+                "--sync-on-start" => options.sync_on_start = true,
+                "--cc-input" => options.cc_input = Some(value("--cc-input")?),
+                "--client-name" => options.client_name = Some(parse_name("--client-name", &value("--client-name")?)?),
+                "--port-name" => options.port_name = Some(parse_name("--port-name", &value("--port-name")?)?),
+                "--backend" => check_backend(&value("--backend")?)?,
+                "--channel" => options.channel = Some(parse_channel(&value("--channel")?)?),
+                "--rate-limit" => options.rate_limit = Some(parse_rate_limit(&value("--rate-limit")?)?),
+                "--filter" => options.filter = Some(Filter::parse(&value("--filter")?)?),
+                "--passthrough-channel" => options.passthrough_channels = Some(ChannelMap::parse(&value("--passthrough-channel")?)?),
+                "--drop-unmapped-channels" => options.drop_unmapped_channels = true,
+                "--device" => options.device = Some(Device::builtin(&value("--device")?)?),
+                "--stats-interval" => options.stats_interval = Some(parse_stats_interval(&value("--stats-interval")?)?),
+                "--record" => options.record = Some(PathBuf::from(value("--record")?)),
+                "--record-passthrough" => options.record_passthrough = true,
+                "--config" => options.config = Some(PathBuf::from(value("--config")?)),
+                "--profile" => options.profile = Some(value("--profile")?),
+                "--legacy-compat" => options.legacy_compat = true,
+                "migrate-legacy" => options.migrate_legacy = Some(args.next_if(|next| !next.starts_with('-')).map(PathBuf::from)),
+                "--check-config" => options.check_config = true,
+                // The path is optional, so a following option isn't taken for it
+                "--dump-config" => options.dump_config = Some(inline.or_else(|| args.next_if(|next| !next.starts_with('-'))).map(PathBuf::from)),
+                "--list-ports" | "list-ports" => options.list_ports = true,
+                "replay" | "convert" => options.replay = true,
+                "--in" => options.replay_in = Some(PathBuf::from(value("--in")?)),
+                "--out" => options.replay_out = Some(PathBuf::from(value("--out")?)),
+                "--spacing" => options.replay_spacing = Some(parse_spacing(&value("--spacing")?)?),
+                "examples" => options.examples = true,
+                "copy" if options.examples => options.example = Some(value("copy")?),
+                "--list-parameters" => options.list_parameters = true,
+                "--learn" => options.learn = true,
+                "verify-device" => options.verify_device = true,
+                "--report" => options.verify_report = Some(PathBuf::from(value("--report")?)),
+                "--calibrate" => options.calibrate = true,
+                "--plain" => options.plain = true,
+                "-q" | "--quiet" => options.level = Level::Error,
+                "-v" | "--verbose" => options.level = Level::Debug,
+                "--json" => options.json = true,
+                "--non-interactive" => options.non_interactive = true,
+                "--daemon" | "--no-stdin" => options.daemon = true,
+                "-h" | "--help" => options.help = true,
+                other => return Err(format!("unknown option '{}'\n\n{}", other, USAGE).into()),
+            }
+        }
+        // Without stdin there is nobody to answer prompts or to look at them
+        if options.daemon {
+            options.non_interactive = true;
+            options.plain = true;
+        }
+        Ok(options)
+    }
+
+    /// The name of a MIDI client, the --client-name (or pg1000cc) and
+    /// what the client is for, e.g. "pg1000cc forwarding input".
+    pub fn client(&self, purpose: &str) -> String {
+        format!("{} {}", self.client_name(), purpose)
+    }
+
+    pub fn client_name(&self) -> &str {
+        self.client_name.as_deref().unwrap_or(DEFAULT_NAME)
+    }
+
+    /// The name of the ports opened, virtual or connected to.
+    pub fn port_name(&self) -> &str {
+        self.port_name.as_deref().unwrap_or(DEFAULT_NAME)
+    }
+}
+
+/// The name of the clients and ports without --client-name and --port-name.
+const DEFAULT_NAME: &str = "pg1000cc";
+
+/// The MIDI backend midir was built with, there is only ever one.
+const BACKEND: &str = if cfg!(feature = "jack") {
+    "jack"
+} else if cfg!(target_os = "linux") {
+    "alsa"
+} else if cfg!(target_os = "macos") {
+    "coremidi"
+} else {
+    "winmm"
+};
+
+fn parse_name(option: &str, text: &str) -> Result<String, Box<dyn Error>> {
+    match text.trim() {
+        "" => Err(format!("{} needs a name that isn't empty", option).into()),
+        name => Ok(name.to_string()),
+    }
+}
+
+/// midir only has one backend in a build, so --backend can't switch, only
+/// say that the other one needs another build.
+pub fn check_backend(text: &str) -> Result<(), Box<dyn Error>> {
+    match text.trim().to_lowercase().as_str() {
+        backend if backend == BACKEND => Ok(()),
+        "jack" => Err(format!("this pg1000cc uses {}, JACK needs a build with --features jack", BACKEND.to_uppercase()).into()),
+        "alsa" if cfg!(target_os = "linux") => Err("this pg1000cc uses JACK, ALSA needs a build without --features jack".into()),
+        "alsa" => Err(format!("this pg1000cc uses {}, ALSA is only there on Linux", BACKEND.to_uppercase()).into()),
+        _ => Err(format!("invalid backend '{}', expected alsa or jack", text).into()),
+    }
+}
+
+/// Parses a 1-16 channel number as written by humans into the 0-15 wire value.
+pub fn parse_channel(text: &str) -> Result<u8, Box<dyn Error>> {
+    match text.trim().parse::<u8>() {
+        Ok(channel @ 1..=16) => Ok(channel - 1),
+        _ => Err(format!("invalid channel '{}', expected a number 1-16", text).into()),
+    }
+}
+
+pub fn parse_rate_limit(text: &str) -> Result<Option<Duration>, Box<dyn Error>> {
+    match text.trim().parse::<u64>() {
+        Ok(0) => Ok(None),
+        Ok(ms @ 1..=1000) => Ok(Some(Duration::from_millis(ms))),
+        _ => Err(format!("invalid rate limit '{}', expected milliseconds 0-1000", text).into()),
+    }
+}
+
+pub fn parse_spacing(text: &str) -> Result<Duration, Box<dyn Error>> {
+    match text.trim().parse::<u64>() {
+        Ok(ms @ 0..=60_000) => Ok(Duration::from_millis(ms)),
+        _ => Err(format!("invalid spacing '{}', expected milliseconds 0-60000", text).into()),
+    }
+}
+
+pub fn parse_stats_interval(text: &str) -> Result<Duration, Box<dyn Error>> {
+    match text.trim().parse::<u64>() {
+        Ok(seconds @ 1..=86400) => Ok(Duration::from_secs(seconds)),
+        _ => Err(format!("invalid stats interval '{}', expected seconds 1-86400", text).into()),
+    }
+}
@@ -0,0 +1,2271 @@
+// Slider mapping configuration, read from a YAML file. See
+// pg1000cc.sample.yaml in the repository for a documented example.
+
+use crate::device::Device;
+use crate::expr::Formula;
+use crate::filter::Filter;
+use crate::parameters;
+use crate::remap::ChannelMap;
+use crate::transform::{NoteTransform, VelocityCurve};
+use crate::yaml::{self, Node};
+use crate::{CcMode, Curve, Mapper, MidiRange, MidiValue, Output, Relative, Slider, SysExId};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Output channel as sent on the wire, 0-15.
+    pub channel: u8,
+    pub sliders: Vec<Slider>,
+    /// Send each slider at most once per this interval, None for no limit.
+    pub rate_limit: Option<Duration>,
+    /// Whether a program change on the input puts the pickup sliders into
+    /// waiting for their last values.
+    pub pickup_after_program_change: bool,
+    /// host:port to send OSC to, besides the MIDI output.
+    pub osc_destination: Option<String>,
+    /// A CC that, arriving on the channel, makes every slider send its
+    /// last value again.
+    pub resend_trigger_cc: Option<u8>,
+    /// The messages dropped instead of passed through.
+    pub filter: Filter,
+    /// The outputs (indexes into the open ones) the messages passed
+    /// through go to, None for all of them.
+    pub passthrough_outputs: Option<Vec<usize>>,
+    /// The channels the channel messages passed through are sent on.
+    pub passthrough_channels: ChannelMap,
+    /// What is done to the notes passed through from some of the inputs,
+    /// at most one for each.
+    pub note_transforms: Vec<NoteTransform>,
+    /// The program change on the input that switches back to the channel
+    /// and sliders above, the "default" profile.
+    pub program_change: Option<u8>,
+    /// Named alternatives to the channel and sliders above.
+    pub profiles: Vec<Profile>,
+    /// The programmer sending the SysEx, the PG-1000 by default.
+    pub device: Device,
+    /// What the other tone's addresses of the sliders do.
+    pub upper_lower: UpperLower,
+    /// In split mode the channel of the Lower tone's sliders, None for the
+    /// same channel as the Upper ones.
+    pub lower_channel: Option<u8>,
+    /// What happens to SysEx values outside a slider's sysex_range.
+    pub out_of_range: OutOfRange,
+    /// Whether the SysEx of a slider is sent on too, besides what it is
+    /// translated into, and which goes first. None replaces it.
+    pub tee: Option<Tee>,
+    /// The outputs the SysEx sent on by tee goes to, None for all of them.
+    pub tee_outputs: Option<Vec<usize>>,
+    /// Messages lighting up which slider moved last, on a controller.
+    pub feedback: Option<Feedback>,
+    /// Sent once the outputs are open, and again whenever an input comes
+    /// back, for devices that need waking up before they listen.
+    pub startup_sequence: Vec<StartupStep>,
+    /// Active Sensing sent by pg1000cc itself, None to only pass on what
+    /// comes from the inputs.
+    pub active_sensing: Option<ActiveSensing>,
+    /// Whether a slider sends a value again when it is the one it sent
+    /// last, as the first version did, rather than leaving it out.
+    pub send_repeats: bool,
+    /// Which of the sources moving a slider wins, None for whichever came
+    /// last. Sliders can have their own.
+    pub arbitration: Option<Arbitration>,
+    /// CCs computed from the positions of several sliders.
+    pub virtual_params: Vec<VirtualParam>,
+}
+
+/// The PG-1000 sends the same slider to the Upper or the Lower tone's
+/// address depending on its UPPER/LOWER selection.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UpperLower {
+    /// Only the addresses of the sliders count, the other tone's are
+    /// passed through.
+    #[default]
+    Separate,
+    /// Both tones' addresses drive the same output.
+    Merged,
+    /// The sliders given with the Upper addresses get the Lower ones as
+    /// sliders of their own, on the lower_channel or their lower_cc_id.
+    Split,
+}
+
+/// A value outside the sysex_range usually means the range in the config is
+/// wrong, so by default it is taken as the nearest end and reported.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutOfRange {
+    #[default]
+    Warn,
+    Clamp,
+    Drop,
+}
+
+/// For software after pg1000cc that understands the SysEx itself, e.g. a
+/// librarian, or the D-50 on a thru chain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tee {
+    SysExFirst,
+    CcFirst,
+}
+
+/// A note or CC for each slider with a `feedback` number, sent when it
+/// moves and taken back once it has rested for `hold`, for a controller
+/// that lights its pads from what it receives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feedback {
+    /// Channel as sent on the wire, 0-15.
+    pub channel: u8,
+    pub message: FeedbackMessage,
+    /// The velocity or CC value that lights it, 0 taking it back.
+    pub value: u8,
+    pub hold: Duration,
+    /// The outputs it goes to, None for all of them.
+    pub outputs: Option<Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FeedbackMessage {
+    #[default]
+    Note,
+    Cc,
+}
+
+impl Default for Feedback {
+    fn default() -> Self {
+        Self { channel: 15, message: FeedbackMessage::Note, value: 127, hold: Duration::from_millis(200), outputs: None }
+    }
+}
+
+impl Feedback {
+    /// The message lighting up `number`, or taking it back.
+    pub fn to_bytes(&self, number: u8, lit: bool) -> [u8; 3] {
+        match (self.message, lit) {
+            (FeedbackMessage::Note, true) => [0x90 | self.channel, number, self.value],
+            (FeedbackMessage::Note, false) => [0x80 | self.channel, number, 0],
+            (FeedbackMessage::Cc, lit) => [0xb0 | self.channel, number, if lit { self.value } else { 0 }],
+        }
+    }
+}
+
+/// Active Sensing for devices that let go of their notes when it stops,
+/// sent whatever the inputs do. The inputs that send it are watched for it
+/// stopping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveSensing {
+    /// The outputs it goes to, None for all of them.
+    pub outputs: Option<Vec<usize>>,
+    pub interval: Duration,
+    /// How long an input that has sent it can go without before it counts
+    /// as lost.
+    pub input_timeout: Duration,
+}
+
+impl Default for ActiveSensing {
+    fn default() -> Self {
+        // Well inside the 300 ms the receivers wait
+        Self { outputs: None, interval: Duration::from_millis(250), input_timeout: Duration::from_millis(350) }
+    }
+}
+
+/// Where a new value for a slider comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Source {
+    /// The PG-1000's own SysEx.
+    Physical,
+    /// The DAW's feedback of the slider's CC, going back to the D-50.
+    Reverse,
+    /// The D-50's answer to a sync.
+    Sync,
+}
+
+impl Source {
+    pub const ALL: [Self; 3] = [Self::Physical, Self::Reverse, Self::Sync];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Physical => "physical",
+            Self::Reverse => "reverse",
+            Self::Sync => "sync",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which source wins when several of them move the same slider: the one
+/// first in `order` that has moved it within `hold` holds off the ones
+/// after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arbitration {
+    /// The sources not in it come after the ones that are.
+    pub order: Vec<Source>,
+    pub hold: Duration,
+    /// Whether a source that was held off gets through again as soon as
+    /// the hold is over, rather than only once it has gone quiet for as
+    /// long, e.g. DAW automation staying paused after the slider was
+    /// grabbed until it is played again.
+    pub resume: bool,
+}
+
+impl Default for Arbitration {
+    fn default() -> Self {
+        Self { order: vec![Source::Physical, Source::Reverse, Source::Sync], hold: Duration::from_millis(500), resume: true }
+    }
+}
+
+impl Arbitration {
+    /// The place of a source in the order, lower winning.
+    pub fn rank(&self, source: Source) -> usize {
+        self.order.iter().position(|other| *other == source).unwrap_or(self.order.len())
+    }
+}
+
+/// A CC of its own computed from where several sliders are, sent whenever
+/// one of them moves, e.g. one brightness for two TVF cutoffs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualParam {
+    /// What the formulas of the others call it.
+    pub name: String,
+    pub cc_id: u8,
+    /// As sent on the wire, None for the top level channel.
+    pub channel: Option<u8>,
+    pub cc_range: MidiRange,
+    /// Over the sliders, by their labels, and other virtual params, by
+    /// their names, each at 0-100, giving 0-100 of cc_range.
+    pub formula: Formula,
+    /// Where a slider is taken to be, 0-100, before it has sent anything,
+    /// 0 for those not given.
+    pub defaults: Vec<(String, u8)>,
+    /// The outputs it goes to, None for all of them.
+    pub outputs: Option<Vec<usize>>,
+}
+
+/// One entry of the startup_sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartupStep {
+    pub action: StartupAction,
+    /// The outputs it goes to, None for all of them.
+    pub outputs: Option<Vec<usize>>,
+    pub on_failure: OnFailure,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartupAction {
+    /// A whole MIDI message: SysEx, or a CC or program change on a channel.
+    Send(Vec<u8>),
+    /// A pause before the next entry, for a device that is still busy.
+    Delay(Duration),
+}
+
+/// What happens to the rest of the startup_sequence when an entry reaches
+/// none of its outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnFailure {
+    #[default]
+    Abort,
+    Continue,
+}
+
+/// A slider table of its own, with its own channel, to switch to at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Output channel as sent on the wire, 0-15.
+    pub channel: u8,
+    pub sliders: Vec<Slider>,
+    /// The program change on the input that switches to this profile.
+    pub program_change: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub path: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn at(node: &Node, key: &str, message: impl Into<String>) -> Self {
+        Self { path: None, line: Some(node.line), key: Some(key.to_string()), message: message.into() }
+    }
+
+    fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "{}:", line)?;
+        }
+        if self.path.is_some() || self.line.is_some() {
+            write!(f, " ")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<yaml::ParseError> for ConfigError {
+    fn from(err: yaml::ParseError) -> Self {
+        Self { path: None, line: Some(err.line), key: None, message: err.message }
+    }
+}
+
+// CCs 120-127 are channel mode messages
+const MAX_CC_ID: u8 = 119;
+
+// The top of the range of each partial parameter, in address order, and
+// whether it is a switch-like one to map stepped
+const PARTIAL_RANGES: [(MidiValue, bool); 54] = [
+    // WG: pitch coarse, fine and keyfollow, LFO, P-ENV and bender modes,
+    // waveform, PCM wave, pulse width, its velocity range, LFO select and
+    // depth, aftertouch range
+    (72, true), (100, false), (16, true), (3, true), (2, true), (2, true),
+    (1, true), (99, true), (100, false), (14, true), (5, true), (100, false), (14, true),
+    // TVF: cutoff, resonance, keyfollow, bias point and level, ENV depth,
+    // velocity range, depth and time keyfollow, T1-T5, L1-L3, sustain and end
+    // level, LFO select and depth, aftertouch range
+    (100, false), (30, true), (14, true), (127, false), (14, true), (100, false),
+    (100, false), (4, true), (4, true),
+    (100, false), (100, false), (100, false), (100, false), (100, false),
+    (100, false), (100, false), (100, false), (100, false), (1, true),
+    (5, true), (100, false), (14, true),
+    // TVA: level, velocity range, bias point and level, T1-T5, L1-L3, sustain
+    // and end level, T1 velocity follow, time keyfollow, LFO select and
+    // depth, aftertouch range
+    (100, false), (100, false), (127, false), (12, true),
+    (100, false), (100, false), (100, false), (100, false), (100, false),
+    (100, false), (100, false), (100, false), (100, false), (1, true),
+    (4, true), (4, true), (5, true), (100, false), (14, true),
+];
+
+impl Default for Config {
+    fn default() -> Self {
+        // These are all the sliders on the PG-1000, that have values ranging from 0-100.
+        // The rest of the sliders have considerably smaller resolution,
+        // ranging e.g. 0-4. Seems their original purpose is to act as
+        // switches. Those are mapped in stepped mode after the sliders.
+        //
+        // These assume patch edit mode (LOWER or UPPER led is lit under COMMON SELECT).
+        // When a partial has been selected the sliders send to its addresses
+        // instead. There aren't enough free CCs left for those, so the partials
+        // are sent as NRPNs, numbered by their SysEx address.
+
+        let free_ccs = Mapper::FREE_CCS;
+        let free_lsb_ccs = Mapper::FREE_LSB_CCS;
+        let default_cc_range = MidiRange::new(0, 127);
+        let default_sysex_range = MidiRange::new(0, 100);
+        let mut sliders = vec![
+            Slider::new(0x000319, free_ccs[0], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000318, free_ccs[1], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000321, free_ccs[2], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00031C, free_ccs[3], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000323, free_ccs[4], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000324, free_ccs[5], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00012F, free_ccs[6], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000116, free_ccs[7], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000117, free_ccs[8], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000118, free_ccs[9], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00011A, free_ccs[10], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00011B, free_ccs[11], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00011E, free_ccs[12], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00011F, free_ccs[13], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000122, free_ccs[14], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000123, free_ccs[15], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00012B, free_ccs[16], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00012C, free_ccs[17], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000320, free_ccs[18], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000111, free_ccs[19], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000112, free_ccs[20], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000113, free_ccs[21], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000114, free_ccs[22], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x000115, free_ccs[23], default_sysex_range.clone(), default_cc_range.clone()),
+
+            // T1 - T4 have a range of 0-50, use those as well.
+            Slider::new(0x00010D, free_ccs[24], MidiRange::new(0, 50), default_cc_range.clone()),
+            Slider::new(0x00010E, free_ccs[25], MidiRange::new(0, 50), default_cc_range.clone()),
+            Slider::new(0x00010F, free_ccs[26], MidiRange::new(0, 50), default_cc_range.clone()),
+            Slider::new(0x000110, free_ccs[27], MidiRange::new(0, 50), default_cc_range.clone()),
+
+            // The switch-like controls, common...
+            Slider::new(0x00010A, free_ccs[28], MidiRange::new(0, 6), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00010B, free_ccs[29], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00010C, free_ccs[30], MidiRange::new(0, 4), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000119, free_ccs[31], MidiRange::new(0, 3), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00011C, free_ccs[32], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00011D, free_ccs[33], MidiRange::new(0, 3), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000120, free_ccs[34], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000121, free_ccs[35], MidiRange::new(0, 3), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000124, free_ccs[36], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000125, free_ccs[37], MidiRange::new(0, 15), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000126, free_ccs[38], MidiRange::new(0, 24), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000127, free_ccs[39], MidiRange::new(0, 21), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000128, free_lsb_ccs[0], MidiRange::new(0, 8), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000129, free_lsb_ccs[1], MidiRange::new(0, 24), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00012A, free_lsb_ccs[2], MidiRange::new(0, 7), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00012E, free_lsb_ccs[3], MidiRange::new(0, 3), default_cc_range.clone()).with_stepped(true),
+
+            // ...and patch.
+            Slider::new(0x000312, free_lsb_ccs[4], MidiRange::new(0, 8), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000313, free_lsb_ccs[5], MidiRange::new(0, 60), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000314, free_lsb_ccs[6], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000315, free_lsb_ccs[7], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000316, free_lsb_ccs[8], MidiRange::new(0, 48), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000317, free_lsb_ccs[9], MidiRange::new(0, 48), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00031A, free_lsb_ccs[10], MidiRange::new(0, 12), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00031B, free_lsb_ccs[11], MidiRange::new(0, 24), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00031D, free_lsb_ccs[12], MidiRange::new(0, 3), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x00031E, free_lsb_ccs[13], MidiRange::new(0, 31), default_cc_range.clone()).with_stepped(true),
+            Slider::new(0x000322, free_lsb_ccs[14], MidiRange::new(0, 2), default_cc_range.clone()).with_stepped(true),
+
+            // The last free CC, and the one slider left over goes with the partials.
+            Slider::new(0x00012D, free_lsb_ccs[15], default_sysex_range.clone(), default_cc_range.clone()),
+            Slider::new(0x00031F, 0, default_sysex_range.clone(), default_cc_range.clone()).with_output(Output::Nrpn(0x031F)),
+        ];
+        for partial in [0x000000, 0x000040] {
+            for (offset, &(hi, stepped)) in PARTIAL_RANGES.iter().enumerate() {
+                let id = partial + offset as SysExId;
+                let slider = Slider::new(id, 0, MidiRange::new(0, hi), default_cc_range.clone());
+                sliders.push(slider.with_output(Output::Nrpn(id as u16)).with_stepped(stepped));
+            }
+        }
+
+        Self {
+            channel: 1,
+            sliders,
+            rate_limit: None,
+            pickup_after_program_change: true,
+            osc_destination: None,
+            resend_trigger_cc: None,
+            filter: Filter::default(),
+            passthrough_outputs: None,
+            passthrough_channels: ChannelMap::default(),
+            note_transforms: vec![],
+            program_change: None,
+            profiles: vec![],
+            device: Device::default(),
+            upper_lower: UpperLower::Separate,
+            lower_channel: None,
+            out_of_range: OutOfRange::Warn,
+            tee: None,
+            tee_outputs: None,
+            feedback: None,
+            startup_sequence: vec![],
+            active_sensing: None,
+            send_repeats: false,
+            arbitration: None,
+            virtual_params: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// The name of the channel and sliders at the top level of the file.
+    pub const DEFAULT_PROFILE: &'static str = "default";
+
+    /// More outputs than this can't be opened, nor routed to.
+    pub const MAX_OUTPUTS: usize = 16;
+
+    /// What the first version of pg1000cc sent, byte for byte, for DAW
+    /// projects learned against it: its 28 sliders on the free CCs in the
+    /// order it had them, on channel 2 (it said 1, but counted from 0),
+    /// rounded down, every value sent even when it is the last one again,
+    /// and nothing passed through. It took any 11 byte SysEx, looking only
+    /// at the last two bytes of the address and the value, and values
+    /// past the end of the range as the end.
+    pub fn legacy() -> Self {
+        // In its table 0x000113 was given twice, the second time with the
+        // same CC as the first, so the ones after it kept theirs
+        const SLIDERS: [(SysExId, MidiValue); 28] = [
+            (0x000319, 100), (0x000318, 100), (0x000321, 100), (0x00031C, 100), (0x000323, 100), (0x000324, 100), (0x00012F, 100),
+            (0x000116, 100), (0x000117, 100), (0x000118, 100), (0x00011A, 100), (0x00011B, 100), (0x00011E, 100), (0x00011F, 100),
+            (0x000122, 100), (0x000123, 100), (0x00012B, 100), (0x00012C, 100), (0x000320, 100), (0x000111, 100), (0x000112, 100),
+            (0x000113, 100), (0x000114, 100), (0x000115, 100), (0x00010D, 50), (0x00010E, 50), (0x00010F, 50), (0x000110, 50),
+        ];
+        let sliders = SLIDERS
+            .iter()
+            .zip(Mapper::FREE_CCS)
+            .map(|(&(id, hi), &cc_id)| Slider::new(id, cc_id, MidiRange::new(0, hi), MidiRange::new(0, 127)).with_truncate(true))
+            .collect();
+        let everything = ["notes", "polypressure", "cc", "program", "pressure", "pitchbend", "sysex", "timecode", "common", "realtime"];
+        let device = Device {
+            name: "legacy".to_string(),
+            length: 11,
+            header: vec![Some(0xf0)],
+            address_offset: 6,
+            address_width: 2,
+            value_offset: 8,
+            checksum_from: None,
+            d50: false,
+        };
+        Self {
+            channel: 1,
+            sliders,
+            filter: Filter::from_names(everything).expect("the names are the filter's own"),
+            device,
+            out_of_range: OutOfRange::Clamp,
+            send_repeats: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names = vec![Self::DEFAULT_PROFILE];
+        names.extend(self.profiles.iter().map(|profile| profile.name.as_str()));
+        names
+    }
+
+    /// The config with the channel and sliders of the named profile in
+    /// place of the default ones, and without the virtual params.
+    pub fn profile(&self, name: &str) -> Result<Config, String> {
+        if name == Self::DEFAULT_PROFILE {
+            return Ok(self.clone());
+        }
+        match self.profiles.iter().find(|profile| profile.name == name) {
+            // The virtual params are made of the top level sliders
+            Some(profile) => Ok(Config { channel: profile.channel, sliders: profile.sliders.clone(), virtual_params: vec![], ..self.clone() }),
+            None => Err(format!("unknown profile '{}', the profiles are: {}", name, self.profile_names().join(", "))),
+        }
+    }
+
+    /// In split mode, the Lower tone's sliders made of the `sliders` given
+    /// with the Upper addresses, each with the index of the one it is made
+    /// of. A slider given with the Lower address already stays as it is.
+    pub fn lower_sliders(&self, sliders: &[Slider]) -> Vec<(usize, Slider)> {
+        if self.upper_lower != UpperLower::Split || !self.device.d50 {
+            return vec![];
+        }
+        let given = |id: SysExId| sliders.iter().any(|slider| slider.sysex_id == id);
+        sliders
+            .iter()
+            .enumerate()
+            .filter(|(_, slider)| parameters::is_upper(slider.sysex_id))
+            .filter_map(|(i, slider)| {
+                let lower = parameters::other_tone(slider.sysex_id).filter(|lower| !given(*lower))?;
+                let mut twin = slider.clone();
+                twin.sysex_id = lower;
+                twin.channel = self.lower_channel.or(slider.channel);
+                if let (Output::Cc(_), Some(cc_id)) = (slider.output, slider.lower_cc_id) {
+                    twin.output = Output::Cc(cc_id);
+                }
+                twin.lower_cc_id = None;
+                twin.name = slider.name.as_ref().map(|name| format!("{} (Lower)", name));
+                twin.osc_address = slider.osc_address.as_ref().map(|address| format!("{}/lower", address));
+                Some((i, twin))
+            })
+            .collect()
+    }
+
+    /// In merged mode, the other tone's address of each of the `sliders`,
+    /// with the address of the slider to drive instead.
+    pub fn tone_aliases(&self, sliders: &[Slider]) -> HashMap<SysExId, SysExId> {
+        if self.upper_lower != UpperLower::Merged || !self.device.d50 {
+            return HashMap::new();
+        }
+        sliders
+            .iter()
+            .filter_map(|slider| Some((parameters::other_tone(slider.sysex_id)?, slider.sysex_id)))
+            .filter(|(other, _)| !sliders.iter().any(|slider| slider.sysex_id == *other))
+            .collect()
+    }
+
+    /// The profile a program change on the input switches to, if any.
+    pub fn profile_for_program(&self, program: u8) -> Option<&str> {
+        if self.program_change == Some(program) {
+            return Some(Self::DEFAULT_PROFILE);
+        }
+        self.profiles.iter().find(|profile| profile.program_change == Some(program)).map(|profile| profile.name.as_str())
+    }
+
+    /// `$XDG_CONFIG_HOME/pg1000cc/config.yaml`, or `~/.config/pg1000cc/config.yaml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("pg1000cc").join("config.yaml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let source = fs::read_to_string(path).map_err(|err| ConfigError {
+            path: Some(path.to_path_buf()),
+            line: None,
+            key: None,
+            message: err.to_string(),
+        })?;
+        let config = Self::parse(&source).map_err(|err| err.with_path(path))?;
+        config.validate().map_err(|err| err.with_path(path))?;
+        Ok(config)
+    }
+
+    /// Puts the sliders, and those of each profile, in address order. The
+    /// mapping doesn't depend on it, there is one slider per address.
+    pub fn sort_sliders(&mut self) {
+        self.sliders.sort_by_key(|slider| slider.sysex_id);
+        for profile in &mut self.profiles {
+            profile.sliders.sort_by_key(|slider| slider.sysex_id);
+        }
+    }
+
+    /// Writes the config out as YAML that `parse` reads back. Comments of
+    /// the original file are not kept.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let io_error = |err: std::io::Error| ConfigError { path: Some(path.to_path_buf()), line: None, key: None, message: err.to_string() };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        fs::write(path, self.to_yaml()).map_err(io_error)
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::from("# Written by pg1000cc\n");
+        if self.device != Device::default() {
+            out += &device_to_yaml(&self.device);
+        }
+        match self.upper_lower {
+            UpperLower::Separate => (),
+            UpperLower::Merged => out += "upper_lower: merged\n",
+            UpperLower::Split => out += "upper_lower: split\n",
+        }
+        if let Some(channel) = self.lower_channel {
+            out += &format!("lower_channel: {}\n", channel + 1);
+        }
+        match self.out_of_range {
+            OutOfRange::Warn => (),
+            OutOfRange::Clamp => out += "out_of_range: clamp\n",
+            OutOfRange::Drop => out += "out_of_range: drop\n",
+        }
+        out += &format!("channel: {}\n", self.channel + 1);
+        if let Some(rate_limit) = self.rate_limit {
+            out += &format!("rate_limit_ms: {}\n", rate_limit.as_millis());
+        }
+        if !self.pickup_after_program_change {
+            out += "pickup_after_program_change: false\n";
+        }
+        if self.send_repeats {
+            out += "send_repeats: true\n";
+        }
+        if let Some(cc) = self.resend_trigger_cc {
+            out += &format!("resend_trigger_cc: {}\n", cc);
+        }
+        if let Some(destination) = &self.osc_destination {
+            out += &format!("osc_destination: \"{}\"\n", destination);
+        }
+        if !self.filter.is_empty() {
+            out += &format!("filter: [{}]\n", self.filter.names().join(", "));
+        }
+        if let Some(outputs) = &self.passthrough_outputs {
+            out += &format!("passthrough_outputs: {}\n", outputs_to_yaml(outputs));
+        }
+        match self.passthrough_channels.to_string() {
+            channels if channels.contains("->") => out += &format!("passthrough_channel: \"{}\"\n", channels),
+            channel if self.passthrough_channels.has_channels() => out += &format!("passthrough_channel: {}\n", channel),
+            _ => (),
+        }
+        if self.passthrough_channels.drop_unmapped {
+            out += "passthrough_unmapped: drop\n";
+        }
+        if !self.note_transforms.is_empty() {
+            out += "note_transforms:\n";
+        }
+        for transform in &self.note_transforms {
+            out += &note_transform_to_yaml(transform);
+        }
+        match self.tee {
+            None => (),
+            Some(Tee::SysExFirst) => out += "tee: sysex_first\n",
+            Some(Tee::CcFirst) => out += "tee: cc_first\n",
+        }
+        if let Some(outputs) = &self.tee_outputs {
+            out += &format!("tee_outputs: {}\n", outputs_to_yaml(outputs));
+        }
+        if let Some(feedback) = &self.feedback {
+            out += &feedback_to_yaml(feedback);
+        }
+        if let Some(program) = self.program_change {
+            out += &format!("program_change: {}\n", program);
+        }
+        if let Some(sensing) = &self.active_sensing {
+            out += &format!("active_sensing:\n  interval_ms: {}\n  input_timeout_ms: {}\n", sensing.interval.as_millis(), sensing.input_timeout.as_millis());
+            if let Some(outputs) = &sensing.outputs {
+                out += &format!("  outputs: {}\n", outputs_to_yaml(outputs));
+            }
+        }
+        if let Some(arbitration) = &self.arbitration {
+            out += "arbitration:\n";
+            out += &arbitration_to_yaml(arbitration, "  ");
+        }
+        if !self.virtual_params.is_empty() {
+            out += "virtual_params:\n";
+        }
+        for param in &self.virtual_params {
+            out += &virtual_param_to_yaml(param);
+        }
+        if !self.startup_sequence.is_empty() {
+            out += "startup_sequence:\n";
+        }
+        for step in &self.startup_sequence {
+            out += &startup_step_to_yaml(step);
+        }
+        out += "sliders:\n";
+        out += &sliders_to_yaml(&self.sliders, &self.device);
+        if !self.profiles.is_empty() {
+            out += "profiles:\n";
+        }
+        for profile in &self.profiles {
+            out += &format!("  \"{}\":\n", profile.name);
+            out += &format!("    channel: {}\n", profile.channel + 1);
+            if let Some(program) = profile.program_change {
+                out += &format!("    program_change: {}\n", program);
+            }
+            out += "    sliders:\n";
+            for line in sliders_to_yaml(&profile.sliders, &self.device).lines() {
+                out += &format!("    {}\n", line);
+            }
+        }
+        out
+    }
+
+    /// The virtual params, each after the ones its formula uses, or the
+    /// ones going round in a cycle.
+    pub fn virtual_params_in_order(&self) -> Result<Vec<VirtualParam>, String> {
+        let params = &self.virtual_params;
+        let index: HashMap<&str, usize> = params.iter().enumerate().map(|(i, param)| (param.name.as_str(), i)).collect();
+        let mut order: Vec<VirtualParam> = vec![];
+        // The ones on the way to the one being visited
+        let mut path: Vec<usize> = vec![];
+        fn visit(i: usize, params: &[VirtualParam], index: &HashMap<&str, usize>, path: &mut Vec<usize>, order: &mut Vec<VirtualParam>) -> Result<(), String> {
+            if order.iter().any(|done| done.name == params[i].name) {
+                return Ok(());
+            }
+            if let Some(start) = path.iter().position(|on_path| *on_path == i) {
+                let names: Vec<&str> = path[start..].iter().chain([&i]).map(|j| params[*j].name.as_str()).collect();
+                return Err(format!("{} go round in a cycle", names.join(" -> ")));
+            }
+            path.push(i);
+            for name in params[i].formula.names() {
+                if let Some(&used) = index.get(name) {
+                    visit(used, params, index, path, order)?;
+                }
+            }
+            path.pop();
+            order.push(params[i].clone());
+            Ok(())
+        }
+        for i in 0..params.len() {
+            visit(i, params, &index, &mut path, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// What is wrong with the virtual params: names the formulas use that
+    /// aren't there, defaults for names they don't use, CCs taken by the
+    /// top level sliders or each other, and cycles.
+    fn virtual_param_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let labels: HashSet<String> = self.sliders.iter().map(|slider| slider.label(&self.device)).collect();
+        let mut ccs: HashMap<(u8, u8), String> = self
+            .sliders
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slider)| Some(((slider.channel.unwrap_or(self.channel), slider.cc_id()?), format!("sliders[{}] (sysex_id 0x{:06X})", i, slider.sysex_id))))
+            .collect();
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        for (i, param) in self.virtual_params.iter().enumerate() {
+            let name = format!("virtual_params[{}] ({})", i, param.name);
+            if labels.contains(&param.name) {
+                problems.push(format!("{}: a slider is named that too", name));
+            }
+            if let Some(other) = names.insert(&param.name, i) {
+                problems.push(format!("{}: same name as virtual_params[{}]", name, other));
+            }
+            let used = param.formula.names();
+            for source in &used {
+                if !labels.contains(*source) && !self.virtual_params.iter().any(|other| other.name == *source) {
+                    problems.push(format!("{}: there is no slider or virtual param named '{}'", name, source));
+                }
+            }
+            for (source, _) in &param.defaults {
+                if !used.contains(&source.as_str()) {
+                    problems.push(format!("{}: defaults has '{}', which the formula doesn't use", name, source));
+                } else if self.virtual_params.iter().any(|other| other.name == *source) {
+                    problems.push(format!("{}: defaults has '{}', a virtual param, which always has a value", name, source));
+                }
+            }
+            let channel = param.channel.unwrap_or(self.channel);
+            if let Some(other) = ccs.insert((channel, param.cc_id), name.clone()) {
+                problems.push(format!("{}: CC {} on channel {} is used by {} too", name, param.cc_id, channel + 1, other));
+            }
+        }
+        if let Err(cycle) = self.virtual_params_in_order() {
+            problems.push(format!("virtual_params: {}", cycle));
+        }
+        problems
+    }
+
+    /// Checks the sliders against each other and against what MIDI allows,
+    /// reporting all problems at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let lower = self.lower_sliders(&self.sliders);
+        let mut problems = slider_problems(&self.sliders, &lower, "sliders", self.channel, self.resend_trigger_cc);
+        let mut programs: HashMap<u8, &str> = HashMap::new();
+        if let Some(program) = self.program_change {
+            programs.insert(program, Self::DEFAULT_PROFILE);
+        }
+        for profile in &self.profiles {
+            let path = format!("profiles.{}", profile.name);
+            let sliders = format!("{}.sliders", path);
+            let lower = self.lower_sliders(&profile.sliders);
+            problems.extend(slider_problems(&profile.sliders, &lower, &sliders, profile.channel, self.resend_trigger_cc));
+            if let Some(program) = profile.program_change {
+                if let Some(other) = programs.insert(program, &profile.name) {
+                    problems.push(format!("{}.program_change: {} already switches to profile '{}'", path, program, other));
+                }
+            }
+        }
+        problems.extend(self.virtual_param_problems());
+        if self.feedback.is_none() {
+            let tables = std::iter::once(("sliders".to_string(), &self.sliders))
+                .chain(self.profiles.iter().map(|profile| (format!("profiles.{}.sliders", profile.name), &profile.sliders)));
+            for (path, sliders) in tables {
+                for (i, slider) in sliders.iter().enumerate().filter(|(_, slider)| slider.feedback.is_some()) {
+                    problems.push(format!("{}[{}] (sysex_id 0x{:06X}): feedback needs a feedback section to send it", path, i, slider.sysex_id));
+                }
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(ConfigError {
+            path: None,
+            line: None,
+            key: None,
+            message: format!("{} problem(s) in the sliders:\n  {}", problems.len(), problems.join("\n  ")),
+        })
+    }
+
+    /// What --check-config and the mapper check: validate(), and that the
+    /// outputs routed to are among the `outputs` opened.
+    pub fn check(&self, outputs: usize) -> Result<(), ConfigError> {
+        self.validate()?;
+        self.check_outputs(outputs)
+    }
+
+    /// Checks that the sliders, passthrough, tee, feedback, startup_sequence
+    /// and active_sensing are only routed to outputs
+    /// there are, `count` of them. Only known once the ports are open.
+    pub fn check_outputs(&self, count: usize) -> Result<(), ConfigError> {
+        let mut problems = vec![];
+        let mut check = |outputs: &Option<Vec<usize>>, name: String| {
+            if let Some(output) = outputs.iter().flatten().find(|output| **output >= count) {
+                problems.push(format!("{}: there is no output {}, {} opened", name, output + 1, count));
+            }
+        };
+        check(&self.passthrough_outputs, "passthrough_outputs".to_string());
+        check(&self.tee_outputs, "tee_outputs".to_string());
+        for (i, param) in self.virtual_params.iter().enumerate() {
+            check(&param.outputs, format!("virtual_params[{}] ({}).outputs", i, param.name));
+        }
+        if let Some(feedback) = &self.feedback {
+            check(&feedback.outputs, "feedback.outputs".to_string());
+        }
+        if let Some(sensing) = &self.active_sensing {
+            check(&sensing.outputs, "active_sensing.outputs".to_string());
+        }
+        for (i, step) in self.startup_sequence.iter().enumerate() {
+            check(&step.outputs, format!("startup_sequence[{}].outputs", i));
+        }
+        let tables = std::iter::once(("sliders".to_string(), &self.sliders))
+            .chain(self.profiles.iter().map(|profile| (format!("profiles.{}.sliders", profile.name), &profile.sliders)));
+        for (path, sliders) in tables {
+            for (i, slider) in sliders.iter().enumerate() {
+                check(&slider.outputs, format!("{}[{}] (sysex_id 0x{:06X})", path, i, slider.sysex_id));
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(ConfigError {
+            path: None,
+            line: None,
+            key: None,
+            message: format!("{} problem(s) with the outputs:\n  {}", problems.len(), problems.join("\n  ")),
+        })
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let root = yaml::parse(source)?;
+        let entries = root.as_map().ok_or_else(|| ConfigError::at(&root, "config", "expected a mapping at the top level"))?;
+        let mut config = Config { sliders: vec![], ..Self::default() };
+        let mut have_sliders = false;
+        // Parsed last, as a profile's channel (and the startup_sequence's)
+        // defaults to the top level one
+        let mut profiles = None;
+        let mut startup_sequence = None;
+        for (key, node) in entries {
+            match key.as_str() {
+                "channel" => config.channel = parse_channel(node, key)?,
+                "rate_limit_ms" => config.rate_limit = parse_duration_ms(node, key)?,
+                "pickup_after_program_change" => config.pickup_after_program_change = parse_bool(node, key)?,
+                "send_repeats" => config.send_repeats = parse_bool(node, key)?,
+                "resend_trigger_cc" => config.resend_trigger_cc = Some(parse_int(node, key, 0, 127)? as u8),
+                "osc_destination" => config.osc_destination = Some(parse_osc_destination(node, key)?),
+                "program_change" => config.program_change = Some(parse_int(node, key, 0, 127)? as u8),
+                "filter" => config.filter = parse_filter(node, key)?,
+                "device" => config.device = parse_device(node, key)?,
+                "upper_lower" => {
+                    config.upper_lower = match node.as_scalar() {
+                        Some("separate") => UpperLower::Separate,
+                        Some("merged") => UpperLower::Merged,
+                        Some("split") => UpperLower::Split,
+                        _ => return Err(ConfigError::at(node, key, "expected separate, merged or split")),
+                    }
+                }
+                "lower_channel" => config.lower_channel = Some(parse_channel(node, key)?),
+                "out_of_range" => {
+                    config.out_of_range = match node.as_scalar() {
+                        Some("warn") => OutOfRange::Warn,
+                        Some("clamp") => OutOfRange::Clamp,
+                        Some("drop") => OutOfRange::Drop,
+                        _ => return Err(ConfigError::at(node, key, "expected warn, clamp or drop")),
+                    }
+                }
+                "passthrough_outputs" => config.passthrough_outputs = Some(parse_outputs(node, key)?),
+                "passthrough_channel" => {
+                    let text = node.as_scalar().ok_or_else(|| ConfigError::at(node, key, "expected a channel or pairs like 1->3, 2->4"))?;
+                    let drop_unmapped = config.passthrough_channels.drop_unmapped;
+                    config.passthrough_channels = ChannelMap::parse(text).map_err(|err| ConfigError::at(node, key, err))?;
+                    config.passthrough_channels.drop_unmapped = drop_unmapped;
+                }
+                "passthrough_unmapped" => {
+                    config.passthrough_channels.drop_unmapped = match node.as_scalar() {
+                        Some("pass") => false,
+                        Some("drop") => true,
+                        _ => return Err(ConfigError::at(node, key, "expected pass or drop")),
+                    }
+                }
+                "note_transforms" => config.note_transforms = parse_note_transforms(node, key)?,
+                "tee" => {
+                    config.tee = match node.as_scalar() {
+                        Some("false") => None,
+                        Some("sysex_first") => Some(Tee::SysExFirst),
+                        Some("cc_first") => Some(Tee::CcFirst),
+                        _ => return Err(ConfigError::at(node, key, "expected sysex_first, cc_first or false")),
+                    }
+                }
+                "tee_outputs" => config.tee_outputs = Some(parse_outputs(node, key)?),
+                "feedback" => config.feedback = Some(parse_feedback(node, key)?),
+                "active_sensing" => config.active_sensing = Some(parse_active_sensing(node, key)?),
+                "arbitration" => config.arbitration = Some(parse_arbitration(node, key)?),
+                "virtual_params" => config.virtual_params = parse_virtual_params(node, key)?,
+                "sliders" => {
+                    have_sliders = true;
+                    config.sliders = parse_sliders(node, key)?;
+                }
+                "profiles" => profiles = Some(node),
+                "startup_sequence" => startup_sequence = Some(node),
+                _ => return Err(ConfigError::at(node, key, "unknown key")),
+            }
+        }
+        if !have_sliders {
+            return Err(ConfigError::at(&root, "sliders", "missing"));
+        }
+        if let Some(node) = startup_sequence {
+            let items = node.as_list().ok_or_else(|| ConfigError::at(node, "startup_sequence", "expected a list of messages and delays"))?;
+            for (i, item) in items.iter().enumerate() {
+                config.startup_sequence.push(parse_startup_step(item, &format!("startup_sequence[{}]", i), config.channel)?);
+            }
+        }
+        if let Some(node) = profiles {
+            let entries = node.as_map().ok_or_else(|| ConfigError::at(node, "profiles", "expected a mapping of profile names"))?;
+            for (name, profile) in entries {
+                let path = format!("profiles.{}", name);
+                if name == Self::DEFAULT_PROFILE || config.profiles.iter().any(|other| &other.name == name) {
+                    return Err(ConfigError::at(profile, &path, format!("there is already a profile named '{}'", name)));
+                }
+                config.profiles.push(parse_profile(profile, name, &path, config.channel)?);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// What is wrong with one slider table, with the `lower` sliders made of it
+/// in split mode, `path` being where it is in the file and `channel` the
+/// channel of the sliders without one of their own.
+fn slider_problems(sliders: &[Slider], lower: &[(usize, Slider)], path: &str, channel: u8, resend_trigger_cc: Option<u8>) -> Vec<String> {
+    let mut problems = vec![];
+    // Who is using what, to name both sides of a conflict. The same CC or
+    // NRPN on different channels is fine.
+    let mut sysex_ids: HashMap<SysExId, String> = HashMap::new();
+    let mut ccs: HashMap<(u8, u8), String> = HashMap::new();
+    let mut nrpns: HashMap<(u8, u16), String> = HashMap::new();
+    let mut rpns: HashMap<(u8, u16), String> = HashMap::new();
+    let mut pitch_bends: HashMap<u8, String> = HashMap::new();
+    let given = sliders.iter().enumerate().map(|(i, slider)| (format!("{}[{}]", path, i), slider));
+    let lower = lower.iter().map(|(i, slider)| (format!("{}[{}] for the Lower tone", path, i), slider));
+    for (name, slider) in given.chain(lower) {
+        let name = format!("{} (sysex_id 0x{:06X})", name, slider.sysex_id);
+        if let Some(other) = sysex_ids.insert(slider.sysex_id, name.clone()) {
+            problems.push(format!("{}: same sysex_id as {}", name, other));
+        }
+        let slider_channel = slider.channel.unwrap_or(channel);
+        let mut uses_cc = |cc: u8, what: &str| {
+            if cc > MAX_CC_ID {
+                problems.push(format!("{}: {} {} is out of 0-{}, those are channel mode messages", name, what, cc, MAX_CC_ID));
+            }
+            if let Some(other) = ccs.insert((slider_channel, cc), name.clone()) {
+                problems.push(format!("{}: {} {} is already used by {}", name, what, cc, other));
+            }
+        };
+        match (slider.output, slider.cc_mode) {
+            (Output::Cc(cc), CcMode::SevenBit) => uses_cc(cc, "cc_id"),
+            (Output::Cc(cc), CcMode::FourteenBit) => {
+                uses_cc(cc, "cc_id");
+                uses_cc(cc + 32, "LSB CC");
+            }
+            (Output::Nrpn(number), _) => {
+                if let Some(other) = nrpns.insert((slider_channel, number), name.clone()) {
+                    problems.push(format!("{}: nrpn {} is already used by {}", name, number, other));
+                }
+            }
+            (Output::Rpn(number), _) => {
+                if let Some(other) = rpns.insert((slider_channel, number), name.clone()) {
+                    problems.push(format!("{}: rpn {} is already used by {}", name, number, other));
+                }
+            }
+            (Output::PitchBend, _) => {
+                if let Some(other) = pitch_bends.insert(slider_channel, name.clone()) {
+                    problems.push(format!("{}: pitch bend on channel {} is already used by {}", name, slider_channel + 1, other));
+                }
+            }
+        }
+        for (range, what, max) in [
+            (&slider.sysex_range, "sysex_range", 127),
+            (&slider.cc_range, "cc_range", slider.cc_mode.max_value()),
+        ] {
+            if range.lo > max || range.hi > max {
+                problems.push(format!("{}: {} [{}, {}] is out of 0-{}", name, what, range.lo, range.hi, max));
+            }
+        }
+        // Every value would scale onto the same point. Backwards is fine,
+        // that reverses the slider
+        let sysex_range = &slider.sysex_range;
+        if sysex_range.lo == sysex_range.hi {
+            problems.push(format!("{}: sysex_range [{}, {}] has no width, every value would go to the same CC value", name, sysex_range.lo, sysex_range.hi));
+        }
+    }
+    if let Some(other) = resend_trigger_cc.and_then(|cc| ccs.get(&(channel, cc))) {
+        problems.push(format!("resend_trigger_cc: used by {} too", other));
+    }
+    // NRPNs and RPNs are sent on these, so they can't be mapped as well on
+    // the same channel
+    for (nrpn_channel, number) in nrpns.keys() {
+        for cc in [6, 38, 98, 99] {
+            if let Some(other) = ccs.remove(&(*nrpn_channel, cc)) {
+                problems.push(format!("{}: CC {} is needed for sending NRPNs (e.g. nrpn {})", other, cc, number));
+            }
+        }
+    }
+    for (rpn_channel, number) in rpns.keys() {
+        for cc in [6, 38, 100, 101] {
+            if let Some(other) = ccs.remove(&(*rpn_channel, cc)) {
+                problems.push(format!("{}: CC {} is needed for sending RPNs (e.g. rpn {})", other, cc, number));
+            }
+        }
+    }
+    problems
+}
+
+/// Only what differs from the defaults is written, besides the ranges.
+/// The sliders, with a comment above each D-50 block they start.
+fn sliders_to_yaml(sliders: &[Slider], device: &Device) -> String {
+    let mut out = String::new();
+    let mut last_block = None;
+    for slider in sliders {
+        let block = device.d50.then(|| parameters::parts(slider.sysex_id)).flatten().map(|(block, _)| block);
+        if let Some(block) = block.filter(|_| block != last_block) {
+            out += &format!("  # {}\n", block);
+        }
+        last_block = block;
+        out += &slider_to_yaml(slider, device);
+    }
+    out
+}
+
+fn slider_to_yaml(slider: &Slider, device: &Device) -> String {
+    let mut out = format!("  - sysex_id: 0x{:06X}", slider.sysex_id);
+    match device.d50.then(|| parameters::name(slider.sysex_id)).flatten() {
+        Some(name) => out += &format!(" # {}\n", name),
+        None => out += "\n",
+    }
+    if let Some(name) = &slider.name {
+        out += &format!("    name: \"{}\"\n", name);
+    }
+    match slider.output {
+        Output::Cc(cc_id) => out += &format!("    cc_id: {}\n", cc_id),
+        Output::Nrpn(number) => out += &format!("    nrpn: {}\n", number),
+        Output::Rpn(number) => out += &format!("    rpn: {}\n", number),
+        Output::PitchBend => out += "    pitch_bend: true\n",
+    }
+    // Pitch bend is always 14-bit
+    if slider.cc_mode == CcMode::FourteenBit && slider.output != Output::PitchBend {
+        out += "    cc_mode: 14bit\n";
+    }
+    out += &format!("    sysex_range: [{}, {}]\n", slider.sysex_range.lo, slider.sysex_range.hi);
+    out += &format!("    cc_range: [{}, {}]\n", slider.cc_range.lo, slider.cc_range.hi);
+    if slider.spread {
+        out += "    stepped: spread\n";
+    } else if slider.stepped {
+        out += "    stepped: true\n";
+    }
+    match &slider.curve {
+        Curve::Linear => (),
+        Curve::Exponential(exponent) => out += &format!("    curve: exponential\n    curve_exponent: {}\n", exponent),
+        Curve::Logarithmic(exponent) => out += &format!("    curve: logarithmic\n    curve_exponent: {}\n", exponent),
+        Curve::Table(points) => {
+            let points: Vec<String> = points.iter().map(|p| format!("{}", (p * 100.0).round())).collect();
+            out += &format!("    curve: table\n    curve_points: [{}]\n", points.join(", "));
+        }
+    }
+    if slider.truncate {
+        out += "    rounding: truncate\n";
+    }
+    if slider.pickup {
+        out += "    pickup: true\n";
+    }
+    if let Some(smoothing) = slider.smoothing {
+        out += &format!("    smoothing_ms: {}\n", smoothing.as_millis());
+    }
+    if slider.hysteresis > 0 {
+        out += &format!("    hysteresis: {}\n", slider.hysteresis);
+    }
+    if let Some(max_step) = slider.max_step {
+        out += &format!("    max_step: {}\n", max_step);
+    }
+    if let Some(per_second) = slider.max_steps_per_second {
+        out += &format!("    max_steps_per_second: {}\n", per_second);
+    }
+    if let Some(osc_address) = &slider.osc_address {
+        out += &format!("    osc_address: \"{}\"\n", osc_address);
+    }
+    if let Some(channel) = slider.channel {
+        out += &format!("    channel: {}\n", channel + 1);
+    }
+    if let Some(outputs) = &slider.outputs {
+        out += &format!("    outputs: {}\n", outputs_to_yaml(outputs));
+    }
+    if let Some(number) = slider.feedback {
+        out += &format!("    feedback: {}\n", number);
+    }
+    if let Some(cc_id) = slider.lower_cc_id {
+        out += &format!("    lower_cc_id: {}\n", cc_id);
+    }
+    match slider.relative {
+        Some(Relative::TwosComplement) => out += "    relative: twos_complement\n",
+        Some(Relative::Offset) => out += "    relative: offset\n",
+        None => (),
+    }
+    if slider.relative_baseline {
+        out += "    relative_start: absolute\n";
+    }
+    if let Some(arbitration) = &slider.arbitration {
+        out += "    arbitration:\n";
+        out += &arbitration_to_yaml(arbitration, "      ");
+    }
+    out
+}
+
+fn outputs_to_yaml(outputs: &[usize]) -> String {
+    let numbers: Vec<String> = outputs.iter().map(|output| (output + 1).to_string()).collect();
+    format!("[{}]", numbers.join(", "))
+}
+
+fn parse_profile(node: &Node, name: &str, path: &str, channel: u8) -> Result<Profile, ConfigError> {
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, path, "expected a mapping"))?;
+    let mut profile = Profile { name: name.to_string(), channel, sliders: vec![], program_change: None };
+    let mut have_sliders = false;
+    for (key, value) in entries {
+        let key_path = format!("{}.{}", path, key);
+        match key.as_str() {
+            "channel" => profile.channel = parse_channel(value, &key_path)?,
+            "program_change" => profile.program_change = Some(parse_int(value, &key_path, 0, 127)? as u8),
+            "sliders" => {
+                have_sliders = true;
+                profile.sliders = parse_sliders(value, &key_path)?;
+            }
+            _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+        }
+    }
+    if !have_sliders {
+        return Err(ConfigError::at(node, &format!("{}.sliders", path), "missing"));
+    }
+    Ok(profile)
+}
+
+fn parse_sliders(node: &Node, path: &str) -> Result<Vec<Slider>, ConfigError> {
+    let items = node.as_list().ok_or_else(|| ConfigError::at(node, path, "expected a list of sliders"))?;
+    items.iter().enumerate().map(|(i, item)| parse_slider(item, &format!("{}[{}]", path, i))).collect()
+}
+
+fn parse_slider(node: &Node, path: &str) -> Result<Slider, ConfigError> {
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, path, "expected a mapping"))?;
+    let mut sysex_id = None;
+    let mut output = None;
+    let mut sysex_range = MidiRange::new(0, 100);
+    let mut cc_range = None;
+    let mut stepped = false;
+    let mut spread = false;
+    let mut inverted = false;
+    let mut pickup = false;
+    let mut smoothing = None;
+    let mut hysteresis = None;
+    let mut name = None;
+    let mut osc_address = None;
+    let mut channel = None;
+    let mut outputs = None;
+    let mut lower_cc_id = None;
+    let mut relative = None;
+    let mut relative_start = None;
+    let mut curve = None;
+    let mut curve_exponent = None;
+    let mut curve_points = None;
+    let mut cc_mode = None;
+    let mut feedback = None;
+    let mut max_step = None;
+    let mut max_steps_per_second = None;
+    let mut arbitration = None;
+    let mut truncate = None;
+    for (key, value) in entries {
+        let key_path = format!("{}.{}", path, key);
+        match key.as_str() {
+            "sysex_id" => sysex_id = Some(parse_sysex_id(value, &key_path)?),
+            "name" => name = Some(value.as_scalar().ok_or_else(|| ConfigError::at(value, &key_path, "expected a name"))?.to_string()),
+            "cc_id" | "nrpn" | "rpn" | "pitch_bend" if output.is_some() => {
+                return Err(ConfigError::at(value, &key_path, "only one of cc_id, nrpn, rpn and pitch_bend can be given"))
+            }
+            "cc_id" => output = Some(Output::Cc(parse_int(value, &key_path, 0, 127)? as u8)),
+            "nrpn" => output = Some(Output::Nrpn(parse_int(value, &key_path, 0, 16383)? as u16)),
+            "rpn" => output = Some(Output::Rpn(parse_int(value, &key_path, 0, 16383)? as u16)),
+            "pitch_bend" => output = parse_bool(value, &key_path)?.then_some(Output::PitchBend),
+            "cc_mode" => cc_mode = Some((parse_cc_mode(value, &key_path)?, value)),
+            "sysex_range" => sysex_range = parse_range(value, &key_path, 127)?,
+            "cc_range" => cc_range = Some((value, key_path)),
+            "stepped" if value.as_scalar() == Some("spread") => spread = true,
+            "stepped" => stepped = parse_bool(value, &key_path).map_err(|_| ConfigError::at(value, &key_path, "expected true, false or spread"))?,
+            "inverted" => inverted = parse_bool(value, &key_path)?,
+            "pickup" => pickup = parse_bool(value, &key_path)?,
+            "smoothing_ms" => smoothing = parse_duration_ms(value, &key_path)?,
+            "hysteresis" => hysteresis = Some((parse_int(value, &key_path, 0, 16383)? as MidiValue, value, key_path)),
+            "osc_address" => osc_address = Some(parse_osc_address(value, &key_path)?),
+            "channel" => channel = Some(parse_channel(value, &key_path)?),
+            "outputs" => outputs = Some(parse_outputs(value, &key_path)?),
+            "feedback" => feedback = Some(parse_int(value, &key_path, 0, 127)? as u8),
+            "max_step" => max_step = Some((parse_int(value, &key_path, 1, 16383)? as MidiValue, value, key_path)),
+            "max_steps_per_second" => max_steps_per_second = Some(parse_int(value, &key_path, 1, 1_000_000)? as u32),
+            "arbitration" => arbitration = Some(parse_arbitration(value, &key_path)?),
+            "rounding" => {
+                truncate = Some(match value.as_scalar() {
+                    Some("nearest") => (false, value, key_path),
+                    Some("truncate") => (true, value, key_path),
+                    _ => return Err(ConfigError::at(value, &key_path, "expected nearest or truncate")),
+                })
+            }
+            "relative" => {
+                relative = Some(match value.as_scalar() {
+                    Some("twos_complement") => (Relative::TwosComplement, value, key_path),
+                    Some("offset") => (Relative::Offset, value, key_path),
+                    _ => return Err(ConfigError::at(value, &key_path, "expected twos_complement or offset")),
+                })
+            }
+            "relative_start" => {
+                relative_start = Some(match value.as_scalar() {
+                    Some("zero") => (false, value, key_path),
+                    Some("absolute") => (true, value, key_path),
+                    _ => return Err(ConfigError::at(value, &key_path, "expected zero or absolute")),
+                })
+            }
+            "lower_cc_id" => lower_cc_id = Some((parse_int(value, &key_path, 0, 127)? as u8, value, key_path)),
+            "curve" => curve = Some((value, key_path)),
+            "curve_exponent" => curve_exponent = Some((parse_float(value, &key_path)?, value, key_path)),
+            "curve_points" => curve_points = Some((parse_curve_points(value, &key_path)?, value, key_path)),
+            _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+        }
+    }
+    let sysex_id = sysex_id.ok_or_else(|| ConfigError::at(node, &format!("{}.sysex_id", path), "missing"))?;
+    let output = output.ok_or_else(|| ConfigError::at(node, &format!("{}.cc_id", path), "missing (or nrpn, rpn or pitch_bend)"))?;
+    // The channel is what tells the pitch bend faders apart
+    if output == Output::PitchBend && channel.is_none() {
+        return Err(ConfigError::at(node, &format!("{}.channel", path), "missing, pitch_bend sliders need a channel of their own"));
+    }
+    let default_cc_mode = if output == Output::PitchBend { CcMode::FourteenBit } else { CcMode::SevenBit };
+    let (cc_mode, cc_mode_node) = cc_mode.unwrap_or((default_cc_mode, node));
+    let cc_range = match cc_range {
+        Some((value, key_path)) => parse_range(value, &key_path, cc_mode.max_value() as i64)?,
+        None => MidiRange::new(0, cc_mode.max_value()),
+    };
+    let cc_range = if inverted { cc_range.inverted() } else { cc_range };
+    if let Some((hysteresis, value, key_path)) = &hysteresis {
+        if *hysteresis as usize > cc_range.width() {
+            return Err(ConfigError::at(value, key_path, "more than the whole cc_range"));
+        }
+    }
+    if let Some((max_step, value, key_path)) = &max_step {
+        if *max_step as usize >= cc_range.width() {
+            return Err(ConfigError::at(value, key_path, "the whole cc_range or more, that limits nothing"));
+        }
+    }
+    if let Some((cc_id, value, key_path)) = &lower_cc_id {
+        match output {
+            Output::Cc(_) if cc_mode == CcMode::FourteenBit && *cc_id > 31 => {
+                return Err(ConfigError::at(value, key_path, "14-bit CCs need a CC number 0-31 (the LSB goes to n + 32)"))
+            }
+            Output::Cc(_) => (),
+            Output::Nrpn(_) | Output::Rpn(_) | Output::PitchBend => return Err(ConfigError::at(value, key_path, "only used by sliders with a cc_id")),
+        }
+    }
+    if let Some((_, value, key_path)) = &relative {
+        if !matches!(output, Output::Cc(_)) || cc_mode != CcMode::SevenBit {
+            return Err(ConfigError::at(value, key_path, "only for 7-bit sliders with a cc_id"));
+        }
+    }
+    if let (Some((_, value, key_path)), None) = (&relative_start, &relative) {
+        return Err(ConfigError::at(value, key_path, "only used by relative sliders"));
+    }
+    let curve = parse_curve(node, path, curve, curve_exponent, curve_points)?;
+    if (stepped || spread) && curve != Curve::Linear {
+        return Err(ConfigError::at(node, &format!("{}.curve", path), "stepped sliders cannot have a curve"));
+    }
+    if let (true, Some((_, value, key_path))) = (stepped || spread, &truncate) {
+        return Err(ConfigError::at(value, key_path, "stepped sliders aren't scaled, so there is nothing to round"));
+    }
+    Slider::new(sysex_id, 0, sysex_range, cc_range)
+        .with_output(output)
+        .with_stepped(stepped)
+        .with_spread(spread)
+        .with_curve(curve)
+        .with_truncate(truncate.is_some_and(|(truncate, _, _)| truncate))
+        .with_pickup(pickup)
+        .with_smoothing(smoothing)
+        .with_hysteresis(hysteresis.map_or(0, |(hysteresis, _, _)| hysteresis))
+        .with_max_step(max_step.map(|(max_step, _, _)| max_step), max_steps_per_second)
+        .with_name(name)
+        .with_osc_address(osc_address)
+        .with_channel(channel)
+        .with_outputs(outputs)
+        .with_feedback(feedback)
+        .with_arbitration(arbitration)
+        .with_lower_cc_id(lower_cc_id.map(|(cc_id, _, _)| cc_id))
+        .with_relative(relative.map(|(relative, _, _)| relative), relative_start.is_some_and(|(baseline, _, _)| baseline))
+        .with_cc_mode(cc_mode)
+        .map_err(|err| ConfigError::at(cc_mode_node, &format!("{}.cc_mode", path), err.to_string()))
+}
+
+type CurveParam<'a, T> = Option<(T, &'a Node, String)>;
+
+fn parse_curve(
+    node: &Node,
+    path: &str,
+    curve: Option<(&Node, String)>,
+    exponent: CurveParam<f64>,
+    points: CurveParam<Vec<f64>>,
+) -> Result<Curve, ConfigError> {
+    let name = match &curve {
+        Some((value, key)) => value.as_scalar().ok_or_else(|| ConfigError::at(value, key, "expected a curve name"))?,
+        None => "linear",
+    };
+    let curve = match name {
+        "linear" => Curve::Linear,
+        "exponential" => Curve::Exponential(exponent.as_ref().map_or(2.0, |(e, _, _)| *e)),
+        "logarithmic" => Curve::Logarithmic(exponent.as_ref().map_or(2.0, |(e, _, _)| *e)),
+        "table" => match &points {
+            Some((points, _, _)) => Curve::Table(points.clone()),
+            None => return Err(ConfigError::at(node, &format!("{}.curve_points", path), "missing, needed by curve: table")),
+        },
+        _ => {
+            let (value, key) = curve.unwrap();
+            return Err(ConfigError::at(value, &key, "expected linear, exponential, logarithmic or table"));
+        }
+    };
+    match (&curve, exponent, points) {
+        (Curve::Linear | Curve::Table(_), Some((_, value, key)), _) => {
+            Err(ConfigError::at(value, &key, "only used by exponential and logarithmic curves"))
+        }
+        (Curve::Linear | Curve::Exponential(_) | Curve::Logarithmic(_), _, Some((_, value, key))) => {
+            Err(ConfigError::at(value, &key, "only used by curve: table"))
+        }
+        _ => Ok(curve),
+    }
+}
+
+/// Curve points are written as percentages, from 0 up to 100.
+fn parse_curve_points(node: &Node, key: &str) -> Result<Vec<f64>, ConfigError> {
+    let items = node.as_list().ok_or_else(|| ConfigError::at(node, key, "expected a list like [0, 10, 40, 100]"))?;
+    let points = items
+        .iter()
+        .map(|item| Ok(parse_int(item, key, 0, 100)? as f64 / 100.0))
+        .collect::<Result<Vec<_>, ConfigError>>()?;
+    if points.len() < 2 || points[0] != 0.0 || points[points.len() - 1] != 1.0 {
+        return Err(ConfigError::at(node, key, "needs at least two points, starting at 0 and ending at 100"));
+    }
+    if points.windows(2).any(|w| w[1] < w[0]) {
+        return Err(ConfigError::at(node, key, "points must never go down"));
+    }
+    Ok(points)
+}
+
+fn parse_float(node: &Node, key: &str) -> Result<f64, ConfigError> {
+    match node.as_scalar().map(str::parse::<f64>) {
+        Some(Ok(value)) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(ConfigError::at(node, key, "expected a positive number")),
+    }
+}
+
+/// A number, or a D-50 parameter name like "Upper Common Chorus Rate".
+/// An address is written 0x00012B, or as its bytes the way the manuals
+/// print them, 00 01 2B or 00-01-2B. The 0x012B of older configs, with the
+/// first byte left out, is the same address.
+fn parse_sysex_id(node: &Node, key: &str) -> Result<SysExId, ConfigError> {
+    let bytes = |text: &str| -> Option<SysExId> {
+        let bytes: Vec<&str> = text.split([' ', '-']).filter(|byte| !byte.is_empty()).collect();
+        match bytes.as_slice() {
+            [_, _, _] if bytes.iter().all(|byte| byte.len() == 2) => {
+                bytes.iter().try_fold(0, |id, byte| Some(id << 8 | u8::from_str_radix(byte, 16).ok()? as SysExId))
+            }
+            _ => None,
+        }
+    };
+    let text = node.as_scalar().unwrap_or_default();
+    let id = match bytes(text) {
+        Some(id) => id,
+        None if !text.is_empty() && !text.starts_with(|c: char| c.is_ascii_digit()) => {
+            return parameters::id(text)
+                .ok_or_else(|| ConfigError::at(node, key, format!("unknown parameter '{}', see pg1000cc --list-parameters", text)));
+        }
+        None => parse_int(node, key, 0, 0xff_ffff)? as SysExId,
+    };
+    if id.to_be_bytes().iter().any(|byte| byte & 0x80 != 0) {
+        return Err(ConfigError::at(node, key, format!("0x{:06X} is not a SysEx address, each of its bytes has to be 00-7F", id)));
+    }
+    Ok(id)
+}
+
+fn parse_osc_destination(node: &Node, key: &str) -> Result<String, ConfigError> {
+    match node.as_scalar() {
+        Some(text) if text.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()) => {
+            Ok(text.to_string())
+        }
+        _ => Err(ConfigError::at(node, key, "expected host:port, e.g. 127.0.0.1:9000")),
+    }
+}
+
+fn parse_osc_address(node: &Node, key: &str) -> Result<String, ConfigError> {
+    match node.as_scalar() {
+        Some(text) if text.starts_with('/') && !text.contains(|c: char| c.is_whitespace() || "#*,?[]{}".contains(c)) => {
+            Ok(text.to_string())
+        }
+        _ => Err(ConfigError::at(node, key, "expected an OSC address like /pg1000/cutoff")),
+    }
+}
+
+fn parse_filter(node: &Node, key: &str) -> Result<Filter, ConfigError> {
+    let items = node.as_list().ok_or_else(|| ConfigError::at(node, key, "expected a list like [clock, activesense]"))?;
+    let names = items
+        .iter()
+        .map(|item| item.as_scalar().ok_or_else(|| ConfigError::at(item, key, "expected a message type")))
+        .collect::<Result<Vec<_>, _>>()?;
+    Filter::from_names(names).map_err(|err| ConfigError::at(node, key, err))
+}
+
+fn parse_cc_mode(node: &Node, key: &str) -> Result<CcMode, ConfigError> {
+    match node.as_scalar() {
+        Some("7bit") => Ok(CcMode::SevenBit),
+        Some("14bit") => Ok(CcMode::FourteenBit),
+        _ => Err(ConfigError::at(node, key, "expected 7bit or 14bit")),
+    }
+}
+
+/// A built-in device by name, or the format of another one.
+fn parse_device(node: &Node, key: &str) -> Result<Device, ConfigError> {
+    if let Some(name) = node.as_scalar() {
+        return Device::builtin(name).map_err(|err| ConfigError::at(node, key, err));
+    }
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, key, "expected a device name or a mapping"))?;
+    let mut name = None;
+    let mut length = None;
+    let mut header = None;
+    let mut address = None;
+    let mut value_offset = None;
+    let mut checksum_from = None;
+    for (entry, value) in entries {
+        let key_path = format!("{}.{}", key, entry);
+        match entry.as_str() {
+            "name" => name = Some(value.as_scalar().ok_or_else(|| ConfigError::at(value, &key_path, "expected a name"))?.to_string()),
+            "length" => length = Some(parse_int(value, &key_path, 4, 64)? as usize),
+            "header" => {
+                let items = value.as_list().ok_or_else(|| ConfigError::at(value, &key_path, "expected a list of bytes like [0xF0, 0x41, any]"))?;
+                let bytes = items
+                    .iter()
+                    .map(|item| match item.as_scalar() {
+                        Some("any") => Ok(None),
+                        _ => Ok(Some(parse_int(item, &key_path, 0, 0xff)? as u8)),
+                    })
+                    .collect::<Result<Vec<_>, ConfigError>>()?;
+                header = Some(bytes);
+            }
+            "address" => match value.as_list() {
+                Some([offset, width]) => {
+                    address = Some((parse_int(offset, &key_path, 0, 63)? as usize, parse_int(width, &key_path, 1, 3)? as usize))
+                }
+                _ => return Err(ConfigError::at(value, &key_path, "expected [offset, width], e.g. [5, 3]")),
+            },
+            "value" => value_offset = Some(parse_int(value, &key_path, 0, 63)? as usize),
+            "checksum_from" => checksum_from = Some(parse_int(value, &key_path, 0, 63)? as usize),
+            _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+        }
+    }
+    let missing = |what: &str| ConfigError::at(node, &format!("{}.{}", key, what), "missing");
+    let (address_offset, address_width) = address.ok_or_else(|| missing("address"))?;
+    let device = Device {
+        name: name.ok_or_else(|| missing("name"))?,
+        length: length.ok_or_else(|| missing("length"))?,
+        header: header.ok_or_else(|| missing("header"))?,
+        address_offset,
+        address_width,
+        value_offset: value_offset.ok_or_else(|| missing("value"))?,
+        checksum_from,
+        d50: false,
+    };
+    device.check().map_err(|err| ConfigError::at(node, key, err))?;
+    Ok(device)
+}
+
+fn parse_feedback(node: &Node, key: &str) -> Result<Feedback, ConfigError> {
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, key, "expected a mapping"))?;
+    let mut feedback = Feedback::default();
+    for (entry, value) in entries {
+        let key_path = format!("{}.{}", key, entry);
+        match entry.as_str() {
+            "channel" => feedback.channel = parse_channel(value, &key_path)?,
+            "message" => {
+                feedback.message = match value.as_scalar() {
+                    Some("note") => FeedbackMessage::Note,
+                    Some("cc") => FeedbackMessage::Cc,
+                    _ => return Err(ConfigError::at(value, &key_path, "expected note or cc")),
+                }
+            }
+            "value" => feedback.value = parse_int(value, &key_path, 1, 127)? as u8,
+            "hold_ms" => feedback.hold = Duration::from_millis(parse_int(value, &key_path, 1, 10_000)? as u64),
+            "outputs" => feedback.outputs = Some(parse_outputs(value, &key_path)?),
+            _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+        }
+    }
+    Ok(feedback)
+}
+
+fn feedback_to_yaml(feedback: &Feedback) -> String {
+    let mut out = format!("feedback:\n  channel: {}\n", feedback.channel + 1);
+    if feedback.message == FeedbackMessage::Cc {
+        out += "  message: cc\n";
+    }
+    out += &format!("  value: {}\n  hold_ms: {}\n", feedback.value, feedback.hold.as_millis());
+    if let Some(outputs) = &feedback.outputs {
+        out += &format!("  outputs: {}\n", outputs_to_yaml(outputs));
+    }
+    out
+}
+
+fn parse_active_sensing(node: &Node, key: &str) -> Result<ActiveSensing, ConfigError> {
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, key, "expected a mapping"))?;
+    let mut sensing = ActiveSensing::default();
+    for (entry, value) in entries {
+        let key_path = format!("{}.{}", key, entry);
+        match entry.as_str() {
+            "outputs" => sensing.outputs = Some(parse_outputs(value, &key_path)?),
+            "interval_ms" => sensing.interval = Duration::from_millis(parse_int(value, &key_path, 50, 300)? as u64),
+            "input_timeout_ms" => sensing.input_timeout = Duration::from_millis(parse_int(value, &key_path, 300, 10_000)? as u64),
+            _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+        }
+    }
+    Ok(sensing)
+}
+
+fn parse_arbitration(node: &Node, key: &str) -> Result<Arbitration, ConfigError> {
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, key, "expected a mapping"))?;
+    let mut arbitration = Arbitration::default();
+    for (entry, value) in entries {
+        let key_path = format!("{}.{}", key, entry);
+        match entry.as_str() {
+            "order" => {
+                let items = value.as_list().ok_or_else(|| ConfigError::at(value, &key_path, "expected a list like [physical, reverse, sync]"))?;
+                arbitration.order.clear();
+                for item in items {
+                    let source = Source::ALL
+                        .into_iter()
+                        .find(|source| item.as_scalar() == Some(source.name()))
+                        .ok_or_else(|| ConfigError::at(item, &key_path, "expected physical, reverse or sync"))?;
+                    if arbitration.order.contains(&source) {
+                        return Err(ConfigError::at(item, &key_path, format!("{} is in the order twice", source)));
+                    }
+                    arbitration.order.push(source);
+                }
+            }
+            "hold_ms" => arbitration.hold = Duration::from_millis(parse_int(value, &key_path, 1, 10_000)? as u64),
+            "resume" => arbitration.resume = parse_bool(value, &key_path)?,
+            _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+        }
+    }
+    Ok(arbitration)
+}
+
+fn arbitration_to_yaml(arbitration: &Arbitration, indent: &str) -> String {
+    let order: Vec<&str> = arbitration.order.iter().map(Source::name).collect();
+    let mut out = format!("{}order: [{}]\n{}hold_ms: {}\n", indent, order.join(", "), indent, arbitration.hold.as_millis());
+    if !arbitration.resume {
+        out += &format!("{}resume: false\n", indent);
+    }
+    out
+}
+
+/// A list of the input each is for, counting from 1 like the outputs, and
+/// the transpose, velocity_curve and note_range of its notes.
+fn parse_note_transforms(node: &Node, key: &str) -> Result<Vec<NoteTransform>, ConfigError> {
+    let items = node.as_list().ok_or_else(|| ConfigError::at(node, key, "expected a list of inputs and what to do with their notes"))?;
+    let mut transforms: Vec<NoteTransform> = vec![];
+    for (i, item) in items.iter().enumerate() {
+        let path = format!("{}[{}]", key, i);
+        let entries = item.as_map().ok_or_else(|| ConfigError::at(item, &path, "expected a mapping"))?;
+        let mut input = None;
+        let mut transform = NoteTransform::new(0);
+        for (entry, value) in entries {
+            let key_path = format!("{}.{}", path, entry);
+            match entry.as_str() {
+                "input" => input = Some((parse_int(value, &key_path, 1, 16)? as usize - 1, value, key_path)),
+                "transpose" => transform.transpose = parse_int(value, &key_path, -127, 127)? as i8,
+                "velocity_curve" => {
+                    let text = value.as_scalar().ok_or_else(|| ConfigError::at(value, &key_path, "expected soft, hard or fixed(1-127)"))?;
+                    transform.velocity_curve = Some(VelocityCurve::parse(text).map_err(|err| ConfigError::at(value, &key_path, err))?);
+                }
+                "note_range" => {
+                    let range = parse_range(value, &key_path, 127)?;
+                    if range.lo > range.hi {
+                        return Err(ConfigError::at(value, &key_path, "expected the lowest note first"));
+                    }
+                    transform.note_range = Some((range.lo as u8, range.hi as u8));
+                }
+                _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+            }
+        }
+        let (input, value, key_path) = input.ok_or_else(|| ConfigError::at(item, &path, "needs the input it is for"))?;
+        if transforms.iter().any(|other| other.input == input) {
+            return Err(ConfigError::at(value, &key_path, format!("input {} already has its note_transforms", input + 1)));
+        }
+        transform.input = input;
+        transforms.push(transform);
+    }
+    Ok(transforms)
+}
+
+fn note_transform_to_yaml(transform: &NoteTransform) -> String {
+    let mut out = format!("  - input: {}\n", transform.input + 1);
+    if transform.transpose != 0 {
+        out += &format!("    transpose: {:+}\n", transform.transpose);
+    }
+    if let Some(curve) = transform.velocity_curve {
+        out += &format!("    velocity_curve: {}\n", curve);
+    }
+    if let Some((lo, hi)) = transform.note_range {
+        out += &format!("    note_range: [{}, {}]\n", lo, hi);
+    }
+    out
+}
+
+/// A list of a name, cc_id and formula each, with a channel, cc_range,
+/// defaults and outputs if they aren't the usual.
+fn parse_virtual_params(node: &Node, key: &str) -> Result<Vec<VirtualParam>, ConfigError> {
+    let items = node.as_list().ok_or_else(|| ConfigError::at(node, key, "expected a list of virtual params"))?;
+    let mut params = vec![];
+    for (i, item) in items.iter().enumerate() {
+        let path = format!("{}[{}]", key, i);
+        let entries = item.as_map().ok_or_else(|| ConfigError::at(item, &path, "expected a mapping"))?;
+        let (mut name, mut cc_id, mut formula) = (None, None, None);
+        let (mut channel, mut cc_range, mut defaults, mut outputs) = (None, MidiRange::new(0, 127), vec![], None);
+        for (entry, value) in entries {
+            let key_path = format!("{}.{}", path, entry);
+            match entry.as_str() {
+                "name" => name = Some(value.as_scalar().ok_or_else(|| ConfigError::at(value, &key_path, "expected a name"))?.to_string()),
+                "cc_id" => cc_id = Some(parse_int(value, &key_path, 0, MAX_CC_ID as i64)? as u8),
+                "channel" => channel = Some(parse_channel(value, &key_path)?),
+                "cc_range" => cc_range = parse_range(value, &key_path, 127)?,
+                "formula" => {
+                    let text = value.as_scalar().ok_or_else(|| ConfigError::at(value, &key_path, "expected a formula like \"0.5 * cutoff + 0.5 * resonance\""))?;
+                    formula = Some(Formula::parse(text).map_err(|err| ConfigError::at(value, &key_path, err))?);
+                }
+                "defaults" => {
+                    let entries = value.as_map().ok_or_else(|| ConfigError::at(value, &key_path, "expected a mapping of names to 0-100"))?;
+                    for (source, default) in entries {
+                        defaults.push((source.clone(), parse_int(default, &format!("{}.{}", key_path, source), 0, 100)? as u8));
+                    }
+                }
+                "outputs" => outputs = Some(parse_outputs(value, &key_path)?),
+                _ => return Err(ConfigError::at(value, &key_path, "unknown key")),
+            }
+        }
+        let missing = |what: &str| ConfigError::at(item, &path, format!("needs a {}", what));
+        params.push(VirtualParam {
+            name: name.ok_or_else(|| missing("name"))?,
+            cc_id: cc_id.ok_or_else(|| missing("cc_id"))?,
+            channel,
+            cc_range,
+            formula: formula.ok_or_else(|| missing("formula"))?,
+            defaults,
+            outputs,
+        });
+    }
+    Ok(params)
+}
+
+fn virtual_param_to_yaml(param: &VirtualParam) -> String {
+    let mut out = format!("  - name: \"{}\"\n    cc_id: {}\n", param.name, param.cc_id);
+    if let Some(channel) = param.channel {
+        out += &format!("    channel: {}\n", channel + 1);
+    }
+    if param.cc_range != MidiRange::new(0, 127) {
+        out += &format!("    cc_range: [{}, {}]\n", param.cc_range.lo, param.cc_range.hi);
+    }
+    out += &format!("    formula: \"{}\"\n", param.formula);
+    if !param.defaults.is_empty() {
+        out += "    defaults:\n";
+    }
+    for (source, default) in &param.defaults {
+        out += &format!("      \"{}\": {}\n", source, default);
+    }
+    if let Some(outputs) = &param.outputs {
+        out += &format!("    outputs: {}\n", outputs_to_yaml(outputs));
+    }
+    out
+}
+
+/// One of sysex: with the bytes in hex, cc: with a value:, program: or
+/// delay_ms:. The CCs and program changes go on `channel` unless they have
+/// a channel: of their own.
+fn parse_startup_step(node: &Node, path: &str, channel: u8) -> Result<StartupStep, ConfigError> {
+    let entries = node.as_map().ok_or_else(|| ConfigError::at(node, path, "expected a mapping"))?;
+    let mut channel = channel;
+    let (mut sysex, mut cc, mut value, mut program, mut delay) = (None, None, None, None, None);
+    let mut step = StartupStep { action: StartupAction::Delay(Duration::ZERO), outputs: None, on_failure: OnFailure::Abort };
+    let mut sends = false;
+    for (entry, node) in entries {
+        let key_path = format!("{}.{}", path, entry);
+        match entry.as_str() {
+            "sysex" => sysex = Some(parse_sysex(node, &key_path)?),
+            "cc" => cc = Some(parse_int(node, &key_path, 0, 127)? as u8),
+            "value" => value = Some(parse_int(node, &key_path, 0, 127)? as u8),
+            "program" => program = Some(parse_int(node, &key_path, 0, 127)? as u8),
+            "channel" => channel = parse_channel(node, &key_path)?,
+            "delay_ms" => delay = Some(Duration::from_millis(parse_int(node, &key_path, 1, 10_000)? as u64)),
+            "outputs" => {
+                step.outputs = Some(parse_outputs(node, &key_path)?);
+                sends = true;
+            }
+            "on_failure" => {
+                step.on_failure = match node.as_scalar() {
+                    Some("abort") => OnFailure::Abort,
+                    Some("continue") => OnFailure::Continue,
+                    _ => return Err(ConfigError::at(node, &key_path, "expected abort or continue")),
+                };
+                sends = true;
+            }
+            _ => return Err(ConfigError::at(node, &key_path, "unknown key")),
+        }
+    }
+    step.action = match (sysex, cc, value, program, delay) {
+        (Some(bytes), None, None, None, None) => StartupAction::Send(bytes),
+        (None, Some(cc), Some(value), None, None) => StartupAction::Send(vec![0xb0 | channel, cc, value]),
+        (None, Some(_), None, None, None) => return Err(ConfigError::at(node, path, "a cc needs a value")),
+        (None, None, None, Some(program), None) => StartupAction::Send(vec![0xc0 | channel, program]),
+        (None, None, None, None, Some(delay)) if !sends => StartupAction::Delay(delay),
+        (None, None, None, None, Some(_)) => return Err(ConfigError::at(node, path, "a delay sends nothing, it has no outputs or on_failure")),
+        _ => return Err(ConfigError::at(node, path, "expected one of sysex, cc with a value, program or delay_ms")),
+    };
+    Ok(step)
+}
+
+/// A whole SysEx message in hex, like "F0 41 00 14 12 ... F7".
+fn parse_sysex(node: &Node, key: &str) -> Result<Vec<u8>, ConfigError> {
+    let text = node.as_scalar().unwrap_or_default();
+    let bytes: Option<Vec<u8>> = text
+        .split_whitespace()
+        .map(|byte| byte.strip_prefix("0x").unwrap_or(byte))
+        .map(|byte| if byte.len() <= 2 { u8::from_str_radix(byte, 16).ok() } else { None })
+        .collect();
+    let bytes = bytes.ok_or_else(|| ConfigError::at(node, key, format!("'{}' is not bytes in hex like F0 41 ... F7", text)))?;
+    match bytes.as_slice() {
+        [0xf0, data @ .., 0xf7] if !data.is_empty() && data.iter().all(|byte| *byte < 0x80) => Ok(bytes),
+        [0xf0, .., 0xf7] => Err(ConfigError::at(node, key, "expected data bytes 00-7F between F0 and F7")),
+        _ => Err(ConfigError::at(node, key, "expected a SysEx message, from F0 to F7")),
+    }
+}
+
+fn startup_step_to_yaml(step: &StartupStep) -> String {
+    let mut out = match &step.action {
+        StartupAction::Delay(delay) => return format!("  - delay_ms: {}\n", delay.as_millis()),
+        StartupAction::Send(bytes) => match bytes.as_slice() {
+            [status, cc, value] if status & 0xf0 == 0xb0 => format!("  - cc: {}\n    value: {}\n    channel: {}\n", cc, value, (status & 0x0f) + 1),
+            [status, program] if status & 0xf0 == 0xc0 => format!("  - program: {}\n    channel: {}\n", program, (status & 0x0f) + 1),
+            _ => {
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                format!("  - sysex: \"{}\"\n", hex.join(" "))
+            }
+        },
+    };
+    if let Some(outputs) = &step.outputs {
+        out += &format!("    outputs: {}\n", outputs_to_yaml(outputs));
+    }
+    if step.on_failure == OnFailure::Continue {
+        out += "    on_failure: continue\n";
+    }
+    out
+}
+
+fn device_to_yaml(device: &Device) -> String {
+    if device.is_builtin() {
+        return format!("device: {}\n", device.name);
+    }
+    let header: Vec<String> = device.header.iter().map(|byte| byte.map_or("any".to_string(), |byte| format!("0x{:02X}", byte))).collect();
+    let mut out = format!("device:\n  name: \"{}\"\n  length: {}\n", device.name, device.length);
+    out += &format!("  header: [{}]\n", header.join(", "));
+    out += &format!("  address: [{}, {}]\n  value: {}\n", device.address_offset, device.address_width, device.value_offset);
+    if let Some(from) = device.checksum_from {
+        out += &format!("  checksum_from: {}\n", from);
+    }
+    out
+}
+
+/// Output numbers count from 1, in the order of the --output options, and
+/// are kept as indexes from 0.
+fn parse_outputs(node: &Node, key: &str) -> Result<Vec<usize>, ConfigError> {
+    let items = node.as_list().ok_or_else(|| ConfigError::at(node, key, "expected a list of outputs like [1, 2]"))?;
+    let mut outputs = vec![];
+    for item in items {
+        let output = parse_int(item, key, 1, Config::MAX_OUTPUTS as i64)? as usize - 1;
+        if !outputs.contains(&output) {
+            outputs.push(output);
+        }
+    }
+    if outputs.is_empty() {
+        return Err(ConfigError::at(node, key, "expected at least one output"));
+    }
+    Ok(outputs)
+}
+
+fn parse_channel(node: &Node, key: &str) -> Result<u8, ConfigError> {
+    Ok(parse_int(node, key, 1, 16)? as u8 - 1)
+}
+
+/// Milliseconds 0-1000, 0 turning the feature off.
+fn parse_duration_ms(node: &Node, key: &str) -> Result<Option<Duration>, ConfigError> {
+    match parse_int(node, key, 0, 1000)? {
+        0 => Ok(None),
+        ms => Ok(Some(Duration::from_millis(ms as u64))),
+    }
+}
+
+fn parse_range(node: &Node, key: &str, max: i64) -> Result<MidiRange, ConfigError> {
+    match node.as_list() {
+        Some([lo, hi]) => Ok(MidiRange::new(
+            parse_int(lo, key, 0, max)? as MidiValue,
+            parse_int(hi, key, 0, max)? as MidiValue,
+        )),
+        _ => Err(ConfigError::at(node, key, "expected a range like [0, 100]")),
+    }
+}
+
+fn parse_bool(node: &Node, key: &str) -> Result<bool, ConfigError> {
+    match node.as_scalar() {
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        _ => Err(ConfigError::at(node, key, "expected true or false")),
+    }
+}
+
+fn parse_int(node: &Node, key: &str, min: i64, max: i64) -> Result<i64, ConfigError> {
+    let text = node.as_scalar().ok_or_else(|| ConfigError::at(node, key, "expected a number"))?;
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => text.parse::<i64>(),
+    };
+    match parsed {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        Ok(value) => Err(ConfigError::at(node, key, format!("{} is out of range {}-{}", value, min, max))),
+        Err(_) => Err(ConfigError::at(node, key, format!("'{}' is not a number", text))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every key there is, each with something other than its default
+    const EVERYTHING: &str = r#"
+channel: 3
+rate_limit_ms: 20
+pickup_after_program_change: false
+resend_trigger_cc: 119
+osc_destination: "127.0.0.1:9000"
+program_change: 10
+filter: [clock, activesense, sysex]
+upper_lower: split
+lower_channel: 4
+out_of_range: clamp
+send_repeats: true
+passthrough_outputs: [2]
+passthrough_channel: "1->3, 2->4"
+passthrough_unmapped: drop
+note_transforms:
+  - input: 2
+    transpose: -24
+    velocity_curve: fixed(90)
+    note_range: [21, 108]
+  - input: 3
+    velocity_curve: soft
+tee: cc_first
+tee_outputs: [1, 2]
+feedback:
+  channel: 10
+  message: cc
+  value: 100
+  hold_ms: 500
+  outputs: [2]
+active_sensing:
+  interval_ms: 200
+  input_timeout_ms: 500
+  outputs: [2]
+arbitration:
+  order: [reverse, physical]
+  hold_ms: 300
+  resume: false
+virtual_params:
+  - name: "speed twice"
+    cc_id: 30
+    channel: 7
+    cc_range: [0, 100]
+    formula: "min([Chorus speed] * 2, 100)"
+    defaults:
+      "Chorus speed": 40
+    outputs: [2]
+  - name: "half"
+    cc_id: 31
+    formula: "[speed twice] / 2"
+startup_sequence:
+  - sysex: "F0 41 10 16 12 7F 00 00 01 00 F7"
+    outputs: [2]
+  - delay_ms: 50
+  - program: 4
+    on_failure: continue
+  - cc: 7
+    value: 100
+    channel: 10
+sliders:
+  - sysex_id: Upper Common Chorus Rate
+    name: "Chorus speed"
+    cc_id: 20
+    sysex_range: [0, 100]
+    cc_range: [10, 110]
+    inverted: true
+    pickup: true
+    smoothing_ms: 50
+    hysteresis: 2
+    max_step: 8
+    max_steps_per_second: 500
+    osc_address: "/chorus/rate"
+    outputs: [1]
+    feedback: 36
+    lower_cc_id: 21
+    arbitration:
+      order: [physical, sync, reverse]
+      hold_ms: 1000
+  - sysex_id: 0x00012C
+    cc_id: 22
+    curve: exponential
+    curve_exponent: 3
+  - sysex_id: 0x000116
+    cc_id: 23
+    curve: logarithmic
+  - sysex_id: 0x000117
+    cc_id: 24
+    curve: table
+    curve_points: [0, 10, 100]
+  - sysex_id: 0x00010A
+    cc_id: 25
+    sysex_range: [0, 6]
+    stepped: true
+  - sysex_id: 0x00010B
+    cc_id: 26
+    sysex_range: [0, 2]
+    stepped: spread
+  - sysex_id: 0x000111
+    cc_id: 27
+    relative: offset
+    relative_start: absolute
+  - sysex_id: 0x000112
+    cc_id: 1
+    cc_mode: 14bit
+    channel: 5
+  - sysex_id: 0x000113
+    nrpn: 300
+    cc_mode: 14bit
+  - sysex_id: 0x000114
+    rpn: 0
+    sysex_range: [0, 24]
+    cc_range: [0, 24]
+    rounding: truncate
+  - sysex_id: 0x000115
+    pitch_bend: true
+    channel: 9
+profiles:
+  "strings":
+    channel: 6
+    program_change: 11
+    sliders:
+      - sysex_id: 0x00012B
+        cc_id: 28
+        relative: twos_complement
+"#;
+
+    // A programmer not built in
+    const CUSTOM_DEVICE: &str = r#"
+device:
+  name: "mks70"
+  length: 10
+  header: [0xF0, 0x41, 0x36, any, 0x24, 0x20, 0x01]
+  address: [7, 1]
+  value: 8
+sliders:
+  - sysex_id: 0x10
+    cc_id: 74
+    sysex_range: [0, 127]
+"#;
+
+    fn round_trip(config: &Config) {
+        let yaml = config.to_yaml();
+        let parsed = match Config::parse(&yaml) {
+            Ok(parsed) => parsed,
+            Err(err) => panic!("{}\n{}", err, yaml),
+        };
+        assert_eq!(&parsed, config, "{}", yaml);
+        assert_eq!(parsed.to_yaml(), yaml);
+    }
+
+    #[test]
+    fn maps_every_control_but_the_lower_tone() {
+        // The Lower tone's addresses are the same controls, for upper_lower
+        let config = Config::default();
+        for (id, name) in parameters::all() {
+            if parameters::is_upper(id) || parameters::other_tone(id).is_none() {
+                assert!(config.sliders.iter().any(|slider| slider.sysex_id() == id), "{} isn't mapped", name);
+            }
+        }
+    }
+
+    #[test]
+    fn sample_is_the_builtin_mapping() {
+        let sample = Config::parse(include_str!("../pg1000cc.sample.yaml")).unwrap();
+        assert_eq!(sample.sliders, Config::default().sliders);
+    }
+
+    #[test]
+    fn writes_the_builtin_mapping_as_it_reads_it() {
+        round_trip(&Config::default());
+    }
+
+    #[test]
+    fn writes_the_legacy_mapping_as_it_reads_it() {
+        let legacy = Config::legacy();
+        legacy.validate().unwrap();
+        round_trip(&legacy);
+    }
+
+    #[test]
+    fn writes_every_option_as_it_reads_it() {
+        for source in [EVERYTHING, CUSTOM_DEVICE, include_str!("../pg1000cc.sample.yaml")] {
+            let config = Config::parse(source).unwrap();
+            config.validate().unwrap();
+            round_trip(&config);
+        }
+    }
+
+    #[test]
+    fn reads_every_option() {
+        let config = Config::parse(EVERYTHING).unwrap();
+        assert_eq!(config.channel, 2);
+        assert_eq!(config.tee, Some(Tee::CcFirst));
+        assert_eq!(config.passthrough_channels.to_string(), "1->3, 2->4");
+        assert!(config.passthrough_channels.drop_unmapped);
+        let keyboard = NoteTransform { input: 1, transpose: -24, velocity_curve: Some(VelocityCurve::Fixed(90)), note_range: Some((21, 108)) };
+        assert_eq!(config.note_transforms, [keyboard, NoteTransform { velocity_curve: Some(VelocityCurve::Soft), ..NoteTransform::new(2) }]);
+        assert_eq!(config.device, Device::pg1000());
+        let chorus = &config.sliders[0];
+        assert_eq!(chorus.sysex_id, 0x00012b);
+        assert_eq!(chorus.cc_range, MidiRange::new(110, 10));
+        assert_eq!(chorus.smoothing, Some(Duration::from_millis(50)));
+        assert_eq!(chorus.feedback, Some(36));
+        assert_eq!((chorus.max_step, chorus.max_steps_per_second), (Some(8), Some(500)));
+        let feedback = Feedback { channel: 9, message: FeedbackMessage::Cc, value: 100, hold: Duration::from_millis(500), outputs: Some(vec![1]) };
+        assert_eq!(config.feedback, Some(feedback));
+        let sysex = vec![0xf0, 0x41, 0x10, 0x16, 0x12, 0x7f, 0x00, 0x00, 0x01, 0x00, 0xf7];
+        let startup = [
+            StartupStep { action: StartupAction::Send(sysex), outputs: Some(vec![1]), on_failure: OnFailure::Abort },
+            StartupStep { action: StartupAction::Delay(Duration::from_millis(50)), outputs: None, on_failure: OnFailure::Abort },
+            // On the top level channel, though it comes after
+            StartupStep { action: StartupAction::Send(vec![0xc2, 4]), outputs: None, on_failure: OnFailure::Continue },
+            StartupStep { action: StartupAction::Send(vec![0xb9, 7, 100]), outputs: None, on_failure: OnFailure::Abort },
+        ];
+        assert_eq!(config.startup_sequence, startup);
+        let sensing = ActiveSensing { outputs: Some(vec![1]), interval: Duration::from_millis(200), input_timeout: Duration::from_millis(500) };
+        assert_eq!(config.active_sensing, Some(sensing));
+        let arbitration = Arbitration { order: vec![Source::Reverse, Source::Physical], hold: Duration::from_millis(300), resume: false };
+        assert_eq!(arbitration.rank(Source::Sync), 2);
+        assert_eq!(config.arbitration, Some(arbitration));
+        let own = Arbitration { order: vec![Source::Physical, Source::Sync, Source::Reverse], hold: Duration::from_secs(1), resume: true };
+        assert_eq!(chorus.arbitration, Some(own));
+        let twice = &config.virtual_params[0];
+        assert_eq!((twice.cc_id, twice.channel, twice.cc_range.clone()), (30, Some(6), MidiRange::new(0, 100)));
+        assert_eq!((twice.defaults.as_slice(), twice.outputs.as_deref()), (&[("Chorus speed".to_string(), 40)][..], Some(&[1][..])));
+        let order: Vec<String> = config.virtual_params_in_order().unwrap().into_iter().map(|param| param.name).collect();
+        assert_eq!(order, ["speed twice", "half"]);
+        assert_eq!(config.sliders[3].curve, Curve::Table(vec![0.0, 0.1, 1.0]));
+        assert!(config.send_repeats);
+        assert!(config.sliders[9].truncate && !chorus.truncate);
+        assert_eq!(config.profiles[0].channel, 5);
+        assert_eq!(Config::parse(CUSTOM_DEVICE).unwrap().device.name, "mks70");
+    }
+
+    #[test]
+    fn needs_a_sysex_range_with_some_width() {
+        let config = |range: &str| Config::parse(&format!("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: {}\n", range)).unwrap();
+        for range in ["[0, 0]", "[64, 64]"] {
+            let err = config(range).validate().unwrap_err();
+            assert!(err.message.contains(&format!("sliders[0] (sysex_id 0x00012B): sysex_range {} has no width", range)), "{}", err);
+        }
+        // Backwards reverses the slider
+        for range in ["[0, 1]", "[100, 10]"] {
+            config(range).validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn needs_somewhere_to_send_the_feedback() {
+        let config = Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    feedback: 36\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.message.contains("sliders[0] (sysex_id 0x00012B): feedback needs a feedback section"), "{}", err);
+        let config = Config::parse("feedback:\n  outputs: [2]\nsliders: []\n").unwrap();
+        assert!(config.check_outputs(1).unwrap_err().message.contains("feedback.outputs: there is no output 2"));
+    }
+
+    #[test]
+    fn checks_what_the_virtual_params_use() {
+        let problems = |params: &str| {
+            let yaml = format!("virtual_params:\n{}sliders:\n  - sysex_id: 0x00012B\n    name: rate\n    cc_id: 85\n", params);
+            Config::parse(&yaml).and_then(|config| config.validate()).unwrap_err().to_string()
+        };
+        for (params, problem) in [
+            ("  - name: a\n    cc_id: 70\n    formula: \"b + 1\"\n  - name: b\n    cc_id: 71\n    formula: \"c\"\n  - name: c\n    cc_id: 72\n    formula: \"a * rate\"\n", "virtual_params: a -> b -> c -> a go round in a cycle"),
+            ("  - name: a\n    cc_id: 70\n    formula: \"a\"\n", "virtual_params: a -> a go round in a cycle"),
+            ("  - name: a\n    cc_id: 70\n    formula: \"rate + depth\"\n", "virtual_params[0] (a): there is no slider or virtual param named 'depth'"),
+            ("  - name: a\n    cc_id: 70\n    formula: \"rate\"\n    defaults:\n      depth: 5\n", "virtual_params[0] (a): defaults has 'depth', which the formula doesn't use"),
+            ("  - name: a\n    cc_id: 85\n    channel: 2\n    formula: \"rate\"\n", "virtual_params[0] (a): CC 85 on channel 2 is used by sliders[0] (sysex_id 0x00012B) too"),
+            ("  - name: rate\n    cc_id: 70\n    formula: \"1\"\n", "virtual_params[0] (rate): a slider is named that too"),
+            ("  - name: a\n    cc_id: 70\n", "virtual_params[0]: needs a formula"),
+            ("  - name: a\n    cc_id: 70\n    formula: \"rate +\"\n", "virtual_params[0].formula: expected a number or a name at character 7"),
+            ("  - name: a\n    cc_id: 120\n    formula: \"rate\"\n", "120 is out of range 0-119"),
+        ] {
+            let err = problems(params);
+            assert!(err.contains(problem), "{}: {}", params, err);
+        }
+    }
+
+    #[test]
+    fn takes_one_note_transform_for_each_input() {
+        let transforms = |items: &str| Config::parse(&format!("note_transforms:\n{}sliders: []\n", items));
+        for (items, problem) in [
+            ("  - transpose: 12\n", "note_transforms[0]: needs the input it is for"),
+            ("  - input: 1\n  - input: 1\n    transpose: 5\n", "note_transforms[1].input: input 1 already has its note_transforms"),
+            ("  - input: 1\n    transpose: 128\n", "128 is out of range -127-127"),
+            ("  - input: 1\n    velocity_curve: fixed(0)\n", "expected soft, hard or fixed(1-127)"),
+            ("  - input: 1\n    note_range: [96, 36]\n", "note_transforms[0].note_range: expected the lowest note first"),
+        ] {
+            let err = transforms(items).unwrap_err();
+            assert!(err.to_string().contains(problem), "{}: {}", items, err);
+        }
+        let config = transforms("  - input: 1\n    transpose: +12\n").unwrap();
+        assert_eq!(config.note_transforms[0].transpose, 12);
+        assert!(config.to_yaml().contains("  - input: 1\n    transpose: +12\n"));
+    }
+
+    #[test]
+    fn rounds_only_scaled_sliders() {
+        let slider = |keys: &str| Config::parse(&format!("sliders:\n  - sysex_id: 0x00010A\n    cc_id: 25\n{}", keys));
+        let err = slider("    sysex_range: [0, 6]\n    stepped: true\n    rounding: truncate\n").unwrap_err();
+        assert!(err.to_string().contains("sliders[0].rounding: stepped sliders aren't scaled"), "{}", err);
+        let err = slider("    rounding: down\n").unwrap_err();
+        assert!(err.to_string().contains("expected nearest or truncate"), "{}", err);
+        assert!(!slider("    rounding: nearest\n").unwrap().sliders[0].truncate);
+    }
+
+    #[test]
+    fn takes_each_source_once_in_the_arbitration_order() {
+        for (order, problem) in [("[physical, gesture]", "expected physical, reverse or sync"), ("[sync, reverse, sync]", "sync is in the order twice")] {
+            let err = Config::parse(&format!("arbitration:\n  order: {}\nsliders: []\n", order)).unwrap_err();
+            assert!(err.to_string().contains(problem), "{}: {}", order, err);
+        }
+        let config = Config::parse("arbitration:\n  order: [sync]\nsliders: []\n").unwrap();
+        // The ones left out come after it, neither holding off the other
+        let arbitration = config.arbitration.unwrap();
+        assert_eq!(arbitration.rank(Source::Sync), 0);
+        assert_eq!(arbitration.rank(Source::Physical), arbitration.rank(Source::Reverse));
+    }
+
+    #[test]
+    fn takes_only_whole_messages_for_the_startup_sequence() {
+        let step = |entry: &str| Config::parse(&format!("startup_sequence:\n  - {}\nsliders: []\n", entry));
+        for (entry, problem) in [
+            ("sysex: \"41 10 F7\"", "expected a SysEx message, from F0 to F7"),
+            ("sysex: \"F0 41 10\"", "expected a SysEx message, from F0 to F7"),
+            ("sysex: \"F0 F7\"", "expected data bytes 00-7F between F0 and F7"),
+            ("sysex: \"F0 41 90 F7\"", "expected data bytes 00-7F between F0 and F7"),
+            ("sysex: \"F0 4G F7\"", "is not bytes in hex"),
+            ("cc: 7", "a cc needs a value"),
+            ("cc: 128\n    value: 1", "128 is out of range 0-127"),
+            ("program: 1\n    cc: 7", "expected one of sysex, cc with a value, program or delay_ms"),
+            ("channel: 2", "expected one of sysex, cc with a value, program or delay_ms"),
+            ("delay_ms: 10\n    on_failure: continue", "a delay sends nothing"),
+            ("program: 1\n    on_failure: retry", "expected abort or continue"),
+        ] {
+            match step(entry) {
+                Ok(config) => panic!("{} was taken: {:?}", entry, config.startup_sequence),
+                Err(err) => assert!(err.to_string().contains(problem), "{}: {}", entry, err),
+            }
+        }
+        let config = step("program: 1\n    outputs: [3]").unwrap();
+        assert!(config.check_outputs(2).unwrap_err().message.contains("startup_sequence[0].outputs: there is no output 3"));
+    }
+}
@@ -0,0 +1,76 @@
+// The commands typed on stdin while forwarding, one per line. Each one
+// becomes an event for the main thread, like the signals do.
+
+use crate::cli;
+use pg1000cc::status::Event;
+
+pub const HELP: &str = "\
+Commands:
+  quit               Close the connections and exit, as does the end of stdin
+  reload             Read the config file again
+  sync               Ask the D-50 for its values and send them as CCs
+  resend             Send the last value of every slider again
+  startup            Send the startup_sequence of the config again
+  profile <name>     Switch to another profile of the config
+  channel <1-16>     Send on another channel, until the next reload or profile switch
+  output add virtual:<name> | port:<index-or-name> [prime]
+                     Send to another output too, with prime starting it out
+                     with the last value of every slider
+  output remove <name>
+                     Close an output, the others keeping their place in the routing
+  mute, unmute       Stop sending the sliders for a while, and start again
+  stats              Print the statistics, stats reset starts them from zero
+  help               Print this";
+
+/// The event for a line typed on stdin, None for an empty line. Anything
+/// unknown gets the help.
+pub fn parse(line: &str) -> Option<Event> {
+    let line = line.trim();
+    let (command, argument) = match line.split_once(char::is_whitespace) {
+        Some((command, argument)) => (command, Some(argument.trim())),
+        None => (line, None),
+    };
+    Some(match (command, argument) {
+        ("", None) => return None,
+        ("quit" | "exit", None) => Event::Quit,
+        ("reload", None) => Event::Reload,
+        ("sync", None) => Event::Sync,
+        ("resend", None) => Event::Resend,
+        ("startup", None) => Event::Startup,
+        ("profile", Some(name)) => Event::Profile(name.to_string()),
+        ("channel", Some(channel)) => match cli::parse_channel(channel) {
+            Ok(channel) => Event::Channel(channel),
+            Err(err) => Event::Message(err.to_string()),
+        },
+        ("output", Some(argument)) => output(argument).unwrap_or_else(|| Event::Message(format!("Unknown command '{}'\n{}", line, HELP))),
+        ("mute", None) => Event::Mute(true),
+        ("unmute", None) => Event::Mute(false),
+        ("stats", None) => Event::Stats,
+        ("stats", Some("reset")) => Event::ResetStats,
+        ("help", None) => Event::Message(HELP.to_string()),
+        _ => Event::Message(format!("Unknown command '{}'\n{}", line, HELP)),
+    })
+}
+
+/// The event for what comes after "output", None if it isn't one.
+fn output(argument: &str) -> Option<Event> {
+    let (action, rest) = argument.split_once(char::is_whitespace)?;
+    let rest = rest.trim();
+    match action {
+        "add" => {
+            let (port, prime) = match rest.rsplit_once(char::is_whitespace) {
+                Some((port, "prime")) => (port.trim(), true),
+                _ => (rest, false),
+            };
+            let (kind, name) = port.split_once(':')?;
+            let virtual_port = match kind {
+                "virtual" => true,
+                "port" => false,
+                _ => return None,
+            };
+            (!name.is_empty()).then(|| Event::AddOutput { virtual_port, name: name.to_string(), prime })
+        }
+        "remove" if !rest.is_empty() => Some(Event::RemoveOutput(rest.to_string())),
+        _ => None,
+    }
+}
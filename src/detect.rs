@@ -0,0 +1,111 @@
+// Finding the input port the PG-1000 (or the D-50 it's chained to) is on,
+// by listening on all inputs at once for a D-50 SysEx message, or one from
+// the other programmer of the config.
+
+use pg1000cc::device::Device;
+use pg1000cc::{data_request, info, Pg1000SysExMessage};
+use midir::{Ignore, MidiInput, MidiInputPort, MidiOutput};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const WAIT: Duration = Duration::from_secs(3);
+
+/// An RQ1 (data request) for the first byte of the patch, which a D-50
+/// answers with a DT1.
+fn rq1_bytes() -> Vec<u8> {
+    data_request(0x000300, 0x000300)
+}
+
+fn is_d50_sysex(message: &[u8]) -> bool {
+    matches!(message, [0xf0, Pg1000SysExMessage::ROLAND_ID, _, Pg1000SysExMessage::D50_MODEL_ID, ..])
+}
+
+/// The part of an ALSA port name before the colon, which the input and
+/// output ports of the same device share.
+fn client_name(port_name: &str) -> &str {
+    port_name.split(':').next().unwrap_or(port_name)
+}
+
+/// The only input, or the first one a message from the `device` (or a D-50)
+/// arrives on within a few seconds. With `probe` a data request is also
+/// sent to the outputs of the same devices, so that a D-50 answers without
+/// anyone touching a slider. Other synths are not asked. The clients and
+/// ports opened for all this are named `listener_name`.
+pub fn detect_input(midi_in: &MidiInput, device: &Device, probe: bool, listener_name: &str) -> Result<Option<MidiInputPort>, Box<dyn Error>> {
+    let ports = midi_in.ports();
+    match ports.len() {
+        0 => {
+            info!("Auto-detect: no input ports");
+            return Ok(None);
+        }
+        1 => {
+            info!("Auto-detect: '{}' is the only input port", midi_in.port_name(&ports[0])?);
+            return Ok(Some(ports[0].clone()));
+        }
+        _ => (),
+    }
+
+    let start = Instant::now();
+    let answers: Arc<Mutex<Vec<Option<Duration>>>> = Arc::new(Mutex::new(vec![None; ports.len()]));
+    let mut names = vec![];
+    let mut connections = vec![];
+    for (i, port) in ports.iter().enumerate() {
+        let name = midi_in.port_name(port)?;
+        let mut listener = MidiInput::new(listener_name)?;
+        listener.ignore(Ignore::None);
+        let port_answers = Arc::clone(&answers);
+        let device = device.clone();
+        match listener.connect(
+            port,
+            listener_name,
+            move |_, message, _| {
+                if device.matches_header(message) || (device.d50 && is_d50_sysex(message)) {
+                    port_answers.lock().unwrap()[i].get_or_insert(start.elapsed());
+                }
+            },
+            (),
+        ) {
+            Ok(connection) => connections.push(connection),
+            Err(err) => info!("Auto-detect: could not listen on '{}': {}", name, err),
+        }
+        names.push(name);
+    }
+
+    if probe && device.d50 {
+        let midi_out = MidiOutput::new(listener_name)?;
+        for port in midi_out.ports() {
+            let name = midi_out.port_name(&port)?;
+            if !names.iter().any(|input| client_name(input) == client_name(&name)) {
+                continue;
+            }
+            let sender = MidiOutput::new(listener_name)?;
+            match sender.connect(&port, listener_name) {
+                Ok(mut connection) => {
+                    info!("Auto-detect: asking for data on '{}'", name);
+                    if let Err(err) = connection.send(&rq1_bytes()) {
+                        info!("Auto-detect: could not send to '{}': {}", name, err);
+                    }
+                }
+                Err(err) => info!("Auto-detect: could not open '{}': {}", name, err),
+            }
+        }
+    } else {
+        info!("Auto-detect: move a slider on the {}...", device);
+    }
+
+    while start.elapsed() < WAIT && answers.lock().unwrap().iter().all(Option::is_none) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    drop(connections);
+
+    let answers = answers.lock().unwrap();
+    for (name, answer) in names.iter().zip(answers.iter()) {
+        match answer {
+            Some(after) => info!("Auto-detect: '{}' got {} SysEx after {} ms", name, device, after.as_millis()),
+            None => info!("Auto-detect: '{}' got nothing", name),
+        }
+    }
+    let first = answers.iter().enumerate().filter_map(|(i, answer)| Some((i, (*answer)?))).min_by_key(|(_, after)| *after);
+    Ok(first.map(|(i, _)| ports[i].clone()))
+}
@@ -0,0 +1,239 @@
+// The SysEx formats of the programmers. The PG-1000 sends Roland DT1
+// messages for the D-50; the PG-800 (JX-8P) and PG-300 (Alpha Juno) go
+// through their synths, which send each change as an IPR (individual tone
+// parameter) message. They all come down to an address and a value at fixed
+// places in a message of fixed length.
+
+use crate::{roland_checksum, Pg1000SysExMessage, SysExError, SysExId};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    /// The whole message, F0 and F7 included.
+    pub length: usize,
+    /// What the message starts with, None for any data byte (e.g. the
+    /// device number or channel).
+    pub header: Vec<Option<u8>>,
+    /// Where the address is, most significant byte first, 1 to 3 bytes.
+    pub address_offset: usize,
+    pub address_width: usize,
+    pub value_offset: usize,
+    /// Where the bytes covered by a Roland checksum start, for messages
+    /// with one. The checksum is the byte before F7.
+    pub checksum_from: Option<usize>,
+    /// Whether the addresses are the D-50's, so that the parameter names
+    /// apply and the D-50 can be asked for its values.
+    pub d50: bool,
+}
+
+impl Device {
+    /// F0 41 <device> 14 12 <address: 3 bytes> <value> <checksum> F7
+    pub fn pg1000() -> Self {
+        use Pg1000SysExMessage as Pg1000;
+        Self {
+            name: "pg1000".to_string(),
+            length: 11,
+            header: vec![Some(0xf0), Some(Pg1000::ROLAND_ID), None, Some(Pg1000::D50_MODEL_ID), Some(Pg1000::DT1_COMMAND)],
+            address_offset: 5,
+            address_width: 3,
+            value_offset: 8,
+            checksum_from: Some(5),
+            d50: true,
+        }
+    }
+
+    /// The JX-8P's IPR: F0 41 36 <channel> 21 20 01 <parameter> <value> F7
+    pub fn pg800() -> Self {
+        Self::ipr("pg800", 0x21)
+    }
+
+    /// The Alpha Juno's (and MKS-50's) IPR, the same with format 23.
+    pub fn pg300() -> Self {
+        Self::ipr("pg300", 0x23)
+    }
+
+    fn ipr(name: &str, format: u8) -> Self {
+        Self {
+            name: name.to_string(),
+            length: 10,
+            header: vec![Some(0xf0), Some(Pg1000SysExMessage::ROLAND_ID), Some(0x36), None, Some(format), Some(0x20), Some(0x01)],
+            address_offset: 7,
+            address_width: 1,
+            value_offset: 8,
+            checksum_from: None,
+            d50: false,
+        }
+    }
+
+    pub fn builtins() -> Vec<Self> {
+        vec![Self::pg1000(), Self::pg800(), Self::pg300()]
+    }
+
+    pub fn builtin(name: &str) -> Result<Self, String> {
+        Self::builtins().into_iter().find(|device| device.name == name.to_lowercase()).ok_or_else(|| {
+            let names: Vec<String> = Self::builtins().into_iter().map(|device| device.name).collect();
+            format!("unknown device '{}', expected one of {}", name, names.join(", "))
+        })
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        Self::builtins().contains(self)
+    }
+
+    /// Whether the fields make sense together, for a format from the config.
+    pub fn check(&self) -> Result<(), String> {
+        let end = self.length - 1;
+        let data_end = if self.checksum_from.is_some() { end - 1 } else { end };
+        if self.header.first() != Some(&Some(0xf0)) {
+            Err("the header has to start with F0".to_string())
+        } else if !(1..=3).contains(&self.address_width) {
+            Err("the address has to be 1 to 3 bytes".to_string())
+        } else if self.header.len() > self.address_offset || self.address_offset + self.address_width > data_end {
+            Err("the address has to be between the header and the end".to_string())
+        } else if self.value_offset >= data_end || (self.address_offset..self.address_offset + self.address_width).contains(&self.value_offset) {
+            Err("the value has to be after the header, apart from the address".to_string())
+        } else if self.header.len() > self.value_offset {
+            Err("the value has to be after the header".to_string())
+        } else if self.checksum_from.is_some_and(|from| from == 0 || from > self.address_offset.min(self.value_offset)) {
+            Err("the checksum has to cover the address and the value".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether the message starts like the device's, whatever its length.
+    pub fn matches_header(&self, bytes: &[u8]) -> bool {
+        let matches = |(expected, byte): (&Option<u8>, &u8)| expected.map_or(*byte < 0x80, |expected| expected == *byte);
+        bytes.len() >= self.header.len() && self.header.iter().zip(bytes).all(matches)
+    }
+
+    pub fn parse(&self, bytes: &[u8]) -> Result<Pg1000SysExMessage, SysExError> {
+        use SysExError::*;
+        let end = self.length - 1;
+        if bytes.len() != self.length {
+            Err(NotPg1000("wrong length"))
+        } else if !self.matches_header(bytes) {
+            Err(NotPg1000("not from the programmer"))
+        } else if bytes[end] != 0xf7 {
+            Err(Corrupted("no end of SysEx"))
+        } else if bytes[1..end].iter().any(|b| b & 0x80 != 0) {
+            Err(Corrupted("status byte inside the message"))
+        } else if self.checksum_from.is_some_and(|from| roland_checksum(&bytes[from..end - 1], &[]) != bytes[end - 1]) {
+            Err(Corrupted("wrong checksum"))
+        } else {
+            let address = &bytes[self.address_offset..self.address_offset + self.address_width];
+            let id = address.iter().fold(0, |id: SysExId, byte| id << 8 | *byte as SysExId);
+            Ok(Pg1000SysExMessage::new(id, bytes[self.value_offset] as u16))
+        }
+    }
+
+    /// The message the programmer would send for the value, with 0 for the
+    /// bytes of the header that can be anything (device number 1, or the
+    /// first channel).
+    pub fn to_bytes(&self, message: &Pg1000SysExMessage) -> Vec<u8> {
+        let end = self.length - 1;
+        let mut bytes = vec![0; self.length];
+        for (byte, expected) in bytes.iter_mut().zip(&self.header) {
+            *byte = expected.unwrap_or(0);
+        }
+        for i in 0..self.address_width {
+            bytes[self.address_offset + i] = (message.id() >> (8 * (self.address_width - 1 - i))) as u8;
+        }
+        bytes[self.value_offset] = message.value() as u8;
+        if let Some(from) = self.checksum_from {
+            bytes[end - 1] = roland_checksum(&bytes[from..end - 1], &[]);
+        }
+        bytes[end] = 0xf7;
+        bytes
+    }
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Self::pg1000()
+    }
+}
+
+/// The model name for the built-in ones, e.g. PG-800.
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name.strip_prefix("pg").filter(|_| self.is_builtin()) {
+            Some(model) => write!(f, "PG-{}", model),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The PG-1000 moving the Upper tone's Chorus Rate to 50
+    const PG1000_CHORUS_RATE: [u8; 11] = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x2b, 0x32, 0x22, 0xf7];
+    // A JX-8P on channel 1 passing on a PG-800 slider, parameter 0x20
+    const PG800_IPR: [u8; 10] = [0xf0, 0x41, 0x36, 0x00, 0x21, 0x20, 0x01, 0x20, 0x55, 0xf7];
+    // An Alpha Juno on channel 3 passing on a PG-300 slider, parameter 0x10
+    const PG300_IPR: [u8; 10] = [0xf0, 0x41, 0x36, 0x02, 0x23, 0x20, 0x01, 0x10, 0x7f, 0xf7];
+
+    fn parsed(device: &Device, bytes: &[u8]) -> Result<(SysExId, u16), SysExError> {
+        device.parse(bytes).map(|message| (message.id(), message.value()))
+    }
+
+    #[test]
+    fn parses_a_pg1000_dt1() {
+        assert_eq!(parsed(&Device::pg1000(), &PG1000_CHORUS_RATE), Ok((0x00012b, 50)));
+        // Any device number
+        let mut other_device = PG1000_CHORUS_RATE;
+        other_device[2] = 0x10;
+        assert_eq!(parsed(&Device::pg1000(), &other_device), Ok((0x00012b, 50)));
+    }
+
+    #[test]
+    fn finds_a_corrupted_checksum() {
+        let mut corrupted = PG1000_CHORUS_RATE;
+        corrupted[9] ^= 0x01;
+        assert_eq!(parsed(&Device::pg1000(), &corrupted), Err(SysExError::Corrupted("wrong checksum")));
+        // A flipped value byte leaves the checksum wrong too
+        let mut corrupted = PG1000_CHORUS_RATE;
+        corrupted[8] = 0x33;
+        assert_eq!(parsed(&Device::pg1000(), &corrupted), Err(SysExError::Corrupted("wrong checksum")));
+    }
+
+    #[test]
+    fn leaves_other_makers_sysex_alone() {
+        // A Yamaha parameter change of the same length
+        let yamaha = [0xf0, 0x43, 0x10, 0x4c, 0x02, 0x01, 0x00, 0x10, 0x00, 0x11, 0xf7];
+        assert_eq!(parsed(&Device::pg1000(), &yamaha), Err(SysExError::NotPg1000("not from the programmer")));
+        // Roland, but another model than the D-50
+        let mut jd800 = PG1000_CHORUS_RATE;
+        jd800[3] = 0x3d;
+        assert_eq!(parsed(&Device::pg1000(), &jd800), Err(SysExError::NotPg1000("not from the programmer")));
+    }
+
+    #[test]
+    fn parses_the_ipr_of_the_pg800_and_pg300() {
+        assert_eq!(parsed(&Device::pg800(), &PG800_IPR), Ok((0x20, 0x55)));
+        assert_eq!(parsed(&Device::pg300(), &PG300_IPR), Ok((0x10, 0x7f)));
+        // The format byte tells them apart
+        assert!(matches!(parsed(&Device::pg800(), &PG300_IPR), Err(SysExError::NotPg1000(_))));
+        assert!(matches!(parsed(&Device::pg300(), &PG800_IPR), Err(SysExError::NotPg1000(_))));
+        assert!(matches!(parsed(&Device::pg1000(), &PG800_IPR), Err(SysExError::NotPg1000(_))));
+    }
+
+    #[test]
+    fn builds_the_messages_it_parses() {
+        for (device, bytes) in [(Device::pg1000(), &PG1000_CHORUS_RATE[..]), (Device::pg800(), &PG800_IPR[..])] {
+            assert_eq!(device.to_bytes(&device.parse(bytes).unwrap()), bytes);
+        }
+        // On the first channel, whatever the parsed message was on
+        assert_eq!(Device::pg300().to_bytes(&Pg1000SysExMessage::new(0x10, 0x7f)), [0xf0, 0x41, 0x36, 0x00, 0x23, 0x20, 0x01, 0x10, 0x7f, 0xf7]);
+    }
+
+    #[test]
+    fn checks_the_builtins() {
+        for device in Device::builtins() {
+            assert_eq!(device.check(), Ok(()), "{}", device);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// Example configs for setups other than the built-in mapping, bundled into
+// the binary so that `pg1000cc examples copy <name>` writes one out to start
+// from. The files are in configs/ in the repository.
+
+/// The name, what it is for and the config file.
+pub const EXAMPLES: &[(&str, &str, &str)] = &[
+    ("default", "The built-in mapping, every control of the panel, documented", include_str!("../pg1000cc.sample.yaml")),
+    ("gm", "The controls with a standard CC (brightness, vibrato, volume...) on it", include_str!("../configs/gm.yaml")),
+    ("nrpn", "The 0-100 sliders as 14-bit NRPNs", include_str!("../configs/nrpn.yaml")),
+    ("upper-lower", "The Upper and Lower tones on channels of their own", include_str!("../configs/upper-lower.yaml")),
+    ("mackie", "Eight sliders as Mackie style 14-bit pitch bend faders", include_str!("../configs/mackie.yaml")),
+];
+
+/// The config file of an example.
+pub fn find(name: &str) -> Result<&'static str, String> {
+    match EXAMPLES.iter().find(|(example, _, _)| *example == name) {
+        Some((_, _, config)) => Ok(config),
+        None => Err(format!("no example '{}', expected one of {}", name, names().join(", "))),
+    }
+}
+
+/// The examples one per line, with what they are for.
+pub fn list() -> String {
+    let lines: Vec<String> = EXAMPLES.iter().map(|(name, about, _)| format!("  {:<12} {}", name, about)).collect();
+    lines.join("\n")
+}
+
+fn names() -> Vec<&'static str> {
+    EXAMPLES.iter().map(|(name, _, _)| *name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pg1000cc::config::Config;
+
+    #[test]
+    fn every_example_passes_the_check() {
+        for (name, _, source) in EXAMPLES {
+            let config = Config::parse(source).unwrap_or_else(|err| panic!("{}: {}", name, err));
+            // With the one output opened when none is given
+            config.check(1).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        }
+    }
+
+    #[test]
+    fn finds_the_examples_by_name() {
+        assert_eq!(find("nrpn"), Ok(include_str!("../configs/nrpn.yaml")));
+        let err = find("mpe").unwrap_err();
+        assert!(err.contains("default, gm, nrpn, upper-lower, mackie"), "{}", err);
+    }
+}
@@ -0,0 +1,244 @@
+// Arithmetic over named values, for the formulas of the virtual params:
+// numbers, names, + - * / with the usual precedence, parentheses and the
+// functions min and max. A name is a word of letters, digits and _, or
+// anything but ] in brackets, e.g. [Upper Common Chorus Rate].
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Name(String),
+    Negate(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Min(Vec<Node>),
+    Max(Vec<Node>),
+}
+
+/// A parsed formula, printed as it was written.
+#[derive(Debug, Clone)]
+pub struct Formula {
+    source: String,
+    root: Node,
+}
+
+/// Formulas are the same when they are written the same.
+impl PartialEq for Formula {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser { chars: source.chars().collect(), at: 0 };
+        let root = parser.sum()?;
+        parser.skip_spaces();
+        if let Some(c) = parser.peek() {
+            return Err(parser.error(&format!("unexpected '{}'", c)));
+        }
+        Ok(Self { source: source.to_string(), root })
+    }
+
+    /// The names it uses, each once, in the order they first come.
+    pub fn names(&self) -> Vec<&str> {
+        fn collect<'a>(node: &'a Node, names: &mut Vec<&'a str>) {
+            match node {
+                Node::Number(_) => (),
+                Node::Name(name) if names.contains(&name.as_str()) => (),
+                Node::Name(name) => names.push(name),
+                Node::Negate(node) => collect(node, names),
+                Node::Binary(_, left, right) => {
+                    collect(left, names);
+                    collect(right, names);
+                }
+                Node::Min(args) | Node::Max(args) => args.iter().for_each(|arg| collect(arg, names)),
+            }
+        }
+        let mut names = vec![];
+        collect(&self.root, &mut names);
+        names
+    }
+
+    /// The value with each name given by `value`. Dividing by 0 gives 0.
+    pub fn eval(&self, value: &impl Fn(&str) -> f64) -> f64 {
+        fn eval(node: &Node, value: &impl Fn(&str) -> f64) -> f64 {
+            match node {
+                Node::Number(number) => *number,
+                Node::Name(name) => value(name),
+                Node::Negate(node) => -eval(node, value),
+                Node::Binary(op, left, right) => {
+                    let (left, right) = (eval(left, value), eval(right, value));
+                    match op {
+                        '+' => left + right,
+                        '-' => left - right,
+                        '*' => left * right,
+                        _ if right == 0.0 => 0.0,
+                        _ => left / right,
+                    }
+                }
+                Node::Min(args) => args.iter().map(|arg| eval(arg, value)).fold(f64::INFINITY, f64::min),
+                Node::Max(args) => args.iter().map(|arg| eval(arg, value)).fold(f64::NEG_INFINITY, f64::max),
+            }
+        }
+        eval(&self.root, value)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn error(&self, problem: &str) -> String {
+        format!("{} at character {}", problem, self.at + 1)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    // The next character, if it is one of `chars`, after any spaces
+    fn take(&mut self, chars: &str) -> Option<char> {
+        self.skip_spaces();
+        let c = self.peek().filter(|c| chars.contains(*c))?;
+        self.at += 1;
+        Some(c)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(op) = self.take("+-") {
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.factor()?;
+        while let Some(op) = self.take("*/") {
+            node = Node::Binary(op, Box::new(node), Box::new(self.factor()?));
+        }
+        Ok(node)
+    }
+
+    fn factor(&mut self) -> Result<Node, String> {
+        if self.take("-").is_some() {
+            return Ok(Node::Negate(Box::new(self.factor()?)));
+        }
+        if self.take("(").is_some() {
+            let node = self.sum()?;
+            return match self.take(")") {
+                Some(_) => Ok(node),
+                None => Err(self.error("expected )")),
+            };
+        }
+        if self.take("[").is_some() {
+            let start = self.at;
+            while self.peek().is_some_and(|c| c != ']') {
+                self.at += 1;
+            }
+            let name: String = self.chars[start..self.at].iter().collect();
+            if self.take("]").is_none() {
+                return Err(self.error("expected ]"));
+            }
+            return match name.trim() {
+                "" => Err(self.error("expected a name in the brackets")),
+                name => Ok(Node::Name(name.to_string())),
+            };
+        }
+        self.skip_spaces();
+        let start = self.at;
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.at += 1;
+                }
+                let text: String = self.chars[start..self.at].iter().collect();
+                text.parse().map(Node::Number).map_err(|_| format!("'{}' is not a number at character {}", text, start + 1))
+            }
+            Some(c) if c.is_alphanumeric() || c == '_' => {
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.at += 1;
+                }
+                let word: String = self.chars[start..self.at].iter().collect();
+                if !matches!(word.as_str(), "min" | "max") || self.take("(").is_none() {
+                    return Ok(Node::Name(word));
+                }
+                let mut args = vec![self.sum()?];
+                while self.take(",").is_some() {
+                    args.push(self.sum()?);
+                }
+                if self.take(")").is_none() {
+                    return Err(self.error("expected , or )"));
+                }
+                Ok(if word == "min" { Node::Min(args) } else { Node::Max(args) })
+            }
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("expected a number or a name")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> f64 {
+        let values = |name: &str| match name {
+            "cutoff" => 80.0,
+            "Upper Common Chorus Rate" => 20.0,
+            _ => 0.0,
+        };
+        Formula::parse(source).unwrap().eval(&values)
+    }
+
+    #[test]
+    fn computes_with_the_usual_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("12 / 3 / 2"), 2.0);
+        assert_eq!(eval("-cutoff + 100"), 20.0);
+        assert_eq!(eval("0.5 * cutoff + 0.5 * [Upper Common Chorus Rate]"), 50.0);
+        assert_eq!(eval("min(cutoff, 50) + max(1, 2, 3)"), 53.0);
+        assert_eq!(eval("cutoff / 0"), 0.0);
+    }
+
+    #[test]
+    fn names_each_name_once() {
+        let formula = Formula::parse("cutoff * 2 + [Lower Cutoff] - min(cutoff, other)").unwrap();
+        assert_eq!(formula.names(), ["cutoff", "Lower Cutoff", "other"]);
+        assert_eq!(formula.to_string(), "cutoff * 2 + [Lower Cutoff] - min(cutoff, other)");
+    }
+
+    #[test]
+    fn says_where_it_goes_wrong() {
+        for (source, problem) in [
+            ("", "expected a number or a name at character 1"),
+            ("1 +", "expected a number or a name at character 4"),
+            ("(1 + 2", "expected ) at character 7"),
+            ("[cutoff", "expected ] at character 8"),
+            ("[ ]", "expected a name in the brackets at character 4"),
+            ("1 2", "unexpected '2' at character 3"),
+            ("1.2.3", "'1.2.3' is not a number at character 1"),
+            ("max(1; 2)", "expected , or ) at character 6"),
+            ("cutoff % 2", "unexpected '%' at character 8"),
+        ] {
+            assert_eq!(Formula::parse(source).unwrap_err(), problem, "{}", source);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Which of the messages passed through from the input are dropped instead,
+// e.g. MIDI clock or other devices' SysEx that would end up in what the DAW
+// records. The translated sliders are never filtered, and program changes
+// still switch profiles when they are dropped.
+
+use midir::Ignore;
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    Notes,
+    PolyPressure,
+    Cc,
+    Program,
+    Pressure,
+    PitchBend,
+    SysEx,
+    Timecode,
+    Common,
+    Clock,
+    Transport,
+    ActiveSense,
+    Reset,
+}
+
+use Kind::*;
+
+// The names used in the config and on the command line, some for several
+// kinds at once
+const NAMES: &[(&str, &[Kind])] = &[
+    ("notes", &[Notes]),
+    ("polypressure", &[PolyPressure]),
+    ("cc", &[Cc]),
+    ("program", &[Program]),
+    ("pressure", &[Pressure]),
+    ("pitchbend", &[PitchBend]),
+    ("sysex", &[SysEx]),
+    ("timecode", &[Timecode]),
+    ("common", &[Common]),
+    ("clock", &[Clock]),
+    ("transport", &[Transport]),
+    ("activesense", &[ActiveSense]),
+    ("reset", &[Reset]),
+    ("realtime", &[Clock, Transport, ActiveSense, Reset]),
+];
+
+impl Kind {
+    /// What a message is, by its first byte. A piece of a SysEx passed
+    /// through in parts is SysEx too.
+    pub fn of(message: &[u8]) -> Option<Self> {
+        Some(match message.first()? {
+            0x00..=0x7f | 0xf0 | 0xf7 => SysEx,
+            0x80..=0x9f => Notes,
+            0xa0..=0xaf => PolyPressure,
+            0xb0..=0xbf => Cc,
+            0xc0..=0xcf => Program,
+            0xd0..=0xdf => Pressure,
+            0xe0..=0xef => PitchBend,
+            0xf1 => Timecode,
+            // Song position and select, tune request
+            0xf2..=0xf6 => Common,
+            0xf8 => Clock,
+            0xfa..=0xfc => Transport,
+            0xfe => ActiveSense,
+            // Undefined, F9 and FD, go with system reset
+            0xf9..=0xff => Reset,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        NAMES.iter().find(|(_, kinds)| kinds == &[*self]).map_or("?", |(name, _)| name)
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    dropped: BTreeSet<Kind>,
+}
+
+impl Filter {
+    /// A comma separated list of names, like "clock,activesense,sysex".
+    pub fn parse(list: &str) -> Result<Self, String> {
+        Self::from_names(list.split(',').map(str::trim).filter(|name| !name.is_empty()))
+    }
+
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut dropped = BTreeSet::new();
+        for name in names {
+            match NAMES.iter().find(|(known, _)| *known == name) {
+                Some((_, kinds)) => dropped.extend(kinds.iter()),
+                None => {
+                    let known: Vec<&str> = NAMES.iter().map(|(name, _)| *name).collect();
+                    return Err(format!("unknown message type '{}', expected some of {}", name, known.join(", ")));
+                }
+            }
+        }
+        Ok(Self { dropped })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty()
+    }
+
+    /// One name per kind, for writing the filter out.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.dropped.iter().map(Kind::name).collect()
+    }
+
+    /// The kind of the message, if it is one to drop.
+    pub fn drops(&self, message: &[u8]) -> Option<Kind> {
+        Kind::of(message).filter(|kind| self.dropped.contains(kind))
+    }
+
+    /// What the MIDI driver can drop already, so that the flood of clock
+    /// and active sensing doesn't even reach the callback. midir puts MIDI
+    /// clock and timecode together. Active sensing still comes through when
+    /// it is `watched`.
+    pub fn driver_ignore(&self, watched: bool) -> Ignore {
+        let time = self.dropped.contains(&Clock) && self.dropped.contains(&Timecode);
+        match (time, self.dropped.contains(&ActiveSense) && !watched) {
+            (true, true) => Ignore::TimeAndActiveSense,
+            (true, false) => Ignore::Time,
+            (false, true) => Ignore::ActiveSense,
+            (false, false) => Ignore::None,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Learn mode: builds the mapping by moving the controls on the PG-1000,
+// instead of looking up their SysEx addresses.
+
+use pg1000cc::config::Config;
+use pg1000cc::{CcMode, Mapper, MidiRange, MidiValue, Slider, SysExId};
+use midir::{MidiInput, MidiInputPort};
+use std::error::Error;
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The control that moved last, with the range of values seen from it.
+#[derive(Debug, Clone, Copy)]
+struct Captured {
+    id: SysExId,
+    lo: MidiValue,
+    hi: MidiValue,
+    count: usize,
+}
+
+/// Runs learn mode on the given input, connecting as `port_name`, until an
+/// empty line is entered, saving the config to `path` after every new
+/// mapping. Gives the input back for the normal forwarding.
+pub fn learn(midi_in: MidiInput, port: &MidiInputPort, port_name: &str, config: &mut Config, path: &Path) -> Result<MidiInput, Box<dyn Error>> {
+    let captured = Arc::new(Mutex::new(None::<Captured>));
+    let callback_captured = Arc::clone(&captured);
+    let device = config.device.clone();
+    let conn = midi_in.connect(
+        port,
+        port_name,
+        move |_, message, _| {
+            // Only complete messages are looked at. With drivers that split
+            // them up some get missed, but a moving slider sends plenty.
+            if let Ok(sysex) = device.parse(message) {
+                let mut captured = callback_captured.lock().unwrap();
+                let (id, value) = (sysex.id(), sysex.value());
+                *captured = match *captured {
+                    Some(c) if c.id == id => Some(Captured { lo: c.lo.min(value), hi: c.hi.max(value), count: c.count + 1, ..c }),
+                    _ => Some(Captured { id, lo: value, hi: value, count: 1 }),
+                };
+            }
+        },
+        (),
+    )?;
+
+    println!("Learn mode, saving to {}", path.display());
+    println!("Move a control on the {} from end to end, then press enter.", config.device);
+    println!("Press enter without moving anything to stop learning.\n");
+    loop {
+        *captured.lock().unwrap() = None;
+        prompt("Waiting for a control to move... ")?;
+        let c = match *captured.lock().unwrap() {
+            Some(c) => c,
+            None => break,
+        };
+        let name = pg1000cc::parameters::name(c.id).filter(|_| config.device.d50).unwrap_or_default();
+        println!("Got 0x{:06X} {}, values {}-{} in {} messages", c.id, name, c.lo, c.hi, c.count);
+        if let Err(err) = learn_one(config, path, c) {
+            eprintln!("{}", err);
+        }
+        println!();
+    }
+    println!("Leaving learn mode");
+    let (midi_in, ()) = conn.close();
+    Ok(midi_in)
+}
+
+fn learn_one(config: &mut Config, path: &Path, c: Captured) -> Result<(), Box<dyn Error>> {
+    let existing = config.sliders.iter().position(|slider| slider.sysex_id() == c.id);
+    if let Some(i) = existing {
+        let question = format!("0x{:06X} is already mapped to {}, overwrite? [y/N] ", c.id, config.sliders[i].output());
+        if !prompt(&question)?.eq_ignore_ascii_case("y") {
+            return Ok(());
+        }
+    }
+
+    let free = free_cc(config, existing);
+    let cc_id = loop {
+        let question = match free {
+            Some(cc) => format!("CC number [{}]: ", cc),
+            None => "CC number (no free ones left): ".to_string(),
+        };
+        match prompt(&question)?.as_str() {
+            "" if free.is_some() => break free.unwrap(),
+            answer => match answer.parse::<u8>() {
+                Ok(cc @ 0..=119) => break cc,
+                _ => println!("Expected a number 0-119"),
+            },
+        }
+    };
+
+    // A control that wasn't moved all the way shows a narrower range, so
+    // this can be corrected
+    let sysex_range = loop {
+        match prompt(&format!("SysEx range [{}-{}]: ", c.lo, c.hi))?.as_str() {
+            "" => break MidiRange::new(c.lo, c.hi),
+            answer => match answer.split_once('-').map(|(lo, hi)| (lo.trim().parse(), hi.trim().parse())) {
+                Some((Ok(lo @ 0..=127), Ok(hi @ 0..=127))) => break MidiRange::new(lo, hi),
+                _ => println!("Expected a range like 0-100"),
+            },
+        }
+    };
+
+    // The controls with only a few values are the switch-like ones
+    let stepped = sysex_range.width() < 50;
+    let slider = Slider::new(c.id, cc_id, sysex_range, MidiRange::new(0, 127)).with_stepped(stepped);
+    let mut candidate = config.clone();
+    match existing {
+        Some(i) => candidate.sliders[i] = slider,
+        None => candidate.sliders.push(slider),
+    }
+    candidate.validate()?;
+    candidate.save(path)?;
+    *config = candidate;
+    println!("Mapped 0x{:06X} to CC {}{}", c.id, cc_id, if stepped { " (stepped)" } else { "" });
+    Ok(())
+}
+
+/// The first of the undefined CCs not used by any slider but `except`.
+fn free_cc(config: &Config, except: Option<usize>) -> Option<u8> {
+    let used: Vec<u8> = config
+        .sliders
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| Some(*i) != except)
+        .flat_map(|(_, slider)| match (slider.cc_id(), slider.cc_mode()) {
+            (Some(cc), CcMode::FourteenBit) => vec![cc, cc + 32],
+            (Some(cc), CcMode::SevenBit) => vec![cc],
+            (None, _) => vec![],
+        })
+        .collect();
+    Mapper::FREE_CCS.iter().chain(Mapper::FREE_LSB_CCS).copied().find(|cc| !used.contains(cc))
+}
+
+fn prompt(question: &str) -> Result<String, Box<dyn Error>> {
+    print!("{}", question);
+    stdout().flush()?;
+    let mut input = String::new();
+    if stdin().read_line(&mut input)? == 0 {
+        return Err("standard input closed".into());
+    }
+    Ok(input.trim().to_string())
+}
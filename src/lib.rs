@@ -0,0 +1,3922 @@
+//! The PG-1000 SysEx to CC translation, without any of the port selection
+//! and other wiring of the pg1000cc program. [`Mapper`] takes the messages
+//! arriving from the PG-1000 and writes the CCs to a [`MidiSink`].
+
+#[macro_use]
+pub mod logging;
+pub mod clock;
+pub mod config;
+pub mod device;
+pub mod expr;
+pub mod filter;
+pub mod osc;
+pub mod parameters;
+pub mod remap;
+pub mod smf;
+pub mod stats;
+pub mod status;
+pub mod transform;
+mod yaml;
+
+use midir::{MidiOutputConnection, SendError};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use config::{ActiveSensing, Arbitration, Config, ConfigError, Feedback, OnFailure, OutOfRange, Source, StartupAction, StartupStep, Tee, VirtualParam};
+use std::sync::mpsc::Sender;
+use std::sync::LazyLock;
+use clock::{Clock, Instant};
+use std::time::Duration;
+
+/// A Roland address, the three 7-bit bytes of it as in 0x00012B for
+/// 00 01 2B. Devices with shorter addresses have the high bytes 0.
+pub type SysExId = u32;
+pub type CcId = u8;
+pub type MidiValue = u16;
+
+// The largest value that fits in a MIDI data byte
+pub const DATA_MAX: MidiValue = 0x7f;
+
+// No bend, halfway up the 14-bit pitch bend range
+pub const PITCH_BEND_CENTER: MidiValue = 0x2000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiRange {
+    lo : MidiValue,
+    hi : MidiValue,
+}
+
+impl MidiRange {
+    pub fn new(lo : MidiValue, hi : MidiValue) -> Self {
+        Self {
+            lo,
+            hi
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.hi.abs_diff(self.lo) as usize
+    }
+
+    /// A range with `hi` below `lo` runs backwards: `lo` still maps onto
+    /// `lo` of the other range, so the mapping is reversed.
+    pub fn is_inverted(&self) -> bool {
+        self.hi < self.lo
+    }
+
+    pub fn inverted(&self) -> Self {
+        Self::new(self.hi, self.lo)
+    }
+
+    /// The value right in the middle, if there is one.
+    pub fn midpoint(&self) -> Option<MidiValue> {
+        self.width().is_multiple_of(2).then(|| (self.lo + self.hi) / 2)
+    }
+
+    pub fn contains(&self, value: MidiValue) -> bool {
+        self.clamp(value) == value
+    }
+
+    /// Brings a value from outside the range, like a bogus data byte, to
+    /// the nearest end of the range.
+    pub fn clamp(&self, value: MidiValue) -> MidiValue {
+        value.clamp(self.lo.min(self.hi), self.lo.max(self.hi))
+    }
+
+    /// How far a value is from `lo` towards `hi`.
+    fn offset_of(&self, value: MidiValue) -> MidiValue {
+        self.clamp(value).abs_diff(self.lo)
+    }
+
+    /// The value `offset` away from `lo` towards `hi`.
+    fn at_offset(&self, offset: MidiValue) -> MidiValue {
+        let offset = offset.min(self.width() as MidiValue);
+        if self.is_inverted() {
+            self.lo - offset
+        } else {
+            self.lo + offset
+        }
+    }
+
+    /// Where a value is in the range, 0.0 at `lo` to 1.0 at `hi`.
+    pub fn absolute_to_relative(&self, value: MidiValue) -> f64 {
+        if self.width() == 0 {
+            return 0.0;
+        }
+        self.offset_of(value) as f64 / self.width() as f64
+    }
+
+    /// The nearest value to a position in the range, never outside it.
+    pub fn relative_to_absolute(&self, relative :f64) -> MidiValue {
+        let relative = relative.clamp(0.0, 1.0);
+        self.at_offset((relative * self.width() as f64).round() as MidiValue)
+    }
+
+    /// The value at a position in the range rounded towards `lo`, as the
+    /// first version of pg1000cc did.
+    pub fn relative_to_absolute_truncated(&self, relative :f64) -> MidiValue {
+        let relative = relative.clamp(0.0, 1.0);
+        self.at_offset((relative * self.width() as f64) as MidiValue)
+    }
+
+    /// Maps each value of this (small) range onto evenly spaced values of the
+    /// other range, as if the other range was split into equal slots, one per
+    /// step: 0-4 onto 0-127 gives 0, 32, 64, 96, 127.
+    pub fn step_in_other_range(&self, value: MidiValue, other_range: &MidiRange) -> MidiValue {
+        let steps = self.width() as f64;
+        if steps == 0.0 {
+            return other_range.lo;
+        }
+        let step = self.offset_of(value) as f64;
+        let slot = (other_range.width() + 1) as f64 / steps;
+        other_range.at_offset((step * slot).round() as MidiValue)
+    }
+
+    /// The inverse of `step_in_other_range`, picking the nearest step.
+    pub fn step_from_other_range(&self, value: MidiValue, other_range: &MidiRange) -> MidiValue {
+        let steps = self.width() as f64;
+        let slot = (other_range.width() + 1) as f64 / steps.max(1.0);
+        let step = (other_range.offset_of(value) as f64 / slot).round().clamp(0.0, steps);
+        self.at_offset(step as MidiValue)
+    }
+
+    /// Like `step_in_other_range`, but with the first and last step on the
+    /// ends of the other range and the others evenly in between, each one
+    /// rounded to the nearest value: 0-4 onto 0-127 gives 0, 32, 64, 95, 127.
+    pub fn spread_in_other_range(&self, value: MidiValue, other_range: &MidiRange) -> MidiValue {
+        let steps = self.width() as u64;
+        if steps == 0 {
+            return other_range.lo;
+        }
+        let step = self.offset_of(value) as u64;
+        let width = other_range.width() as u64;
+        other_range.at_offset(((2 * step * width + steps) / (2 * steps)) as MidiValue)
+    }
+
+    /// The inverse of `spread_in_other_range`, picking the nearest step.
+    pub fn spread_from_other_range(&self, value: MidiValue, other_range: &MidiRange) -> MidiValue {
+        let width = other_range.width() as u64;
+        if width == 0 {
+            return self.lo;
+        }
+        let offset = other_range.offset_of(value) as u64;
+        let steps = self.width() as u64;
+        self.at_offset(((2 * offset * steps + width) / (2 * width)) as MidiValue)
+    }
+}
+
+/// How the position of a slider is shaped before scaling it onto the CC
+/// range. All curves go from 0.0 to 1.0 without ever going back down, so
+/// the ends of the ranges still map onto each other.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// x^exponent: more resolution at the low end for exponents above 1
+    Exponential(f64),
+    /// The mirror image of `Exponential`: more resolution at the high end
+    Logarithmic(f64),
+    /// Output positions (0.0-1.0, never decreasing) at evenly spaced
+    /// input positions, interpolated linearly in between
+    Table(Vec<f64>),
+}
+
+impl Curve {
+    pub fn apply(&self, relative: f64) -> f64 {
+        let x = relative.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential(exponent) => x.powf(*exponent),
+            Curve::Logarithmic(exponent) => 1.0 - (1.0 - x).powf(*exponent),
+            Curve::Table(points) => {
+                let segments = (points.len() - 1) as f64;
+                let i = ((x * segments) as usize).min(points.len() - 2);
+                let t = x * segments - i as f64;
+                points[i] + t * (points[i + 1] - points[i])
+            }
+        }
+    }
+
+    /// The inverse of `apply`, for the reverse direction.
+    pub fn invert(&self, relative: f64) -> f64 {
+        let y = relative.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => y,
+            Curve::Exponential(exponent) => y.powf(1.0 / exponent),
+            Curve::Logarithmic(exponent) => 1.0 - (1.0 - y).powf(1.0 / exponent),
+            Curve::Table(points) => {
+                let segments = (points.len() - 1) as f64;
+                // The first segment reaching y; flat segments give their start
+                let i = points.windows(2).position(|w| y <= w[1]).unwrap_or(points.len() - 2);
+                let rise = points[i + 1] - points[i];
+                let t = if rise > 0.0 { (y - points[i]) / rise } else { 0.0 };
+                (i as f64 + t) / segments
+            }
+        }
+    }
+}
+
+/// Plain 7-bit CCs, or 14-bit values sent as an MSB/LSB pair on CC n
+/// and n + 32 as the MIDI standard defines for CCs 0-31.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CcMode {
+    SevenBit,
+    FourteenBit,
+}
+
+impl CcMode {
+    pub fn max_value(&self) -> MidiValue {
+        match self {
+            CcMode::SevenBit => 127,
+            CcMode::FourteenBit => 16383,
+        }
+    }
+}
+
+/// How a relative slider sends its changes instead of its positions, for
+/// hosts that take CCs as increments, as from an endless encoder. Either way
+/// a change of up to 63 fits in one CC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Relative {
+    /// 1 to 63 up, 127 down to 65 for 1 to 63 down
+    TwosComplement,
+    /// 64 plus the change: 65 and up, 63 and down
+    Offset,
+}
+
+impl Relative {
+    pub const MAX_STEP: i32 = 63;
+
+    /// The CC values for a change, several for one larger than `MAX_STEP`,
+    /// none for no change.
+    pub fn encode(&self, change: i32) -> Vec<MidiValue> {
+        let mut values = vec![];
+        let mut rest = change;
+        while rest != 0 {
+            let step = rest.clamp(-Self::MAX_STEP, Self::MAX_STEP);
+            rest -= step;
+            values.push(match self {
+                Relative::TwosComplement => (step & 0x7f) as MidiValue,
+                Relative::Offset => (64 + step) as MidiValue,
+            });
+        }
+        values
+    }
+}
+
+/// What a slider sends: a plain CC, or an NRPN for when there are not
+/// enough free CCs (or the DAW prefers NRPNs), or pitch bend on the
+/// slider's own channel, the way Mackie style DAW faders get 14 bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Cc(CcId),
+    Nrpn(u16),
+    /// A registered parameter, e.g. 0 for pitch bend sensitivity
+    Rpn(u16),
+    PitchBend,
+}
+
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Output::Cc(cc_id) => write!(f, "CC {}", cc_id),
+            Output::Nrpn(number) => write!(f, "NRPN {}", number),
+            Output::Rpn(number) => write!(f, "RPN {}", number),
+            Output::PitchBend => write!(f, "pitch bend"),
+        }
+    }
+}
+
+// A slider's label as it is written out, for the lines printed per event
+// that would otherwise put a String together for it
+struct Label<'a>(&'a Slider, &'a device::Device);
+
+impl std::fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let Label(slider, device) = self;
+        match (&slider.name, device.d50.then(|| parameters::parts(slider.sysex_id)).flatten()) {
+            (Some(name), _) => write!(f, "{}", name),
+            (None, Some((block, name))) => write!(f, "{} {}", block, name),
+            (None, None) => write!(f, "0x{:06X}", slider.sysex_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slider {
+    sysex_id : SysExId,
+    output : Output,
+    sysex_range : MidiRange,
+    cc_range : MidiRange,
+    cc_mode : CcMode,
+    // For the switch-like controls with only a few values: spread them
+    // evenly over the CC range instead of scaling. With `spread` the first
+    // and last land on the ends of the range, otherwise each value gets an
+    // equal slot of it.
+    stepped : bool,
+    spread : bool,
+    curve : Curve,
+    // Scaled values are rounded towards the low end of cc_range instead of
+    // to the nearest, for projects learned against the first version
+    truncate : bool,
+    // Soft takeover: after the synth has changed its values (e.g. on a
+    // program change), wait for the slider to reach the last sent value
+    // before sending again, to avoid jumps.
+    pickup : bool,
+    // When set, changes are sent as a ramp of CCs over this time instead of
+    // a single jump, for the coarse controls
+    smoothing : Option<Duration>,
+    // Shown in the output instead of the D-50 parameter name
+    name : Option<String>,
+    // Overrides the OSC address made of the parameter name
+    osc_address : Option<String>,
+    // Overrides the mapper's channel, as sent on the wire (0-15)
+    channel : Option<u8>,
+    // The outputs, by index, to send to instead of all of them
+    outputs : Option<Vec<usize>>,
+    // The note or CC of the feedback lit while the slider moves, shared by
+    // the sliders of a group
+    feedback : Option<u8>,
+    // In split mode, the CC of the slider made of this one for the Lower
+    // tone, instead of the same one
+    lower_cc_id : Option<CcId>,
+    // Sends the changes of the value rather than the value, and with
+    // `relative_baseline` the first value as is, so that the host starts
+    // from the same place
+    relative : Option<Relative>,
+    relative_baseline : bool,
+    // Values closer than this to the last one sent are left out, for a
+    // worn slider jittering between neighbouring values. 0 for none
+    hysteresis : MidiValue,
+    // A hard limit on how far apart two values sent in a row can be, and
+    // on how many CC steps a second the value can move, for gear that
+    // bangs on a jump. Nothing gets around it, a resend or a sync included.
+    max_step : Option<MidiValue>,
+    max_steps_per_second : Option<u32>,
+    // Which source wins when several move it, instead of the config's
+    arbitration : Option<Arbitration>,
+}
+
+impl Slider {
+    pub fn new(sysex_id : SysExId, cc_id : CcId, sysex_range: MidiRange, cc_range: MidiRange) -> Self {
+        Self {
+            sysex_id,
+            output: Output::Cc(cc_id),
+            sysex_range,
+            cc_range,
+            cc_mode: CcMode::SevenBit,
+            stepped: false,
+            spread: false,
+            curve: Curve::Linear,
+            truncate: false,
+            pickup: false,
+            smoothing: None,
+            name: None,
+            osc_address: None,
+            channel: None,
+            outputs: None,
+            feedback: None,
+            lower_cc_id: None,
+            relative: None,
+            relative_baseline: false,
+            hysteresis: 0,
+            max_step: None,
+            max_steps_per_second: None,
+            arbitration: None,
+        }
+    }
+
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_osc_address(mut self, osc_address: Option<String>) -> Self {
+        self.osc_address = osc_address;
+        self
+    }
+
+    pub fn with_channel(mut self, channel: Option<u8>) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// The slider's own channel, None for the one of the mapping.
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    pub fn with_outputs(mut self, outputs: Option<Vec<usize>>) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    pub fn with_feedback(mut self, feedback: Option<u8>) -> Self {
+        self.feedback = feedback;
+        self
+    }
+
+    pub fn with_arbitration(mut self, arbitration: Option<Arbitration>) -> Self {
+        self.arbitration = arbitration;
+        self
+    }
+
+    pub fn with_lower_cc_id(mut self, lower_cc_id: Option<CcId>) -> Self {
+        self.lower_cc_id = lower_cc_id;
+        self
+    }
+
+    pub fn with_relative(mut self, relative: Option<Relative>, baseline: bool) -> Self {
+        self.relative = relative;
+        self.relative_baseline = baseline;
+        self
+    }
+
+    pub fn sysex_id(&self) -> SysExId {
+        self.sysex_id
+    }
+
+    pub fn output(&self) -> Output {
+        self.output
+    }
+
+    pub fn cc_mode(&self) -> CcMode {
+        self.cc_mode
+    }
+
+    /// The values from the PG-1000 the CC range is spread over.
+    pub fn sysex_range(&self) -> &MidiRange {
+        &self.sysex_range
+    }
+
+    pub fn with_sysex_range(mut self, sysex_range: MidiRange) -> Self {
+        self.sysex_range = sysex_range;
+        self
+    }
+
+    /// The given name, or the D-50 parameter name with a D-50, or the
+    /// address.
+    pub fn label(&self, device: &device::Device) -> String {
+        Label(self, device).to_string()
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: MidiValue) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn with_max_step(mut self, max_step: Option<MidiValue>, per_second: Option<u32>) -> Self {
+        self.max_step = max_step;
+        self.max_steps_per_second = per_second;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Option<Duration>) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn with_pickup(mut self, pickup: bool) -> Self {
+        self.pickup = pickup;
+        self
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn with_stepped(mut self, stepped: bool) -> Self {
+        self.stepped = stepped;
+        self
+    }
+
+    pub fn with_truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Stepped with the steps spread from end to end of the CC range.
+    pub fn with_spread(mut self, spread: bool) -> Self {
+        self.spread = spread;
+        self.stepped |= spread;
+        self
+    }
+
+    pub fn with_output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn with_cc_mode(mut self, cc_mode: CcMode) -> Result<Self, Box<dyn Error>> {
+        match (self.output, cc_mode) {
+            (Output::Cc(cc_id), CcMode::FourteenBit) if cc_id > 31 => {
+                return Err(format!("14-bit CCs need a CC number 0-31 (the LSB goes to n + 32), got {}", cc_id).into());
+            }
+            (Output::PitchBend, CcMode::SevenBit) => return Err("pitch bend is always 14-bit".into()),
+            _ => (),
+        }
+        self.cc_mode = cc_mode;
+        Ok(self)
+    }
+
+    pub fn cc_id(&self) -> Option<CcId> {
+        match self.output {
+            Output::Cc(cc_id) => Some(cc_id),
+            Output::Nrpn(_) | Output::Rpn(_) | Output::PitchBend => None,
+        }
+    }
+
+    // Pitch bend faders have a center detent: the middle of the slider is
+    // no bend at all, which scaling misses by one
+    fn has_center_detent(&self) -> bool {
+        self.output == Output::PitchBend
+            && !self.stepped
+            && self.curve == Curve::Linear
+            && self.sysex_range.midpoint().is_some()
+            && self.cc_range.contains(PITCH_BEND_CENTER)
+    }
+
+    pub fn sysex_value_as_cc_value(&self, value: MidiValue) -> MidiValue {
+        if self.has_center_detent() && self.sysex_range.midpoint() == Some(value) {
+            return PITCH_BEND_CENTER;
+        }
+        if self.spread {
+            self.sysex_range.spread_in_other_range(value, &self.cc_range)
+        } else if self.stepped {
+            self.sysex_range.step_in_other_range(value, &self.cc_range)
+        } else {
+            let relative = self.curve.apply(self.sysex_range.absolute_to_relative(value));
+            match self.truncate {
+                true => self.cc_range.relative_to_absolute_truncated(relative),
+                false => self.cc_range.relative_to_absolute(relative),
+            }
+        }
+    }
+
+    pub fn cc_value_as_sysex_value(&self, value: MidiValue) -> MidiValue {
+        if self.has_center_detent() && value == PITCH_BEND_CENTER {
+            return self.sysex_range.midpoint().unwrap_or_default();
+        }
+        if self.spread {
+            self.sysex_range.spread_from_other_range(value, &self.cc_range)
+        } else if self.stepped {
+            self.sysex_range.step_from_other_range(value, &self.cc_range)
+        } else {
+            let relative = self.cc_range.absolute_to_relative(value);
+            self.sysex_range.relative_to_absolute(self.curve.invert(relative))
+        }
+    }
+}
+
+/// A slider waiting to reach the value the synth was last sent before it
+/// takes over again.
+#[derive(Debug, Clone, Copy)]
+struct Pickup {
+    target: MidiValue,
+    // Which side of the target the slider was on last time
+    side: Option<Ordering>,
+}
+
+impl Pickup {
+    fn new(target: MidiValue) -> Self {
+        Self { target, side: None }
+    }
+
+    /// Whether the slider has now reached the target or moved across it.
+    fn reached(&mut self, value: MidiValue) -> bool {
+        let side = value.cmp(&self.target);
+        match self.side {
+            _ if side == Ordering::Equal => true,
+            Some(previous) if previous != side => true,
+            _ => {
+                self.side = Some(side);
+                false
+            }
+        }
+    }
+}
+
+/// A smoothed change in progress from one value to another.
+#[derive(Debug, Clone, Copy)]
+struct Ramp {
+    from: MidiValue,
+    to: MidiValue,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Ramp {
+    fn new(from: MidiValue, to: MidiValue, start: Instant, duration: Duration) -> Self {
+        Self { from, to, start, duration }
+    }
+
+    /// The value to be at by now; exactly `to` once the time is up.
+    fn value_at(&self, now: Instant) -> MidiValue {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return self.to;
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        (self.from as f64 + (self.to as f64 - self.from as f64) * progress).round() as MidiValue
+    }
+
+    fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.duration
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ControlMessage {
+    cc: CcId,
+    // Always 0-127
+    value: MidiValue,
+    channel: u8,
+}
+
+impl ControlMessage {
+    /// Values above 127 are clamped to 127: a maxed out CC is a better
+    /// guess than whatever the low 7 bits would happen to be.
+    pub fn new(cc: CcId, value: MidiValue, channel: u8) -> Self {
+        debug_assert!(channel < 16, "channel must be validated before use");
+        debug_assert!(cc as MidiValue <= DATA_MAX, "CC number must be validated before use");
+        Self { cc, value: value.min(DATA_MAX), channel }
+    }
+
+    pub fn cc(&self) -> CcId {
+        self.cc
+    }
+
+    pub fn value(&self) -> MidiValue {
+        self.value
+    }
+
+    /// The channel as sent on the wire, 0-15.
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [status, cc, value] if status & 0xf0 == 0xb0 && (cc | value) & 0x80 == 0 => {
+                Some(Self::new(*cc, *value as MidiValue, status & 0x0f))
+            }
+            _ => None,
+        }
+    }
+
+    /// The channel is 1-16 here, as people count them.
+    pub fn to_json(&self) -> String {
+        format!("{{\"channel\":{},\"cc\":{},\"value\":{}}}", self.channel + 1, self.cc, self.value)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        // Besides the MIDI standard, here's a convenient page describing
+        // the protocol: https://www.songstuff.com/recording/article/midi_message_format/
+        let status: u8 = 0xb0 | self.channel;
+        [status, self.cc, self.value as u8]
+    }
+}
+
+/// A 14-bit pitch bend, LSB first on the wire.
+#[derive(Debug, Clone, Copy)]
+pub struct PitchBendMessage {
+    // Always 0-16383
+    value: MidiValue,
+    channel: u8,
+}
+
+impl PitchBendMessage {
+    pub fn new(value: MidiValue, channel: u8) -> Self {
+        debug_assert!(channel < 16, "channel must be validated before use");
+        Self { value: value.min(0x3fff), channel }
+    }
+
+    pub fn value(&self) -> MidiValue {
+        self.value
+    }
+
+    /// The channel as sent on the wire, 0-15.
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [status, lsb, msb] if status & 0xf0 == 0xe0 && (lsb | msb) & 0x80 == 0 => {
+                Some(Self::new((*msb as MidiValue) << 7 | *lsb as MidiValue, status & 0x0f))
+            }
+            _ => None,
+        }
+    }
+
+    /// The channel is 1-16 here, as people count them.
+    pub fn to_json(&self) -> String {
+        format!("{{\"channel\":{},\"value\":{}}}", self.channel + 1, self.value)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [0xe0 | self.channel, (self.value & 0x7f) as u8, (self.value >> 7) as u8]
+    }
+}
+
+/// An NRPN value: the parameter number is selected with CC 99 (MSB) and
+/// 98 (LSB), then the value is sent with data entry CC 6, plus CC 38 for
+/// the LSB of 14-bit values. An RPN is the same with CC 101 and 100.
+#[derive(Debug, Clone)]
+struct NrpnMessage {
+    number: u16,
+    registered: bool,
+    value: MidiValue,
+    cc_mode: CcMode,
+    channel: u8,
+}
+
+impl NrpnMessage {
+    fn new(number: u16, registered: bool, value: MidiValue, cc_mode: CcMode, channel: u8) -> Self {
+        Self { number, registered, value, cc_mode, channel }
+    }
+
+    /// The parameter select can be left out when the receiver already has
+    /// this NRPN selected.
+    fn to_control_messages(&self, select: bool) -> impl Iterator<Item = ControlMessage> {
+        let (msb, lsb) = if self.registered { (101, 100) } else { (99, 98) };
+        let select = select.then(|| {
+            [ControlMessage::new(msb, self.number >> 7, self.channel), ControlMessage::new(lsb, self.number & 0x7f, self.channel)]
+        });
+        let data = match self.cc_mode {
+            CcMode::SevenBit => [Some(ControlMessage::new(6, self.value, self.channel)), None],
+            CcMode::FourteenBit => {
+                [Some(ControlMessage::new(6, self.value >> 7, self.channel)), Some(ControlMessage::new(38, self.value & 0x7f, self.channel))]
+            }
+        };
+        select.into_iter().flatten().chain(data.into_iter().flatten())
+    }
+
+    /// Selects the RPN null, so that data entry CCs from elsewhere change
+    /// nothing.
+    fn null(channel: u8) -> [ControlMessage; 2] {
+        [ControlMessage::new(101, 127, channel), ControlMessage::new(100, 127, channel)]
+    }
+}
+
+/// The PG-1000's format, made once instead of for every message.
+static PG1000: LazyLock<device::Device> = LazyLock::new(device::Device::pg1000);
+
+#[derive(Debug, Clone)]
+pub struct Pg1000SysExMessage {
+    id: SysExId,
+    // Always 0-127
+    value: MidiValue,
+}
+
+impl Pg1000SysExMessage {
+    pub const ROLAND_ID: u8 = 0x41;
+    pub const D50_MODEL_ID: u8 = 0x14;
+    pub const DT1_COMMAND: u8 = 0x12;
+
+    pub fn new(id: SysExId, value: MidiValue) -> Self {
+        Self { id, value: value.min(DATA_MAX) }
+    }
+
+    pub fn id(&self) -> SysExId {
+        self.id
+    }
+
+    pub fn value(&self) -> MidiValue {
+        self.value
+    }
+
+    /// A Roland DT1 (data set) message for the D-50, as the PG-1000 itself
+    /// would send it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        PG1000.to_bytes(self)
+    }
+
+    pub fn to_json(&self) -> String {
+        format!("{{\"sysex_id\":\"0x{:06X}\",\"value\":{}}}", self.id, self.value)
+    }
+
+    /// Parses a DT1 message as sent by the PG-1000:
+    /// F0 41 <device> 14 12 <address: 3 bytes> <value> <checksum> F7
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SysExError> {
+        PG1000.parse(bytes)
+    }
+}
+
+/// Why a message was not accepted as one from the programmer (the PG-1000,
+/// or the device of the config). Anything else is passed through, but a
+/// corrupted one is dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SysExError {
+    NotPg1000(&'static str),
+    Corrupted(&'static str),
+}
+
+impl std::fmt::Display for SysExError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SysExError::NotPg1000(reason) => write!(f, "not a programmer message: {}", reason),
+            SysExError::Corrupted(reason) => write!(f, "corrupted programmer message: {}", reason),
+        }
+    }
+}
+
+impl Error for SysExError {}
+
+/// Roland checksum: address and data bytes plus the checksum sum to 0 (mod 128).
+pub fn roland_checksum(address: &[u8], data: &[u8]) -> u8 {
+    let sum = address.iter().chain(data).map(|&b| b as u32).sum::<u32>();
+    ((128 - sum % 128) % 128) as u8
+}
+
+// Roland addresses are three 7-bit bytes, so that 00 00 7F is followed by
+// 00 01 00. The SysEx ids have one byte of the address in each of theirs.
+fn address_offset(address: [u8; 3]) -> u32 {
+    (address[0] as u32) << 14 | (address[1] as u32) << 7 | address[2] as u32
+}
+
+fn offset_address(offset: u32) -> [u8; 3] {
+    [(offset >> 14) as u8 & 0x7f, (offset >> 7) as u8 & 0x7f, offset as u8 & 0x7f]
+}
+
+fn address_of(id: SysExId) -> [u8; 3] {
+    [(id >> 16) as u8, (id >> 8) as u8, id as u8]
+}
+
+fn id_of(address: [u8; 3]) -> SysExId {
+    (address[0] as SysExId) << 16 | (address[1] as SysExId) << 8 | address[2] as SysExId
+}
+
+/// An RQ1 (data request) for the values from `first` to `last`, which a
+/// D-50 answers with one or more DT1 messages.
+pub fn data_request(first: SysExId, last: SysExId) -> Vec<u8> {
+    let address = address_of(first);
+    let size = offset_address(address_offset(address_of(last)) - address_offset(address) + 1);
+    let mut ret = vec![0xf0, Pg1000SysExMessage::ROLAND_ID, 0x00, Pg1000SysExMessage::D50_MODEL_ID, 0x11];
+    ret.extend_from_slice(&address);
+    ret.extend_from_slice(&size);
+    ret.push(roland_checksum(&address, &size));
+    ret.push(0xf7);
+    ret
+}
+
+/// A DT1 message with any number of values, as the D-50 sends them in
+/// answer to a data request:
+/// F0 41 <device> 14 12 <address: 3 bytes> <values> <checksum> F7
+#[derive(Debug, Clone)]
+pub struct DataSet {
+    address: [u8; 3],
+    data: Vec<u8>,
+}
+
+impl DataSet {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SysExError> {
+        use SysExError::*;
+        let (body, end) = match bytes {
+            [0xf0, Pg1000SysExMessage::ROLAND_ID, device, Pg1000SysExMessage::D50_MODEL_ID, Pg1000SysExMessage::DT1_COMMAND, body @ .., end]
+                if *device <= 0x0f =>
+            {
+                (body, *end)
+            }
+            _ => return Err(NotPg1000("not a D-50 DT1 message")),
+        };
+        if end != 0xf7 {
+            Err(Corrupted("no end of SysEx"))
+        } else if body.len() < 5 {
+            Err(Corrupted("too short"))
+        } else if body.iter().any(|b| b & 0x80 != 0) {
+            Err(Corrupted("status byte inside the message"))
+        } else {
+            let (address, rest) = body.split_at(3);
+            let (data, checksum) = rest.split_at(rest.len() - 1);
+            if roland_checksum(address, data) != checksum[0] {
+                return Err(Corrupted("wrong checksum"));
+            }
+            Ok(Self { address: [address[0], address[1], address[2]], data: data.to_vec() })
+        }
+    }
+
+    /// The values with the SysEx ids they are for.
+    pub fn values(&self) -> impl Iterator<Item = (SysExId, MidiValue)> + '_ {
+        let start = address_offset(self.address);
+        self.data.iter().enumerate().map(move |(i, &value)| (id_of(offset_address(start + i as u32)), value as MidiValue))
+    }
+}
+
+/// Where the mapper sends its output. A trait rather than the midir
+/// connection itself, so that something else can stand in for the port.
+pub trait MidiSink: Send {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError>;
+}
+
+// Not in the browser, where the connection isn't Send and stays with the
+// page instead
+#[cfg(not(target_arch = "wasm32"))]
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        MidiOutputConnection::send(self, message)
+    }
+}
+
+/// What has gone to one output port.
+#[derive(Debug, Clone, Default)]
+pub struct OutputStats {
+    pub sent: u64,
+    pub errors: u64,
+}
+
+struct Destination {
+    name: String,
+    // None once removed, the slot staying so that the outputs after it
+    // keep their index in the routing
+    sink: Option<Box<dyn MidiSink>>,
+    stats: OutputStats,
+    consecutive_errors: u32,
+}
+
+/// What has come from one input port.
+#[derive(Debug, Clone, Default)]
+pub struct InputStats {
+    pub messages: u64,
+    pub translated: u64,
+    pub passed_through: u64,
+    pub filtered: u64,
+}
+
+struct Input {
+    name: String,
+    stats: InputStats,
+    // SysEx messages split over several callbacks are collected here
+    sysex_buffer: Vec<u8>,
+    in_sysex: bool,
+    sysex_overflowed: bool,
+    // When it last sent Active Sensing, if it ever did, and whether it
+    // has stopped since
+    last_sensing: Option<Instant>,
+    sensing_lost: bool,
+    notes: transform::SoundingNotes,
+    // The first timestamp midir gave since it was connected, with when that
+    // was by the clock. Its timestamps count from a start of their own.
+    first_stamp: Option<(u64, Instant)>,
+}
+
+impl Input {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            stats: InputStats::default(),
+            sysex_buffer: Vec::with_capacity(Mapper::MAX_SYSEX_BUFFER),
+            in_sysex: false,
+            sysex_overflowed: false,
+            last_sensing: None,
+            sensing_lost: false,
+            notes: transform::SoundingNotes::default(),
+            first_stamp: None,
+        }
+    }
+
+    /// When a message with midir's timestamp `stamp`, in microseconds,
+    /// came in, by the clock that says it is `now`: as long after the first
+    /// one as the timestamps say. Never later than now, which the two
+    /// clocks drifting apart could make it.
+    fn arrived(&mut self, stamp: u64, now: Instant) -> Instant {
+        let (first, at) = match self.first_stamp {
+            // Timestamps going back to before the first one started over
+            Some((first, at)) if first <= stamp => (first, at),
+            _ => *self.first_stamp.insert((stamp, now)),
+        };
+        let arrived = at + Duration::from_micros(stamp - first);
+        if arrived > now {
+            now
+        } else {
+            arrived
+        }
+    }
+}
+
+// What a data request asked the D-50 for, until all of it has arrived
+struct SyncRequest {
+    sent_at: Instant,
+    first: SysExId,
+    last: SysExId,
+    // The sliders asked for that are still mapped
+    asked: HashSet<SysExId>,
+    missing: HashSet<SysExId>,
+}
+
+// The outputs something is routed to, by index, as a bit for each so that
+// the routing can be copied around without allocating: all of them unless
+// the config says otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Route(u16);
+
+const _: () = assert!(Config::MAX_OUTPUTS <= u16::BITS as usize, "a Route has a bit per output");
+
+impl Route {
+    const ALL: Self = Self(u16::MAX);
+
+    fn of(outputs: &Option<Vec<usize>>) -> Self {
+        match outputs {
+            Some(outputs) => Self(outputs.iter().fold(0, |bits, output| bits | 1 << output)),
+            None => Self::ALL,
+        }
+    }
+
+    fn contains(&self, output: usize) -> bool {
+        self.0 & 1 << output != 0
+    }
+
+    fn without(&self, other: Route) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+// The sliders by channel and CC, as sliders can have channels of their own
+type CcIndex = HashMap<(u8, CcId), SysExId>;
+
+pub struct Mapper {
+    sliders: HashMap<SysExId, Slider>,
+    ccs: CcIndex,
+    // In merged mode the other tone's addresses, with the slider they drive
+    tone_aliases: HashMap<SysExId, SysExId>,
+    channel: u8,
+    // Each output fails on its own, so that a stalled one doesn't keep the
+    // others from getting anything
+    outputs: Vec<Destination>,
+    passthrough_outputs: Route,
+    passthrough_channels: remap::ChannelMap,
+    note_transforms: Vec<transform::NoteTransform>,
+    tee: Option<Tee>,
+    tee_outputs: Route,
+    // The feedback numbers lit, with when a slider last lit each. Being
+    // for the controller, not the DAW, it isn't recorded nor counted with
+    // the CCs sent.
+    feedback: Option<Feedback>,
+    lit: HashMap<u8, Instant>,
+    // The startup_sequence, and while it is being sent the next entry with
+    // when it is due. It goes out as it is, muted or not, and leaves the
+    // last values alone.
+    startup_sequence: Vec<StartupStep>,
+    startup: Option<(usize, Instant)>,
+    // Active Sensing of our own, and when it is due next
+    active_sensing: Option<ActiveSensing>,
+    next_sensing: Option<Instant>,
+    // So that our own messages echoed back by the DAW or the D-50 are not
+    // converted a second time: when each slider last sent to the DAW, as
+    // whatever comes back for it soon after is its own (the echoes of a
+    // quick move arrive behind it), and the last SysEx values sent to the
+    // D-50.
+    echoes: HashMap<SysExId, Instant>,
+    sent_sysex: HashMap<SysExId, MidiValue>,
+    sent_lsbs: HashMap<(u8, CcId), MidiValue>,
+    // The virtual params, each after the ones its formula uses, the sliders
+    // their formulas name, by label, and the value each sent last
+    virtual_params: Vec<VirtualParam>,
+    virtual_sources: HashMap<String, SysExId>,
+    virtual_values: HashMap<String, MidiValue>,
+    // The DAW's feedback in pieces: the MSB of each 14-bit CC, and the
+    // parameter selected for data entry on each channel with the MSB of
+    // its value
+    feedback_msbs: HashMap<(u8, CcId), MidiValue>,
+    feedback_parameters: HashMap<u8, (Output, Option<MidiValue>)>,
+    // The NRPN or RPN last selected on each channel, and the outputs it was
+    // sent to
+    selected_parameters: HashMap<u8, (Output, Route)>,
+    // The channels with an RPN still selected, with when its last value
+    // went out and where to. It stays selected while the slider moves, and
+    // the RPN null follows once it rests.
+    rpn_nulls: HashMap<u8, (Instant, Route)>,
+    // The last value sent for each slider, to skip sending it again
+    last_values: HashMap<SysExId, MidiValue>,
+    // What the changes of the relative sliders are from. Unlike last_values
+    // this is never forgotten to make a value go out again.
+    relative_bases: HashMap<SysExId, MidiValue>,
+    // The last value each slider with a max_step (or max_steps_per_second)
+    // sent and when, never forgotten either, and the values the interlock
+    // is still stepping towards. Each slider says once that it engaged.
+    steps: HashMap<SysExId, (MidiValue, Instant)>,
+    interlocked: HashMap<SysExId, MidiValue>,
+    interlock_noticed: HashSet<SysExId>,
+    send_repeats: bool,
+    // With a rate limit, values coming too soon after the previous one wait
+    // here, only the latest one of each slider
+    rate_limit: Option<Duration>,
+    last_sent_at: HashMap<SysExId, Instant>,
+    pending: HashMap<SysExId, MidiValue>,
+    pickups: HashMap<SysExId, Pickup>,
+    pickup_after_program_change: bool,
+    ramps: HashMap<SysExId, Ramp>,
+    // The source each slider last took a value from and when, and the
+    // sources held off that stay so until they have gone quiet, with when
+    // they last tried
+    arbitration: Option<Arbitration>,
+    owners: HashMap<SysExId, (Source, Instant)>,
+    held_off: HashMap<(SysExId, Source), Instant>,
+    // Slider updates for the live status display, if there is one, and
+    // output failures go to the main thread through here
+    events: Option<Sender<status::Event>>,
+    live_status: bool,
+    osc: Option<osc::OscSender>,
+    // Everything sent, with the time it was sent rather than when the input
+    // arrived, as ramps and rate limited values are sent from tick()
+    recording: Option<smf::Recording>,
+    // When the message being mapped came in, going by its timestamp
+    arrived: Option<Instant>,
+    sync: Option<SyncRequest>,
+    resend_trigger: Option<CcId>,
+    // What values outside the sysex_range do, and when each slider last
+    // said so, with how many more there have been since
+    out_of_range: OutOfRange,
+    range_warnings: HashMap<SysExId, (Instant, u64)>,
+    // What is dropped instead of passed through, and how many of each
+    filter: filter::Filter,
+    // What the programmer's messages look like
+    device: device::Device,
+    // The whole config, for switching profiles, and the name of the one
+    // in use
+    config: Config,
+    profile: String,
+    sysex_values: HashMap<SysExId, MidiValue>,
+    sysex_events: u64,
+    cc_events: u64,
+    stats: stats::Stats,
+    // While muted the sliders send nothing, but everything is still counted
+    muted: bool,
+    // What every timer goes by
+    clock: Box<dyn Clock>,
+    // While an added output is primed, the one output sent to
+    priming: Option<usize>,
+    // Each input port has its own SysEx in progress, and the one the
+    // message being mapped came from is `input`
+    inputs: Vec<Input>,
+    input: usize,
+}
+
+impl Mapper {
+    // undefined CC's from MIDI standard:
+    pub const FREE_CCS: &'static [u8] = &[
+        3, 9, 14, 15, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 85, 86, 87, 88, 89, 90, 102,
+        103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119,
+    ];
+    // ...and the LSB's of the undefined CC's above that have one. These are
+    // free too, as long as the corresponding CC isn't used as 14-bit.
+    pub const FREE_LSB_CCS: &'static [u8] = &[
+        35, 41, 46, 47, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63,
+    ];
+
+    // Anything longer than PG-1000 messages is passed through in pieces of
+    // this size rather than collected whole.
+    const MAX_SYSEX_BUFFER: usize = 64;
+    // ...except while waiting for the D-50 to answer a data request, which
+    // it does in pieces of up to 256 values
+    const MAX_DUMP_BUFFER: usize = 512;
+
+    // How long the D-50 gets to answer a data request
+    const SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+
+    // How often each slider can say its values are out of range
+    const RANGE_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+    // How long an RPN stays selected after its last value
+    const RPN_RELEASE: Duration = Duration::from_millis(100);
+
+    // How often smoothing ramps send their next value
+    const RAMP_TICK: Duration = Duration::from_millis(2);
+
+    // How often the lit feedback is checked for having been held long enough
+    const FEEDBACK_TICK: Duration = Duration::from_millis(10);
+
+    // How long after a slider sends whatever comes back for it from the
+    // DAW is taken as its own echo
+    const ECHO_WINDOW: Duration = Duration::from_millis(100);
+
+    // After this many sends in a row have failed an output is considered
+    // gone, and once all of them are, the main thread is told about it
+    const MAX_CONSECUTIVE_SEND_ERRORS: u32 = 20;
+
+    /// `outputs` are the names and connections of the output ports, the
+    /// translated messages going to all of them unless the config routes
+    /// them elsewhere.
+    pub fn new(config: Config, outputs: Vec<(String, Box<dyn MidiSink>)>) -> Result<Self, ConfigError> {
+        config.check(outputs.len())?;
+        let (sliders, ccs, tone_aliases) = Self::index(&config);
+        let outputs = outputs
+            .into_iter()
+            .map(|(name, sink)| Destination { name, sink: Some(sink), stats: OutputStats::default(), consecutive_errors: 0 })
+            .collect();
+        Ok(Self {
+            sliders,
+            ccs,
+            tone_aliases,
+            channel: config.channel,
+            outputs,
+            passthrough_outputs: Route::of(&config.passthrough_outputs),
+            passthrough_channels: config.passthrough_channels.clone(),
+            note_transforms: config.note_transforms.clone(),
+            tee: config.tee,
+            tee_outputs: Route::of(&config.tee_outputs),
+            feedback: config.feedback.clone(),
+            lit: HashMap::new(),
+            startup_sequence: config.startup_sequence.clone(),
+            startup: None,
+            active_sensing: config.active_sensing.clone(),
+            next_sensing: None,
+            echoes: HashMap::new(),
+            sent_sysex: HashMap::new(),
+            sent_lsbs: HashMap::new(),
+            virtual_params: config.virtual_params_in_order().unwrap_or_default(),
+            virtual_sources: Self::virtual_sources(&config),
+            virtual_values: HashMap::new(),
+            feedback_msbs: HashMap::new(),
+            feedback_parameters: HashMap::new(),
+            selected_parameters: HashMap::new(),
+            rpn_nulls: HashMap::new(),
+            last_values: HashMap::new(),
+            relative_bases: HashMap::new(),
+            steps: HashMap::new(),
+            interlocked: HashMap::new(),
+            interlock_noticed: HashSet::new(),
+            send_repeats: config.send_repeats,
+            rate_limit: config.rate_limit,
+            last_sent_at: HashMap::new(),
+            pending: HashMap::new(),
+            pickups: HashMap::new(),
+            pickup_after_program_change: config.pickup_after_program_change,
+            ramps: HashMap::new(),
+            arbitration: config.arbitration.clone(),
+            owners: HashMap::new(),
+            held_off: HashMap::new(),
+            events: None,
+            live_status: false,
+            osc: None,
+            recording: None,
+            arrived: None,
+            sync: None,
+            resend_trigger: config.resend_trigger_cc,
+            out_of_range: config.out_of_range,
+            range_warnings: HashMap::new(),
+            filter: config.filter.clone(),
+            device: config.device.clone(),
+            config,
+            profile: Config::DEFAULT_PROFILE.to_string(),
+            sysex_values: HashMap::new(),
+            sysex_events: 0,
+            cc_events: 0,
+            stats: stats::Stats::new(),
+            muted: false,
+            clock: Box::new(clock::SystemClock),
+            priming: None,
+            inputs: vec![Input::new("input".to_string())],
+            input: 0,
+        })
+    }
+
+    /// The sliders the formulas of the virtual params name, by label.
+    fn virtual_sources(config: &Config) -> HashMap<String, SysExId> {
+        let used: HashSet<&str> = config.virtual_params.iter().flat_map(|param| param.formula.names()).collect();
+        config
+            .sliders
+            .iter()
+            .map(|slider| (slider.label(&config.device), slider.sysex_id))
+            .filter(|(label, _)| used.contains(label.as_str()))
+            .collect()
+    }
+
+    /// The sliders of the config by address, the Lower tone's ones of split
+    /// mode included, the same by CC, and the other tone's addresses of
+    /// merged mode. The sliders' OSC addresses are filled in here, rather
+    /// than for every value sent.
+    fn index(config: &Config) -> (HashMap<SysExId, Slider>, CcIndex, HashMap<SysExId, SysExId>) {
+        let lower = config.lower_sliders(&config.sliders).into_iter().map(|(_, slider)| slider);
+        let sliders: HashMap<SysExId, Slider> = config
+            .sliders
+            .iter()
+            .cloned()
+            .chain(lower)
+            .map(|mut slider| {
+                slider.osc_address.get_or_insert_with(|| osc::default_address(&config.device, slider.sysex_id));
+                (slider.sysex_id, slider)
+            })
+            .collect();
+        let ccs = sliders
+            .values()
+            .filter_map(|slider| Some(((slider.channel.unwrap_or(config.channel), slider.cc_id()?), slider.sysex_id)))
+            .collect();
+        (sliders, ccs, config.tone_aliases(&config.sliders))
+    }
+
+    /// Swaps in a new mapping, returning the number of sliders. The state
+    /// of sliders that stay the same is kept; an invalid config changes nothing.
+    /// The profile in use stays in use, if the new config still has it.
+    pub fn reload(&mut self, config: Config) -> Result<usize, ConfigError> {
+        config.check(self.outputs.len())?;
+        let active = match config.profile(&self.profile) {
+            Ok(active) => active,
+            Err(_) => {
+                self.notice(format!("Profile '{}' is gone, using '{}'", self.profile, Config::DEFAULT_PROFILE));
+                self.profile = Config::DEFAULT_PROFILE.to_string();
+                config.clone()
+            }
+        };
+        self.config = config;
+        self.apply(active);
+        Ok(self.sliders.len())
+    }
+
+    /// Switches to another profile of the config, returning the number of
+    /// sliders in it. Nothing is known to have been sent with the new
+    /// mapping, so every slider sends its next value whatever it is.
+    pub fn select_profile(&mut self, name: &str) -> Result<usize, String> {
+        let active = self.config.profile(name)?;
+        self.apply(active);
+        self.invalidate_sent(None);
+        self.last_sent_at.clear();
+        // The answer would be for sliders that might not be there anymore
+        self.sync = None;
+        self.profile = name.to_string();
+        self.notice(format!(
+            "Using profile '{}', {} sliders on channel {}",
+            name,
+            self.sliders.len(),
+            self.channel + 1
+        ));
+        Ok(self.sliders.len())
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Sends on another channel, until a reload or a profile switch brings
+    /// back the one of the config. Sliders with a channel of their own stay
+    /// on it, and one of them already using a CC on the new channel makes
+    /// this fail.
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), String> {
+        let mut active = self.config.profile(&self.profile)?;
+        active.channel = channel;
+        active.validate().map_err(|err| format!("Can't switch to channel {}: {}", channel + 1, err))?;
+        self.apply(active);
+        self.notice(format!("Sending on channel {}", channel + 1));
+        Ok(())
+    }
+
+    /// Stops sending the sliders, or starts again, each slider then sending
+    /// its next value whatever it is. What is passed through or teed still
+    /// goes out.
+    pub fn set_muted(&mut self, muted: bool) {
+        if self.muted && !muted {
+            self.invalidate_sent(None);
+        }
+        self.muted = muted;
+        self.notice(if muted { "Muted the sliders" } else { "Unmuted the sliders" }.to_string());
+    }
+
+    fn apply(&mut self, config: Config) {
+        let (sliders, ccs, tone_aliases) = Self::index(&config);
+        let unchanged = |id: &SysExId| self.sliders.get(id).is_some_and(|old| sliders.get(id) == Some(old));
+        self.last_values.retain(|id, _| unchanged(id));
+        self.relative_bases.retain(|id, _| unchanged(id));
+        self.steps.retain(|id, _| unchanged(id));
+        self.interlocked.retain(|id, _| unchanged(id));
+        self.last_sent_at.retain(|id, _| unchanged(id));
+        self.pending.retain(|id, _| unchanged(id));
+        self.pickups.retain(|id, _| unchanged(id));
+        self.ramps.retain(|id, _| unchanged(id));
+        self.owners.retain(|id, _| unchanged(id));
+        self.held_off.retain(|(id, _), _| unchanged(id));
+        self.sent_sysex.retain(|id, _| unchanged(id));
+        self.echoes.retain(|id, _| unchanged(id));
+        self.sent_lsbs.retain(|cc, _| ccs.get(cc).is_some_and(&unchanged));
+        self.feedback_msbs.clear();
+        self.feedback_parameters.clear();
+        // A sync under way waits for the sliders still mapped only
+        if let Some(sync) = &mut self.sync {
+            sync.asked.retain(|id| sliders.contains_key(id));
+            sync.missing.retain(|id| sliders.contains_key(id));
+        }
+        if let Some(sync) = self.sync.take_if(|sync| sync.missing.is_empty()) {
+            self.notice(format!("Synced {} sliders from the D-50", sync.asked.len()));
+        }
+        if config.channel != self.channel {
+            self.invalidate_sent(None);
+        }
+        let virtual_params = config.virtual_params_in_order().unwrap_or_default();
+        if virtual_params != self.virtual_params {
+            self.virtual_values.clear();
+        }
+        self.virtual_params = virtual_params;
+        self.virtual_sources = Self::virtual_sources(&config);
+        self.sliders = sliders;
+        self.ccs = ccs;
+        self.tone_aliases = tone_aliases;
+        self.channel = config.channel;
+        self.rate_limit = config.rate_limit;
+        self.pickup_after_program_change = config.pickup_after_program_change;
+        self.send_repeats = config.send_repeats;
+        self.arbitration = config.arbitration;
+        self.resend_trigger = config.resend_trigger_cc;
+        self.filter = config.filter;
+        self.out_of_range = config.out_of_range;
+        self.passthrough_outputs = Route::of(&config.passthrough_outputs);
+        self.passthrough_channels = config.passthrough_channels;
+        // The notes sounding stay with their inputs, to be let go of as
+        // they were sent
+        self.note_transforms = config.note_transforms;
+        self.tee = config.tee;
+        self.tee_outputs = Route::of(&config.tee_outputs);
+        if config.feedback != self.feedback {
+            // Taken back the way it was lit
+            self.clear_feedback(|_| true);
+            self.feedback = config.feedback;
+        }
+        if config.startup_sequence != self.startup_sequence {
+            // The rest of the old one would be half a sequence
+            self.startup = None;
+            self.startup_sequence = config.startup_sequence;
+        }
+        if config.active_sensing != self.active_sensing {
+            self.active_sensing = config.active_sensing;
+            self.next_sensing = None;
+            for input in &mut self.inputs {
+                input.last_sensing = None;
+                input.sensing_lost = false;
+            }
+        }
+        self.device = config.device;
+    }
+
+    /// Names the input ports, in the order of the `input` numbers given to
+    /// `map_from`. Without this there is one, called "input".
+    pub fn set_inputs(&mut self, names: Vec<String>) {
+        for transform in self.note_transforms.iter().filter(|transform| transform.input >= names.len()) {
+            error!("note_transforms: there is no input {}, {} opened", transform.input + 1, names.len());
+        }
+        self.inputs = names.into_iter().map(Input::new).collect();
+        self.input = 0;
+    }
+
+    pub fn map(&mut self, message: &[u8]) {
+        self.map_from(0, None, message)
+    }
+
+    /// Maps a message that came from the `input`th input port, with the
+    /// timestamp midir gave it, if any. What it sends is recorded at the
+    /// time of the timestamp rather than when it got through to here.
+    pub fn map_from(&mut self, input: usize, stamp: Option<u64>, message: &[u8]) {
+        debug_assert!(input < self.inputs.len(), "input must be one given to set_inputs");
+        let now = self.clock.now();
+        self.arrived = stamp.map(|stamp| self.inputs[input].arrived(stamp, now));
+        self.map_input(input, message);
+        self.arrived = None;
+    }
+
+    fn map_input(&mut self, input: usize, message: &[u8]) {
+        self.input = input;
+        // midir normally hands over one complete message at a time, but
+        // some drivers split SysEx over several calls. Collect the pieces
+        // before parsing.
+        let mut bytes = message;
+        while !bytes.is_empty() {
+            let current = &mut self.inputs[input];
+            if !current.in_sysex {
+                if bytes[0] != 0xf0 {
+                    return self.map_message(bytes);
+                }
+                current.in_sysex = true;
+                current.sysex_overflowed = false;
+                current.sysex_buffer.clear();
+                current.sysex_buffer.push(0xf0);
+                bytes = &bytes[1..];
+            }
+            let mut consumed = bytes.len();
+            for (i, &byte) in bytes.iter().enumerate() {
+                if byte >= 0xf8 {
+                    // Realtime messages are allowed in the middle of SysEx
+                    self.map_message(&[byte]);
+                } else if byte == 0xf7 {
+                    self.inputs[input].sysex_buffer.push(byte);
+                    self.end_sysex();
+                    consumed = i + 1;
+                    break;
+                } else if byte & 0x80 != 0 {
+                    // Any other status byte ends the SysEx unfinished
+                    self.end_sysex();
+                    consumed = i;
+                    break;
+                } else {
+                    let max = if self.sync.is_some() { Self::MAX_DUMP_BUFFER } else { Self::MAX_SYSEX_BUFFER };
+                    if self.inputs[input].sysex_buffer.len() == max {
+                        self.passthrough_sysex_buffer();
+                        self.inputs[input].sysex_overflowed = true;
+                    }
+                    self.inputs[input].sysex_buffer.push(byte);
+                }
+            }
+            bytes = &bytes[consumed..];
+        }
+    }
+
+    fn end_sysex(&mut self) {
+        let current = &mut self.inputs[self.input];
+        current.in_sysex = false;
+        if current.sysex_overflowed || current.sysex_buffer.last() != Some(&0xf7) {
+            self.passthrough_sysex_buffer();
+        } else {
+            let buffer = std::mem::take(&mut current.sysex_buffer);
+            self.map_message(&buffer);
+            self.inputs[self.input].sysex_buffer = buffer;
+        }
+    }
+
+    fn passthrough_sysex_buffer(&mut self) {
+        let buffer = std::mem::take(&mut self.inputs[self.input].sysex_buffer);
+        self.passthrough(&buffer);
+        let current = &mut self.inputs[self.input];
+        current.sysex_buffer = buffer;
+        current.sysex_buffer.clear();
+    }
+
+    fn map_message(&mut self, message: &[u8]) {
+        self.inputs[self.input].stats.messages += 1;
+        if message == [0xfe] && self.active_sensing.is_some() {
+            self.heard_sensing(self.clock.now());
+        }
+        if let Some(sync) = &self.sync {
+            match DataSet::from_bytes(message) {
+                Ok(data) if data.values().any(|(id, _)| sync.first <= id && id <= sync.last) => return self.apply_sync(data),
+                _ => (),
+            }
+        }
+        // If this is a Roland PG-1000 sysex message and we've got a
+        // mapping for it, then map...
+        let sysex = match self.device.parse(message) {
+            Ok(sysex) => sysex,
+            Err(SysExError::NotPg1000(_)) => {
+                if self.is_resend_trigger(message) {
+                    self.resend();
+                    return;
+                }
+                if let [status, program] = message {
+                    if status & 0xf0 == 0xc0 {
+                        self.program_change(*program);
+                    }
+                }
+                return self.passthrough(message);
+            }
+            Err(err) => {
+                self.stats.parse_failures += 1;
+                error!("Dropping {:X?}, {}", message, err);
+                logging::event(|| format!(
+                    "{{\"time\":{},\"event\":\"dropped\",\"reason\":{},\"bytes\":{}}}",
+                    logging::timestamp(),
+                    logging::json_string(&err.to_string()),
+                    logging::json_bytes(message)
+                ));
+                return;
+            }
+        };
+        if self.sent_sysex.get(&sysex.id) == Some(&sysex.value) {
+            self.sent_sysex.remove(&sysex.id);
+            return;
+        }
+        let sysex = match self.tone_aliases.get(&sysex.id) {
+            Some(&id) => Pg1000SysExMessage::new(id, sysex.value),
+            None => sysex,
+        };
+        self.sysex_events += 1;
+        self.stats.receive(sysex.id, sysex.value);
+        if !self.sliders.contains_key(&sysex.id) {
+            // ...otherwise pass it through as is.
+            return self.passthrough(message);
+        }
+        // Whatever becomes of the value, the SysEx goes on as it came
+        match self.tee {
+            Some(Tee::SysExFirst) => {
+                self.tee(message);
+                self.translate(sysex);
+            }
+            Some(Tee::CcFirst) => {
+                self.translate(sysex);
+                self.tee(message);
+            }
+            None => self.translate(sysex),
+        }
+    }
+
+    /// Sends the value of a slider as what it is mapped to, once it is in
+    /// range and picked up, or starts a smoothing ramp to it.
+    fn translate(&mut self, mut sysex: Pg1000SysExMessage) {
+        if !self.sliders[&sysex.id].sysex_range.contains(sysex.value) {
+            sysex = match self.bring_in_range(sysex) {
+                Some(sysex) => sysex,
+                None => return,
+            };
+        }
+        if !self.arbitrate(sysex.id, Source::Physical) {
+            return;
+        }
+        self.inputs[self.input].stats.translated += 1;
+        self.sysex_values.insert(sysex.id, sysex.value);
+        self.update_virtual_params(Some(sysex.id));
+        let slider = &self.sliders[&sysex.id];
+        let value = slider.sysex_value_as_cc_value(sysex.value);
+        if let Some(pickup) = self.pickups.get_mut(&sysex.id) {
+            if !pickup.reached(value) {
+                return;
+            }
+            self.pickups.remove(&sysex.id);
+            // The synth may have another value by now, so the one picked up
+            // at goes out even if it is the one sent last
+            self.last_values.remove(&sysex.id);
+        }
+        if self.within_hysteresis(sysex.id, slider, value) {
+            return;
+        }
+        if let Some(duration) = slider.smoothing {
+            // A newer value replaces a ramp in progress, starting from
+            // wherever it had got to
+            if let Some(&from) = self.last_values.get(&sysex.id) {
+                self.ramps.insert(sysex.id, Ramp::new(from, value, self.clock.now(), duration));
+                return;
+            }
+        }
+        self.send_or_hold(sysex.id, value);
+    }
+
+    /// Whether a value is too close to the last one sent, or on its way, to
+    /// be sent, with the slider's hysteresis. The ends of the range always
+    /// get through, so that a full move lands exactly.
+    fn within_hysteresis(&self, id: SysExId, slider: &Slider, value: MidiValue) -> bool {
+        if slider.hysteresis == 0 || value == slider.cc_range.lo || value == slider.cc_range.hi {
+            return false;
+        }
+        let last = match (self.ramps.get(&id), self.pending.get(&id)) {
+            (Some(ramp), _) => Some(ramp.to),
+            (None, Some(pending)) => Some(*pending),
+            (None, None) => self.last_values.get(&id).copied(),
+        };
+        last.is_some_and(|last| last.abs_diff(value) < slider.hysteresis)
+    }
+
+    /// For a value outside the slider's sysex_range, the message with the
+    /// value brought into it, or None to drop it. Scaling would take the
+    /// nearest end anyway, but a value outside usually means the range in
+    /// the config is wrong.
+    fn bring_in_range(&mut self, sysex: Pg1000SysExMessage) -> Option<Pg1000SysExMessage> {
+        let slider = &self.sliders[&sysex.id];
+        let range = &slider.sysex_range;
+        self.stats.out_of_range += 1;
+        let clamped = Pg1000SysExMessage::new(sysex.id, range.clamp(sysex.value));
+        // Only put together when it is going to be printed
+        let problem = || format!("{}: {} is outside the sysex_range {}-{}", slider.label(&self.device), sysex.value, range.lo, range.hi);
+        match self.out_of_range {
+            OutOfRange::Clamp => return Some(clamped),
+            OutOfRange::Drop => {
+                debug!("{}, dropping it", problem());
+                return None;
+            }
+            OutOfRange::Warn => (),
+        }
+        let now = self.clock.now();
+        match self.range_warnings.get_mut(&sysex.id) {
+            Some((at, more)) if now.saturating_duration_since(*at) < Self::RANGE_WARNING_INTERVAL => *more += 1,
+            previous => {
+                let more = match previous {
+                    Some((_, more)) if *more > 0 => format!(" ({} more since the last time)", more),
+                    _ => String::new(),
+                };
+                error!("{}, using {}{}", problem(), clamped.value, more);
+                self.range_warnings.insert(sysex.id, (now, 0));
+            }
+        }
+        Some(clamped)
+    }
+
+    /// A program change on the input, on any channel, switches to the
+    /// profile it is mapped to. It is passed through all the same.
+    fn program_change(&mut self, program: u8) {
+        if let Some(name) = self.config.profile_for_program(program).map(str::to_string) {
+            if name != self.profile {
+                // Can't fail, the name comes from the config
+                let _ = self.select_profile(&name);
+            }
+        }
+        if self.pickup_after_program_change {
+            self.desync();
+        }
+    }
+
+    /// Sends the value, or keeps it for later if the rate limit says
+    /// it's too soon.
+    fn send_or_hold(&mut self, id: SysExId, value: MidiValue) {
+        if let Some(interval) = self.rate_limit {
+            let now = self.clock.now();
+            if self.last_sent_at.get(&id).is_some_and(|at| now.saturating_duration_since(*at) < interval) {
+                self.pending.insert(id, value);
+                return;
+            }
+        }
+        self.send_value(id, value);
+    }
+
+    /// Sends the last value of every slider that has sent something again,
+    /// in SysEx address order, e.g. for a DAW that was connected only now.
+    /// Returns the number of sliders.
+    pub fn resend(&mut self) -> usize {
+        let mut values: Vec<(SysExId, MidiValue)> = self
+            .last_values
+            .iter()
+            // A value held back by the rate limit is newer
+            .map(|(id, value)| (*id, self.pending.get(id).copied().unwrap_or(*value)))
+            .collect();
+        values.sort();
+        self.invalidate_sent(None);
+        for &(id, value) in &values {
+            self.send_or_hold(id, value);
+        }
+        self.update_virtual_params(None);
+        self.notice(format!("Resent {} sliders", values.len()));
+        values.len()
+    }
+
+    fn is_resend_trigger(&self, message: &[u8]) -> bool {
+        let cc = ControlMessage::from_bytes(message);
+        cc.is_some_and(|cc| cc.channel == self.channel && Some(cc.cc) == self.resend_trigger)
+    }
+
+    /// Asks for the values of all the sliders from the D-50, returning the
+    /// request to send to it. The answer is sent on as CCs, as if every
+    /// slider had been moved to where the D-50 has it.
+    pub fn start_sync(&mut self) -> Result<Vec<u8>, String> {
+        if !self.device.d50 {
+            return Err(format!("Syncing needs a D-50, not there with the {}", self.device));
+        }
+        let (first, last) = match (self.sliders.keys().min(), self.sliders.keys().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Err("No sliders to sync".to_string()),
+        };
+        let asked: HashSet<SysExId> = self.sliders.keys().copied().collect();
+        self.sync = Some(SyncRequest { sent_at: self.clock.now(), first, last, missing: asked.clone(), asked });
+        Ok(data_request(first, last))
+    }
+
+    /// For when the request could not be sent after all.
+    pub fn cancel_sync(&mut self) {
+        self.sync = None;
+    }
+
+    fn apply_sync(&mut self, data: DataSet) {
+        for (id, sysex_value) in data.values() {
+            let slider = match self.sliders.get(&id) {
+                Some(slider) => slider,
+                None => continue,
+            };
+            let value = slider.sysex_value_as_cc_value(sysex_value);
+            let pickup = slider.pickup;
+            if let Some(sync) = &mut self.sync {
+                sync.missing.remove(&id);
+            }
+            if !self.arbitrate(id, Source::Sync) {
+                continue;
+            }
+            self.sysex_values.insert(id, sysex_value);
+            self.update_virtual_params(Some(id));
+            self.ramps.remove(&id);
+            self.last_values.remove(&id);
+            self.send_value(id, value);
+            // The D-50 has the value now, the slider is wherever it is
+            if pickup {
+                self.pickups.insert(id, Pickup::new(value));
+            }
+        }
+        if let Some(sync) = self.sync.take_if(|sync| sync.missing.is_empty()) {
+            self.notice(format!("Synced {} sliders from the D-50", sync.asked.len()));
+        }
+    }
+
+    /// Computes the virtual params again after a slider they use has moved,
+    /// or after any of them has with None, sending those whose value changed
+    /// since it was last sent. A slider that hasn't sent anything yet is
+    /// where the defaults say.
+    fn update_virtual_params(&mut self, moved: Option<SysExId>) {
+        let used = |id: &SysExId| match moved {
+            Some(moved) => *id == moved,
+            None => self.sysex_values.contains_key(id),
+        };
+        if !self.virtual_sources.values().any(used) {
+            return;
+        }
+        let mut positions: HashMap<&str, f64> = HashMap::new();
+        let mut changed = vec![];
+        for param in &self.virtual_params {
+            let position = |name: &str| {
+                if let Some(position) = positions.get(name) {
+                    return *position;
+                }
+                let slider = self.virtual_sources.get(name).and_then(|id| Some((self.sliders.get(id)?, self.sysex_values.get(id)?)));
+                match slider {
+                    Some((slider, value)) => slider.sysex_range.absolute_to_relative(*value) * 100.0,
+                    None => param.defaults.iter().find(|(source, _)| source == name).map_or(0.0, |(_, default)| *default as f64),
+                }
+            };
+            let position = match param.formula.eval(&position) {
+                position if position.is_nan() => 0.0,
+                position => position.clamp(0.0, 100.0),
+            };
+            positions.insert(&param.name, position);
+            let value = param.cc_range.relative_to_absolute(position / 100.0);
+            if self.virtual_values.get(&param.name) != Some(&value) {
+                changed.push((param.name.clone(), ControlMessage::new(param.cc_id, value, param.channel.unwrap_or(self.channel)), Route::of(&param.outputs)));
+            }
+        }
+        if self.muted {
+            return;
+        }
+        for (name, cc, outputs) in changed {
+            self.send(&cc.to_bytes(), outputs);
+            if !self.live_status {
+                info!("{} (virtual) -> CC {} = {}", name, cc.cc, cc.value);
+            }
+            self.virtual_values.insert(name, cc.value);
+        }
+    }
+
+    /// Whether a value from `source` gets through to the slider, rather
+    /// than being held off by a source before it in the arbitration order
+    /// that moved the slider within the hold. Always, without arbitration.
+    fn arbitrate(&mut self, id: SysExId, source: Source) -> bool {
+        let policy = match self.sliders.get(&id).and_then(|slider| slider.arbitration.as_ref()).or(self.arbitration.as_ref()) {
+            Some(policy) => policy,
+            None => return true,
+        };
+        let now = self.clock.now();
+        let holding = |at: &Instant| now.saturating_duration_since(*at) < policy.hold;
+        let outranked = self
+            .owners
+            .get(&id)
+            .is_some_and(|(owner, at)| *owner != source && holding(at) && policy.rank(*owner) < policy.rank(source));
+        let paused = !policy.resume && self.held_off.get(&(id, source)).is_some_and(holding);
+        if outranked || paused {
+            if !policy.resume {
+                self.held_off.insert((id, source), now);
+            }
+            *self.stats.suppressed.entry(source).or_default() += 1;
+            return false;
+        }
+        self.held_off.remove(&(id, source));
+        self.owners.insert(id, (source, now));
+        true
+    }
+
+    /// The source holding a slider, as long as its hold lasts.
+    pub fn owner(&self, id: SysExId) -> Option<Source> {
+        let policy = self.sliders.get(&id).and_then(|slider| slider.arbitration.as_ref()).or(self.arbitration.as_ref())?;
+        let now = self.clock.now();
+        self.owners.get(&id).filter(|(_, at)| now.saturating_duration_since(*at) < policy.hold).map(|(owner, _)| *owner)
+    }
+
+    /// Sends a message for the user to the status display, or prints it.
+    fn notice(&self, message: String) {
+        match self.events.as_ref().filter(|_| self.live_status) {
+            Some(events) => {
+                let _ = events.send(status::Event::Message(message));
+            }
+            None => info!("{}", message),
+        }
+    }
+
+    /// How often `tick` needs to be called, if at all.
+    pub fn tick_interval(&self) -> Option<Duration> {
+        let ramp_tick = self.sliders.values().any(|slider| slider.smoothing.is_some()).then_some(Self::RAMP_TICK);
+        let sync_tick = self.sync.as_ref().map(|_| Self::SYNC_TIMEOUT / 10);
+        let rpn_tick = (!self.rpn_nulls.is_empty()).then_some(Self::RPN_RELEASE);
+        let feedback_tick = (!self.lit.is_empty()).then_some(Self::FEEDBACK_TICK);
+        let interlock_tick = (!self.interlocked.is_empty()).then_some(Self::RAMP_TICK);
+        let sequence_tick = self.startup.map(|(_, due)| due.saturating_duration_since(self.clock.now()));
+        let sensing_tick = self.active_sensing.as_ref().map(|sensing| {
+            let now = self.clock.now();
+            let next = self.next_sensing.map_or(Duration::ZERO, |due| due.saturating_duration_since(now));
+            let watched = self.inputs.iter().filter(|input| !input.sensing_lost).filter_map(|input| input.last_sensing);
+            watched.map(|at| (at + sensing.input_timeout).saturating_duration_since(now)).fold(next, Duration::min)
+        });
+        [self.rate_limit, ramp_tick, sync_tick, rpn_tick, feedback_tick, interlock_tick, sequence_tick, sensing_tick].into_iter().flatten().min()
+    }
+
+    /// Sends whatever is due from the startup sequence, Active Sensing, the
+    /// rate limit, the smoothing ramps, the interlock, the RPNs and the
+    /// feedback, and gives up on a data request the D-50 hasn't answered.
+    pub fn tick(&mut self) {
+        self.advance_sequence();
+        let now = self.clock.now();
+        self.sense(now);
+        self.flush_pending(false);
+        self.advance_ramps(false);
+        let interlocked: Vec<(SysExId, MidiValue)> = self.interlocked.iter().map(|(id, value)| (*id, *value)).collect();
+        for (id, value) in interlocked {
+            self.send_value(id, value);
+        }
+        self.release_rpns(|_, at| now.saturating_duration_since(at) >= Self::RPN_RELEASE);
+        if let Some(hold) = self.feedback.as_ref().map(|feedback| feedback.hold) {
+            self.clear_feedback(|at| now.saturating_duration_since(at) >= hold);
+        }
+        if let Some(sync) = self.sync.take_if(|sync| now.saturating_duration_since(sync.sent_at) > Self::SYNC_TIMEOUT) {
+            let message = if sync.missing.len() == sync.asked.len() {
+                "The D-50 didn't answer the data request. Check that its MIDI out is connected to the input, \
+                 and that its basic channel is 1"
+                    .to_string()
+            } else {
+                format!("The D-50 only sent {} of {} sliders", sync.asked.len() - sync.missing.len(), sync.asked.len())
+            };
+            error!("{}", message);
+            if self.live_status {
+                self.notice(message);
+            }
+        }
+    }
+
+    /// Sends everything still held back, e.g. before exiting. A move the
+    /// interlock is still stepping through stays where it got to, rather
+    /// than being rushed. Active Sensing stops for good, so that the
+    /// devices let go of their notes themselves.
+    pub fn flush(&mut self) {
+        self.flush_pending(true);
+        self.advance_ramps(true);
+        self.release_rpns(|_, _| true);
+        self.clear_feedback(|_| true);
+        self.active_sensing = None;
+    }
+
+    /// Sends Active Sensing if it is due at `now`, and tells about the
+    /// inputs that have stopped sending theirs.
+    fn sense(&mut self, now: Instant) {
+        let sensing = match &self.active_sensing {
+            Some(sensing) => sensing.clone(),
+            None => return,
+        };
+        if self.next_sensing.is_none_or(|due| due <= now) {
+            self.send_to(&[0xfe], Route::of(&sensing.outputs), "send active sensing");
+            self.next_sensing = Some(now + sensing.interval);
+        }
+        let mut lost = vec![];
+        for input in self.inputs.iter_mut().filter(|input| !input.sensing_lost) {
+            if input.last_sensing.is_some_and(|at| now.saturating_duration_since(at) >= sensing.input_timeout) {
+                input.sensing_lost = true;
+                lost.push(input.name.clone());
+            }
+        }
+        for name in lost {
+            let message = format!("'{}' stopped sending Active Sensing, check its cable and power", name);
+            error!("{}", message);
+            if self.live_status {
+                self.notice(message);
+            }
+        }
+    }
+
+    /// Active Sensing from the input being mapped, at `now`.
+    fn heard_sensing(&mut self, now: Instant) {
+        let input = &mut self.inputs[self.input];
+        input.last_sensing = Some(now);
+        if std::mem::take(&mut input.sensing_lost) {
+            let message = format!("'{}' is sending Active Sensing again", input.name);
+            self.notice(message);
+        }
+    }
+
+    /// Lights the feedback of a slider that moved, or keeps it lit for
+    /// longer if it already is.
+    fn light(&mut self, number: u8) {
+        let feedback = match &self.feedback {
+            Some(feedback) => feedback,
+            None => return,
+        };
+        let message = feedback.to_bytes(number, true);
+        let outputs = Route::of(&feedback.outputs);
+        if self.lit.insert(number, self.clock.now()).is_none() {
+            self.send_feedback(&message, outputs);
+        }
+    }
+
+    /// Takes back the feedback lit at a time that is `due`.
+    fn clear_feedback(&mut self, due: impl Fn(Instant) -> bool) {
+        let feedback = match &self.feedback {
+            Some(feedback) => feedback.clone(),
+            None => return self.lit.clear(),
+        };
+        let numbers: Vec<u8> = self.lit.iter().filter(|(_, at)| due(**at)).map(|(number, _)| *number).collect();
+        for number in numbers {
+            self.lit.remove(&number);
+            self.send_feedback(&feedback.to_bytes(number, false), Route::of(&feedback.outputs));
+        }
+    }
+
+    fn send_feedback(&mut self, message: &[u8], outputs: Route) {
+        if self.send_to(message, outputs, "send feedback") {
+            self.stats.feedback += 1;
+            debug!("Sent feedback {:X?}", message);
+        }
+    }
+
+    /// Sends the RPN null on the channels that have an RPN selected, of
+    /// those that are `due` by the channel and the time of the last value.
+    fn release_rpns(&mut self, due: impl Fn(u8, Instant) -> bool) {
+        let channels: Vec<u8> = self.rpn_nulls.iter().filter(|(channel, (at, _))| due(**channel, *at)).map(|(channel, _)| *channel).collect();
+        for channel in channels {
+            if let Some((_, outputs)) = self.rpn_nulls.remove(&channel) {
+                for cc in NrpnMessage::null(channel) {
+                    self.send(&cc.to_bytes(), outputs);
+                }
+                self.selected_parameters.remove(&channel);
+            }
+        }
+    }
+
+    fn advance_ramps(&mut self, finish: bool) {
+        let now = self.clock.now();
+        let ramps: Vec<(SysExId, Ramp)> = self.ramps.iter().map(|(id, ramp)| (*id, *ramp)).collect();
+        for (id, ramp) in ramps {
+            if finish || ramp.is_done(now) {
+                self.ramps.remove(&id);
+                self.send_value(id, ramp.to);
+            } else {
+                // Unchanged values are skipped by send_value
+                self.send_value(id, ramp.value_at(now));
+            }
+        }
+    }
+
+    /// Sends the values held back by the rate limit whose time has come, or
+    /// all of them. Called regularly so that the end of a sweep is never lost.
+    fn flush_pending(&mut self, all: bool) {
+        let interval = match self.rate_limit {
+            Some(interval) => interval,
+            None => return,
+        };
+        let now = self.clock.now();
+        let due: Vec<SysExId> = self
+            .pending
+            .keys()
+            .filter(|id| all || self.last_sent_at.get(id).is_none_or(|at| now.saturating_duration_since(*at) >= interval))
+            .copied()
+            .collect();
+        for id in due {
+            if let Some(value) = self.pending.remove(&id) {
+                self.send_value(id, value);
+            }
+        }
+    }
+
+    /// How far a slider with a max_step can go towards `value` now, from
+    /// the last value it sent: the value, a step on the way to it with the
+    /// rest left for tick(), or None for not yet. The first value has
+    /// nothing to be limited from and always goes. While muted nothing is
+    /// sent, so nothing moves.
+    fn limit_step(&mut self, id: SysExId, value: MidiValue) -> Option<MidiValue> {
+        let slider = match self.sliders.get(&id) {
+            Some(slider) if !self.muted && (slider.max_step.is_some() || slider.max_steps_per_second.is_some()) => slider,
+            _ => return Some(value),
+        };
+        let (last, at) = match self.steps.get(&id) {
+            Some(&last) => last,
+            None => {
+                self.steps.insert(id, (value, self.clock.now()));
+                return Some(value);
+            }
+        };
+        let mut allowed = slider.max_step.unwrap_or(MidiValue::MAX);
+        if let Some(per_second) = slider.max_steps_per_second {
+            // Saturates rather than wraps after a long rest
+            allowed = allowed.min((self.clock.now().saturating_duration_since(at).as_secs_f64() * per_second as f64) as MidiValue);
+        }
+        if last.abs_diff(value) <= allowed {
+            self.interlocked.remove(&id);
+            if value != last {
+                self.steps.insert(id, (value, self.clock.now()));
+            }
+            return Some(value);
+        }
+        if self.interlock_noticed.insert(id) {
+            error!(
+                "{}: the interlock is holding back a jump from {} to {}, sending it in steps (shown once)",
+                slider.label(&self.device),
+                last,
+                value
+            );
+        }
+        self.interlocked.insert(id, value);
+        if allowed == 0 {
+            return None;
+        }
+        let step = if value > last { last + allowed } else { last - allowed };
+        self.steps.insert(id, (step, self.clock.now()));
+        Some(step)
+    }
+
+    fn send_value(&mut self, id: SysExId, value: MidiValue) {
+        self.pending.remove(&id);
+        // The output stage, that everything sending a value goes through.
+        // A primed output gets its first values, with nothing to limit them from
+        let limited = match self.priming {
+            Some(_) => Some(value),
+            None => self.limit_step(id, value),
+        };
+        let value = match limited {
+            Some(value) => value,
+            None => return,
+        };
+        let slider = match self.sliders.get(&id) {
+            Some(slider) => slider,
+            None => return,
+        };
+        // Several SysEx values can scale onto the same CC value, and the
+        // PG-1000 resends values anyway when a slider is barely touched.
+        if !self.send_repeats && self.last_values.get(&id) == Some(&value) {
+            self.stats.duplicates += 1;
+            return;
+        }
+        self.last_values.insert(id, value);
+        if self.rate_limit.is_some() {
+            self.last_sent_at.insert(id, self.clock.now());
+        }
+        let output = slider.output;
+        let feedback = slider.feedback;
+        let channel = slider.channel.unwrap_or(self.channel);
+        let outputs = Route::of(&slider.outputs);
+        let osc_value = slider.cc_range.absolute_to_relative(value) as f32;
+        // Only for the --json event
+        let json = logging::json();
+        let mut sent = vec![];
+        let mut pitch_bend = None;
+        match (slider.output, slider.cc_mode) {
+            (Output::Cc(cc_id), CcMode::SevenBit) if slider.relative.is_some() => {
+                // While muted the base stays where the host was left, so that
+                // the first change after unmuting makes up for the ones it
+                // didn't get
+                let values = if self.muted {
+                    vec![]
+                } else {
+                    match (self.relative_bases.insert(id, value), slider.relative) {
+                        (Some(base), Some(relative)) => relative.encode(value as i32 - base as i32),
+                        // Nothing to tell the change from yet
+                        _ if slider.relative_baseline => vec![value],
+                        _ => vec![],
+                    }
+                };
+                for value in values {
+                    let cc = ControlMessage::new(cc_id, value, channel);
+                    self.send(&cc.to_bytes(), outputs);
+                    if json {
+                        sent.push(cc);
+                    }
+                }
+            }
+            (Output::Cc(cc_id), CcMode::SevenBit) => {
+                let cc = ControlMessage::new(cc_id, value, channel);
+                self.send(&cc.to_bytes(), outputs);
+                if json {
+                    sent.push(cc);
+                }
+            }
+            (Output::Cc(cc_id), CcMode::FourteenBit) => {
+                let msb = ControlMessage::new(cc_id, value >> 7, channel);
+                let lsb = ControlMessage::new(cc_id + 32, value & 0x7f, channel);
+                self.send(&msb.to_bytes(), outputs);
+                // Fine moves within the same LSB only need the MSB
+                if self.sent_lsbs.get(&(channel, msb.cc)) != Some(&lsb.value) {
+                    self.send(&lsb.to_bytes(), outputs);
+                    self.sent_lsbs.insert((channel, msb.cc), lsb.value);
+                    if json {
+                        sent.push(lsb);
+                    }
+                }
+                if json {
+                    sent.insert(0, msb);
+                }
+            }
+            (Output::Nrpn(number) | Output::Rpn(number), cc_mode) => {
+                let registered = matches!(output, Output::Rpn(_));
+                let nrpn = NrpnMessage::new(number, registered, value, cc_mode, channel);
+                // Another output may have a different NRPN selected
+                let select = self.selected_parameters.get(&channel).is_none_or(|(selected, selected_outputs)| *selected != output || *selected_outputs != outputs);
+                for cc in nrpn.to_control_messages(select) {
+                    self.send(&cc.to_bytes(), outputs);
+                    if json {
+                        sent.push(cc);
+                    }
+                }
+                self.selected_parameters.insert(channel, (output, outputs));
+                if registered {
+                    self.rpn_nulls.insert(channel, (self.clock.now(), outputs));
+                } else if self.rpn_nulls.get(&channel).is_some_and(|(_, rpn_outputs)| *rpn_outputs == outputs) {
+                    // The NRPN took its place
+                    self.rpn_nulls.remove(&channel);
+                }
+            }
+            (Output::PitchBend, _) => {
+                let bend = PitchBendMessage::new(value, channel);
+                self.send(&bend.to_bytes(), outputs);
+                pitch_bend = Some(bend);
+            }
+        }
+        if !self.muted {
+            self.echoes.insert(id, self.clock.now());
+        }
+        if let Some(number) = feedback.filter(|_| !self.muted) {
+            self.light(number);
+        }
+        // The address is always there, see index()
+        if let (Some(osc), Some(address), false) = (&self.osc, self.sliders.get(&id).and_then(|slider| slider.osc_address.as_ref()), self.muted) {
+            osc.send(address, osc_value);
+        }
+        let input = self.sysex_values.get(&id).copied().unwrap_or_default();
+        // Only put together when someone is going to see it
+        let label = || self.sliders.get(&id).map(|slider| slider.label(&self.device)).unwrap_or_default();
+        if let Some(slider) = self.sliders.get(&id).filter(|_| !self.live_status && logging::enabled(logging::Level::Info)) {
+            let label = Label(slider, &self.device);
+            let muted = if self.muted { " (muted)" } else { "" };
+            if channel != self.channel {
+                info!("{}: {} -> {} on channel {} = {}{}", label, input, output, channel + 1, value, muted);
+            } else {
+                info!("{}: {} -> {} = {}{}", label, input, output, value, muted);
+            }
+        }
+        if json {
+            let ccs: Vec<String> = sent.iter().map(ControlMessage::to_json).collect();
+            let pitch_bend = match &pitch_bend {
+                Some(bend) => format!(",\"pitch_bend\":{}", bend.to_json()),
+                None => String::new(),
+            };
+            logging::event(|| format!(
+                "{{\"time\":{},\"event\":\"translated\",\"name\":{},\"sysex\":{},\"output\":{},\"ccs\":[{}]{}}}",
+                logging::timestamp(),
+                logging::json_string(&label()),
+                Pg1000SysExMessage::new(id, input).to_json(),
+                value,
+                ccs.join(","),
+                pitch_bend
+            ));
+        }
+        self.show(id, input, value);
+    }
+
+    /// A slider's row on the status display, if there is one.
+    fn show(&self, id: SysExId, sysex_value: MidiValue, cc_value: MidiValue) {
+        if let Some(events) = self.events.as_ref().filter(|_| self.live_status) {
+            // A closed display just means nobody is watching
+            let _ = events.send(status::Event::Update(status::SliderUpdate {
+                id,
+                label: self.sliders.get(&id).map(|slider| slider.label(&self.device)).unwrap_or_default(),
+                sysex_value,
+                cc_value,
+                owner: self.owner(id),
+                sysex_events: self.sysex_events,
+                cc_events: self.cc_events,
+            }));
+        }
+    }
+
+    /// Keeps everything sent from now on, to be written to a MIDI file.
+    pub fn start_recording(&mut self, passthrough: bool) {
+        self.recording = Some(smf::Recording::started(self.clock.now(), passthrough));
+    }
+
+    pub fn take_recording(&mut self) -> Option<smf::Recording> {
+        self.recording.take()
+    }
+
+    /// Also sends every slider value as OSC.
+    pub fn set_osc(&mut self, osc: osc::OscSender) {
+        self.osc = Some(osc);
+    }
+
+    /// Drops a SysEx message cut short by losing the input, and sends the
+    /// startup_sequence again, as the devices were most likely switched off
+    /// with it.
+    pub fn input_reconnected(&mut self, input: usize) {
+        let current = &mut self.inputs[input];
+        current.in_sysex = false;
+        current.sysex_buffer.clear();
+        // Watched again once it sends some
+        current.last_sensing = None;
+        current.sensing_lost = false;
+        current.first_stamp = None;
+        self.start_sequence();
+    }
+
+    /// Sends the startup_sequence from the start, up to its first delay;
+    /// tick() sends the rest. One already under way starts over. False if
+    /// the config has none.
+    pub fn start_sequence(&mut self) -> bool {
+        if self.startup_sequence.is_empty() {
+            return false;
+        }
+        info!("Sending the startup sequence, {} entries", self.startup_sequence.len());
+        self.startup = Some((0, self.clock.now()));
+        self.advance_sequence();
+        true
+    }
+
+    /// Whether the startup_sequence is still being sent.
+    pub fn sequence_running(&self) -> bool {
+        self.startup.is_some()
+    }
+
+    /// Sends the entries of the startup_sequence that are due, up to the
+    /// next delay.
+    fn advance_sequence(&mut self) {
+        while let Some((index, due)) = self.startup {
+            if due > self.clock.now() {
+                return;
+            }
+            let step = match self.startup_sequence.get(index) {
+                Some(step) => step.clone(),
+                None => {
+                    info!("Sent the startup sequence");
+                    self.startup = None;
+                    return;
+                }
+            };
+            let count = self.startup_sequence.len();
+            let next = match step.action {
+                StartupAction::Delay(delay) => self.clock.now() + delay,
+                StartupAction::Send(bytes) => {
+                    if self.send_to(&bytes, Route::of(&step.outputs), "send the startup sequence") {
+                        info!("Startup sequence {}/{}: sent {:X?}", index + 1, count, bytes);
+                    } else if step.on_failure == OnFailure::Abort {
+                        error!("Startup sequence {}/{}: {:X?} reached no output, giving up on the rest", index + 1, count, bytes);
+                        self.startup = None;
+                        return;
+                    } else {
+                        error!("Startup sequence {}/{}: {:X?} reached no output, carrying on", index + 1, count, bytes);
+                    }
+                    due
+                }
+            };
+            self.startup = Some((index + 1, next));
+        }
+    }
+
+    /// Each input port's name with what has come from it.
+    pub fn input_stats(&self) -> impl Iterator<Item = (&str, &InputStats)> {
+        self.inputs.iter().map(|input| (input.name.as_str(), &input.stats))
+    }
+
+    /// Gives the mapper a way to reach the main thread. With `live_status`
+    /// slider updates go there for the status display instead of being
+    /// printed one per line.
+    pub fn set_events(&mut self, events: Sender<status::Event>, live_status: bool) {
+        self.events = Some(events);
+        self.live_status = live_status;
+    }
+
+    /// Has the timers go by another clock, the statistics starting over
+    /// from its time.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.stats = stats::Stats::since(clock.now());
+        self.clock = clock;
+    }
+
+    /// Forgets the last sent value of a slider, or of all of them with
+    /// None, so that the next value is sent even if it is the same.
+    pub fn invalidate_sent(&mut self, id: Option<SysExId>) {
+        match id {
+            Some(id) => {
+                self.last_values.remove(&id);
+            }
+            None => {
+                self.last_values.clear();
+                self.sent_lsbs.clear();
+                self.selected_parameters.clear();
+                self.virtual_values.clear();
+            }
+        }
+    }
+
+    /// The synth's values no longer match the sliders: the sliders in
+    /// pickup mode stay silent until they reach their last sent value.
+    pub fn desync(&mut self) {
+        for (id, slider) in &self.sliders {
+            if let (true, Some(value)) = (slider.pickup, self.last_values.get(id)) {
+                self.pickups.insert(*id, Pickup::new(*value));
+            }
+        }
+    }
+
+    pub fn suppressed_duplicates(&self) -> u64 {
+        self.stats.duplicates
+    }
+
+    /// How many messages of each kind the filter has dropped since the
+    /// start or the last reset.
+    pub fn filtered(&self) -> &BTreeMap<filter::Kind, u64> {
+        &self.stats.filtered
+    }
+
+    /// The statistics since the start or the last reset, as a table.
+    pub fn stats_report(&self) -> String {
+        let label = |id: SysExId| match self.sliders.get(&id) {
+            Some(slider) => slider.label(&self.device),
+            None => self.device.d50.then(|| parameters::name(id)).flatten().unwrap_or_default(),
+        };
+        let mut out = self.stats.report(self.clock.now(), label);
+        let mut owned: Vec<(SysExId, Source)> = self.owners.keys().filter_map(|id| Some((*id, self.owner(*id)?))).collect();
+        owned.sort();
+        if !owned.is_empty() {
+            let owned: Vec<String> = owned.into_iter().map(|(id, owner)| format!("{} ({})", label(id), owner)).collect();
+            out += &format!("\nHeld now: {}", owned.join(", "));
+        }
+        out
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = stats::Stats::since(self.clock.now());
+    }
+
+    /// Failed sends over all outputs.
+    pub fn send_errors(&self) -> u64 {
+        self.outputs.iter().map(|output| output.stats.errors).sum()
+    }
+
+    /// Each output port's name with what has gone to it, the removed ones
+    /// left out.
+    pub fn output_stats(&self) -> impl Iterator<Item = (&str, &OutputStats)> {
+        self.outputs.iter().filter(|output| output.sink.is_some()).map(|output| (output.name.as_str(), &output.stats))
+    }
+
+    /// Adds an output while forwarding, after the ones there are, so that
+    /// the routing of the config keeps meaning the same outputs. It gets
+    /// what isn't routed elsewhere. With `prime`, the last value of every
+    /// slider is sent to it first, so that it starts out in sync.
+    pub fn add_output(&mut self, name: String, sink: Box<dyn MidiSink>, prime: bool) -> Result<(), String> {
+        if self.outputs.iter().any(|output| output.sink.is_some() && output.name == name) {
+            return Err(format!("There is an output named '{}' already", name));
+        }
+        if self.outputs.len() == Config::MAX_OUTPUTS {
+            return Err(format!("At most {} outputs can be added, the removed ones included", Config::MAX_OUTPUTS));
+        }
+        self.outputs.push(Destination { name: name.clone(), sink: Some(sink), stats: OutputStats::default(), consecutive_errors: 0 });
+        let index = self.outputs.len() - 1;
+        let primed = if prime { self.prime(index) } else { 0 };
+        match prime {
+            true => self.notice(format!("Added output {} '{}', with the values of {} sliders", index + 1, name, primed)),
+            false => self.notice(format!("Added output {} '{}'", index + 1, name)),
+        }
+        Ok(())
+    }
+
+    /// Closes an output while forwarding. Nothing is queued for it, each
+    /// message having gone to every output as it came, so there is nothing
+    /// left to drain or drop.
+    pub fn remove_output(&mut self, name: &str) -> Result<(), String> {
+        let index = match self.outputs.iter().position(|output| output.sink.is_some() && output.name == name) {
+            Some(index) => index,
+            None => return Err(format!("There is no output named '{}'", name)),
+        };
+        // Dropping the connection closes the port
+        self.outputs[index].sink = None;
+        self.notice(format!("Removed output {} '{}'", index + 1, name));
+        Ok(())
+    }
+
+    /// Sends the last value of every slider to the one output, the others
+    /// having had it already. Returns the number of sliders.
+    fn prime(&mut self, output: usize) -> usize {
+        let mut values: Vec<(SysExId, MidiValue)> = self.last_values.iter().map(|(id, value)| (*id, *value)).collect();
+        values.sort();
+        // Also sends the NRPN selects and 14-bit LSBs again
+        self.invalidate_sent(None);
+        // What is held back by the rate limit stays held back for all of them
+        let pending = std::mem::take(&mut self.pending);
+        let last_sent_at = self.last_sent_at.clone();
+        self.priming = Some(output);
+        for &(id, value) in &values {
+            self.send_value(id, value);
+        }
+        self.priming = None;
+        self.pending = pending;
+        self.last_sent_at = last_sent_at;
+        values.len()
+    }
+
+    /// Sends to the given outputs, or all of them, returning whether it
+    /// got to at least one. The callback carries on regardless of failures,
+    /// but an output failing long enough is reported, once, and once all
+    /// of them are, so is it to the main thread.
+    fn send_to(&mut self, message: &[u8], outputs: Route, what: &str) -> bool {
+        let mut any_sent = false;
+        let mut gone = vec![];
+        for (i, output) in self.outputs.iter_mut().enumerate() {
+            let sink = match &mut output.sink {
+                Some(sink) if outputs.contains(i) && self.priming.is_none_or(|priming| priming == i) => sink,
+                _ => continue,
+            };
+            match sink.send(message) {
+                Ok(()) => {
+                    output.stats.sent += 1;
+                    output.consecutive_errors = 0;
+                    any_sent = true;
+                }
+                Err(err) => {
+                    output.stats.errors += 1;
+                    self.stats.send_errors += 1;
+                    output.consecutive_errors += 1;
+                    error!("Could not {} {:X?} to '{}': {}", what, message, output.name, err);
+                    if output.consecutive_errors == Self::MAX_CONSECUTIVE_SEND_ERRORS {
+                        gone.push(format!("{} sends in a row to '{}' failed, last with: {}", output.consecutive_errors, output.name, err));
+                    }
+                }
+            }
+        }
+        let all_gone = self.outputs.iter().filter(|output| output.sink.is_some()).all(|output| output.consecutive_errors >= Self::MAX_CONSECUTIVE_SEND_ERRORS);
+        for message in gone {
+            match (&self.events, all_gone) {
+                (Some(events), true) => {
+                    let _ = events.send(status::Event::OutputFailing(message));
+                }
+                (None, true) => (),
+                (_, false) => error!("{}, carrying on with the other outputs", message),
+            }
+        }
+        any_sent
+    }
+
+    fn passthrough(&mut self, message: &[u8]) {
+        if let Some(kind) = self.filter.drops(message) {
+            *self.stats.filtered.entry(kind).or_default() += 1;
+            self.inputs[self.input].stats.filtered += 1;
+            logging::event(|| format!(
+                "{{\"time\":{},\"event\":\"filtered\",\"kind\":\"{}\",\"bytes\":{}}}",
+                logging::timestamp(),
+                kind,
+                logging::json_bytes(message)
+            ));
+            return;
+        }
+        // On the channel it came on, which the note transforms go by
+        let mut transformed = [0; 3];
+        let transform = self.note_transforms.iter().find(|transform| transform.input == self.input);
+        let message = match self.inputs[self.input].notes.apply(transform, message, &mut transformed) {
+            Some(message) => message,
+            None => {
+                self.inputs[self.input].stats.filtered += 1;
+                debug!("Dropped {:X?}, outside the note_range of its input or transposed past the end", message);
+                logging::event(|| {
+                    format!("{{\"time\":{},\"event\":\"filtered\",\"kind\":\"note_range\",\"bytes\":{}}}", logging::timestamp(), logging::json_bytes(message))
+                });
+                return;
+            }
+        };
+        let mut remapped = [0; 3];
+        let message = match self.passthrough_channels.apply(message, &mut remapped) {
+            Some(message) => message,
+            None => {
+                self.inputs[self.input].stats.filtered += 1;
+                debug!("Dropped {:X?}, its channel isn't in passthrough_channel", message);
+                logging::event(|| {
+                    format!("{{\"time\":{},\"event\":\"filtered\",\"kind\":\"channel\",\"bytes\":{}}}", logging::timestamp(), logging::json_bytes(message))
+                });
+                return;
+            }
+        };
+        self.inputs[self.input].stats.passed_through += 1;
+        // Data entry from elsewhere would change the selected RPN
+        if let Some(cc) = ControlMessage::from_bytes(message).filter(|cc| [6, 38, 96, 97].contains(&cc.cc)) {
+            self.release_rpns(|channel, _| channel == cc.channel);
+        }
+        self.stats.passed_through += 1;
+        if let Some(recording) = self.recording.as_mut().filter(|recording| recording.passthrough) {
+            recording.record(self.arrived.unwrap_or_else(|| self.clock.now()), message);
+        }
+        // The outputs getting Active Sensing of our own don't need this one too
+        let outputs = match &self.active_sensing {
+            Some(sensing) if message == [0xfe] => self.passthrough_outputs.without(Route::of(&sensing.outputs)),
+            _ => self.passthrough_outputs,
+        };
+        if self.send_to(message, outputs, "pass through") {
+            debug!("Passed through {:X?}", message);
+        }
+        logging::event(|| format!(
+            "{{\"time\":{},\"event\":\"passthrough\",\"bytes\":{}}}",
+            logging::timestamp(),
+            logging::json_bytes(message)
+        ));
+    }
+
+    /// Sends the SysEx of a slider on as it came, besides what it was
+    /// translated into. Like passthrough it goes on while muted, mute being
+    /// for the CCs only.
+    fn tee(&mut self, message: &[u8]) {
+        if let Some(recording) = self.recording.as_mut().filter(|recording| recording.passthrough) {
+            recording.record(self.arrived.unwrap_or_else(|| self.clock.now()), message);
+        }
+        if self.send_to(message, self.tee_outputs, "tee") {
+            self.stats.teed += 1;
+            debug!("Teed {:X?}", message);
+        }
+        logging::event(|| format!("{{\"time\":{},\"event\":\"teed\",\"bytes\":{}}}", logging::timestamp(), logging::json_bytes(message)));
+    }
+
+    fn send(&mut self, message: &[u8], outputs: Route) {
+        if self.muted {
+            return;
+        }
+        // A primed output gets what has been recorded already
+        if let Some(recording) = self.recording.as_mut().filter(|_| self.priming.is_none()) {
+            recording.record(self.arrived.unwrap_or_else(|| self.clock.now()), message);
+        }
+        if self.send_to(message, outputs, "send") {
+            self.cc_events += 1;
+            self.stats.sent(message);
+            debug!("Sent {:X?}", message);
+        }
+    }
+
+    /// The reverse direction: converts a CC (or pitch bend, 14-bit CC pair
+    /// or NRPN) for a mapped slider, on its channel, back into the PG-1000
+    /// SysEx it came from, to be sent to the D-50. A slider in pickup mode
+    /// then waits to reach the new value before sending again, as when the
+    /// DAW loads a project. Nothing goes back to the DAW.
+    pub fn reverse_map(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        if self.is_resend_trigger(message) {
+            self.resend();
+            return None;
+        }
+        let (id, value, refines) = match PitchBendMessage::from_bytes(message) {
+            Some(bend) => {
+                let slider = self
+                    .sliders
+                    .values()
+                    .find(|slider| slider.output == Output::PitchBend && slider.channel == Some(bend.channel))?;
+                (slider.sysex_id, bend.value, false)
+            }
+            None => self.reverse_cc(ControlMessage::from_bytes(message)?)?,
+        };
+        if self.echoes.get(&id).is_some_and(|at| self.clock.now().saturating_duration_since(*at) < Self::ECHO_WINDOW) {
+            return None;
+        }
+        if !self.arbitrate(id, Source::Reverse) {
+            return None;
+        }
+        let slider = &self.sliders[&id];
+        let sysex = Pg1000SysExMessage::new(id, slider.cc_value_as_sysex_value(value));
+        self.show(id, sysex.value, value);
+        if slider.pickup {
+            // The destination is at the DAW's value now, wherever the
+            // slider is
+            self.pickups.insert(id, Pickup::new(value));
+            self.last_values.insert(id, value);
+        } else {
+            // The D-50 now has a value the DAW knows about but we didn't send
+            self.invalidate_sent(Some(id));
+        }
+        if let Some(step) = self.steps.get_mut(&id) {
+            step.0 = value;
+        }
+        self.interlocked.remove(&id);
+        self.ramps.remove(&id);
+        // The LSB of a 14-bit value often changes nothing for the D-50
+        if refines && self.sent_sysex.get(&id) == Some(&sysex.value) {
+            return None;
+        }
+        self.sent_sysex.insert(id, sysex.value);
+        Some(self.device.to_bytes(&sysex))
+    }
+
+    /// The slider a CC is for and the value it brings, once there is one,
+    /// and whether it refines the one before: the MSB of a 14-bit CC
+    /// already is a value, its LSB refines it, and NRPN data entry goes to
+    /// the slider of the NRPN selected before.
+    fn reverse_cc(&mut self, cc: ControlMessage) -> Option<(SysExId, MidiValue, bool)> {
+        let key = (cc.channel, cc.cc);
+        // The changes of relative sliders don't say where they are
+        let absolute = |slider: &&Slider| slider.relative.is_none();
+        if let Some(slider) = self.ccs.get(&key).and_then(|id| self.sliders.get(id)).filter(absolute) {
+            return Some(match slider.cc_mode {
+                CcMode::SevenBit => (slider.sysex_id, cc.value, false),
+                CcMode::FourteenBit => {
+                    self.feedback_msbs.insert(key, cc.value);
+                    (slider.sysex_id, cc.value << 7, false)
+                }
+            });
+        }
+        if (32..64).contains(&cc.cc) {
+            let msb_key = (cc.channel, cc.cc - 32);
+            if let Some(slider) = self.ccs.get(&msb_key).and_then(|id| self.sliders.get(id)).filter(absolute) {
+                let msb = self.feedback_msbs.get(&msb_key).filter(|_| slider.cc_mode == CcMode::FourteenBit)?;
+                return Some((slider.sysex_id, msb << 7 | cc.value, true));
+            }
+        }
+        let selected = self.feedback_parameters.get(&cc.channel).map(|(output, _)| *output);
+        let (nrpn, rpn) = match selected {
+            Some(Output::Nrpn(number)) => (number, 0),
+            Some(Output::Rpn(number)) => (0, number),
+            _ => (0, 0),
+        };
+        let select = match cc.cc {
+            99 => Output::Nrpn(cc.value << 7 | nrpn & 0x7f),
+            98 => Output::Nrpn(nrpn & !0x7f | cc.value),
+            101 => Output::Rpn(cc.value << 7 | rpn & 0x7f),
+            100 => Output::Rpn(rpn & !0x7f | cc.value),
+            6 | 38 => {
+                let channel = self.channel;
+                let slider = self.sliders.values().find(|slider| Some(slider.output) == selected && slider.channel.unwrap_or(channel) == cc.channel)?;
+                let (_, msb) = self.feedback_parameters.get_mut(&cc.channel)?;
+                return match (cc.cc, slider.cc_mode) {
+                    (6, CcMode::SevenBit) => Some((slider.sysex_id, cc.value, false)),
+                    (6, CcMode::FourteenBit) => {
+                        *msb = Some(cc.value);
+                        Some((slider.sysex_id, cc.value << 7, false))
+                    }
+                    (_, CcMode::FourteenBit) => Some((slider.sysex_id, (*msb)? << 7 | cc.value, true)),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+        self.feedback_parameters.insert(cc.channel, (select, None));
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+    use std::sync::{Arc, Mutex};
+
+    // DT1 messages as the PG-1000 sends them, moving the Upper tone's Chorus
+    // Rate (0x00012B, 0-100) and P-ENV T1 (0x00010D, 0-50)
+    const CHORUS_RATE_0: [u8; 11] = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x2b, 0x00, 0x54, 0xf7];
+    const CHORUS_RATE_50: [u8; 11] = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x2b, 0x32, 0x22, 0xf7];
+    const CHORUS_RATE_100: [u8; 11] = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x2b, 0x64, 0x70, 0xf7];
+    const T1_25: [u8; 11] = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x0d, 0x19, 0x59, 0xf7];
+
+    /// Keeps everything sent to it, a clone looking at the same messages.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<Vec<u8>> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl MidiSink for Recorder {
+        fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+            self.0.lock().unwrap().push(message.to_vec());
+            Ok(())
+        }
+    }
+
+    /// An output port that has gone away.
+    struct Failing;
+
+    impl MidiSink for Failing {
+        fn send(&mut self, _: &[u8]) -> Result<(), SendError> {
+            Err(SendError::Other("the port is gone"))
+        }
+    }
+
+    fn mapper(config: Config) -> (Mapper, Recorder) {
+        logging::set_level(logging::Level::Error);
+        let recorder = Recorder::default();
+        let mapper = Mapper::new(config, vec![("test".to_string(), Box::new(recorder.clone()))]).unwrap();
+        (mapper, recorder)
+    }
+
+    /// Has the mapper's timers go by a clock that only moves when told.
+    fn clocked(mapper: &mut Mapper) -> ManualClock {
+        let clock = ManualClock::new();
+        mapper.set_clock(Box::new(clock.clone()));
+        clock
+    }
+
+    fn slider(sysex_range: MidiRange, cc_range: MidiRange) -> Slider {
+        Slider::new(0x00012b, 85, sysex_range, cc_range)
+    }
+
+    #[test]
+    fn clamps_values_instead_of_masking_them() {
+        for (value, clamped) in [(0, 0), (100, 100), (127, 127), (128, 127), (200, 127), (0x3fff, 127)] {
+            assert_eq!(ControlMessage::new(85, value, 0).value(), clamped, "{}", value);
+            assert_eq!(Pg1000SysExMessage::new(0x00012b, value).value(), clamped, "{}", value);
+        }
+        assert_eq!(PitchBendMessage::new(0x4000, 0).value(), 0x3fff);
+        // A value past the end of the sysex_range scales onto the end of the
+        // CC range, not past it
+        let slider = slider(MidiRange::new(0, 100), MidiRange::new(0, 127));
+        for (value, cc_value) in [(0, 0), (100, 127), (127, 127)] {
+            assert_eq!(slider.sysex_value_as_cc_value(value), cc_value, "{}", value);
+        }
+        let range = MidiRange::new(0, 100);
+        assert_eq!([0, 100, 127].map(|value| range.clamp(value)), [0, 100, 100]);
+        assert_eq!([0, 100, 127].map(|value| range.contains(value)), [true, true, false]);
+        let (mut mapper, sent) = mapper(Config::default());
+        for value in [0, 100, 127] {
+            mapper.invalidate_sent(None);
+            mapper.map(&Pg1000SysExMessage::new(0x00012b, value).to_bytes());
+        }
+        assert_eq!(sent.take(), [[0xb1, 85, 0], [0xb1, 85, 127], [0xb1, 85, 127]]);
+    }
+
+    #[test]
+    fn maps_inverted_ranges_backwards() {
+        let sysex = MidiRange::new(0, 100);
+        let cc = MidiRange::new(0, 127);
+        let cases = [
+            (slider(sysex.clone(), cc.inverted()), [127, 63, 0]),
+            (slider(sysex.inverted(), cc.clone()), [127, 64, 0]),
+            // Inverted twice is the right way round again, but for the
+            // halfway value rounding towards the lo end, 127
+            (slider(sysex.inverted(), cc.inverted()), [0, 63, 127]),
+        ];
+        for (slider, expected) in cases {
+            assert_eq!([0, 50, 100].map(|value| slider.sysex_value_as_cc_value(value)), expected, "{:?}", slider);
+            for value in 0..=100 {
+                assert_eq!(slider.cc_value_as_sysex_value(slider.sysex_value_as_cc_value(value)), value, "{:?}", slider);
+            }
+        }
+        assert!(cc.inverted().is_inverted() && !cc.is_inverted());
+        assert_eq!(cc.inverted().width(), cc.width());
+        // Clamping doesn't care which way round the range is
+        assert_eq!([0, 100, 127].map(|value| sysex.inverted().clamp(value)), [0, 100, 100]);
+        // As the config gives it
+        let config = Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 100]\n    inverted: true\n").unwrap();
+        assert_eq!(config.sliders[0], slider(sysex, cc.inverted()));
+    }
+
+    #[test]
+    fn maps_the_envelope_times_over_their_0_to_50() {
+        // T1-T4 of the P-ENV go up to 0x32
+        let cc = MidiRange::new(0, 127);
+        let times = slider(MidiRange::new(0, 0x32), cc.clone());
+        assert_eq!([0, 1, 25, 49, 0x32].map(|value| times.sysex_value_as_cc_value(value)), [0, 3, 64, 124, 127]);
+        let inverted = slider(MidiRange::new(0, 0x32), cc.inverted());
+        assert_eq!([0, 1, 25, 49, 0x32].map(|value| inverted.sysex_value_as_cc_value(value)), [127, 124, 63, 3, 0]);
+        for slider in [times, inverted] {
+            for value in 0..=0x32 {
+                assert_eq!(slider.cc_value_as_sysex_value(slider.sysex_value_as_cc_value(value)), value);
+            }
+        }
+        let config = Config::default();
+        for id in 0x00010d..=0x000110 {
+            let slider = config.sliders.iter().find(|slider| slider.sysex_id == id).unwrap();
+            assert_eq!(slider.sysex_range, MidiRange::new(0, 0x32), "0x{:06X}", id);
+        }
+    }
+
+    fn assert_near(actual: f64, expected: f64, what: &str) {
+        assert!((actual - expected).abs() < 1e-9, "{}: {} instead of {}", what, actual, expected);
+    }
+
+    #[test]
+    fn shapes_the_curves() {
+        // 0, halfway, 1, and what the halfway point is sent as on 0-127
+        let cases = [
+            (Curve::Linear, 0.5, 64),
+            (Curve::Exponential(2.0), 0.25, 32),
+            (Curve::Exponential(3.0), 0.125, 16),
+            (Curve::Logarithmic(2.0), 0.75, 95),
+            (Curve::Table(vec![0.0, 0.1, 1.0]), 0.1, 13),
+        ];
+        for (curve, halfway, cc_value) in cases {
+            let what = format!("{:?}", curve);
+            assert_near(curve.apply(0.0), 0.0, &what);
+            assert_near(curve.apply(0.5), halfway, &what);
+            assert_near(curve.apply(1.0), 1.0, &what);
+            // Outside 0.0-1.0 it stays at the ends
+            assert_near(curve.apply(-0.5), 0.0, &what);
+            assert_near(curve.apply(1.5), 1.0, &what);
+            for x in [0.0, 0.1, 0.5, 0.9, 1.0] {
+                assert_near(curve.invert(curve.apply(x)), x, &what);
+            }
+            let slider = slider(MidiRange::new(0, 100), MidiRange::new(0, 127)).with_curve(curve);
+            assert_eq!([0, 50, 100].map(|value| slider.sysex_value_as_cc_value(value)), [0, cc_value, 127], "{}", what);
+        }
+        // Between the points of a table it is linear
+        let table = Curve::Table(vec![0.0, 0.1, 1.0]);
+        assert_near(table.apply(0.25), 0.05, "table");
+        assert_near(table.apply(0.75), 0.55, "table");
+        // A flat part inverts to its start
+        let flat = Curve::Table(vec![0.0, 0.5, 0.5, 1.0]);
+        assert_near(flat.invert(0.5), 1.0 / 3.0, "flat table");
+    }
+
+    // Checks a scaling of every value of `from` onto 0-127: the ends land on
+    // the ends, it never goes back down and never skips more than it has to,
+    // and each value is the nearest one to the exact position
+    fn check_scaling(from: &MidiRange, scale: impl Fn(MidiValue) -> MidiValue) {
+        let max_step = 127_f64 / from.width() as f64;
+        let values: Vec<MidiValue> = (0..=from.hi).map(&scale).collect();
+        assert_eq!((values[0], values[from.hi as usize]), (0, 127), "{:?}", from);
+        for (value, pair) in values.windows(2).enumerate() {
+            let step = pair[1] - pair[0];
+            assert!(step >= 1 && step as f64 <= max_step.ceil(), "{:?}: {} to {} is a step of {}", from, value, value + 1, step);
+        }
+        for (value, scaled) in values.iter().enumerate() {
+            let exact = value as f64 * max_step;
+            assert!((*scaled as f64 - exact).abs() <= 0.5, "{:?}: {} is {} instead of about {}", from, value, scaled, exact);
+        }
+    }
+
+    #[test]
+    fn scales_every_value_to_the_nearest() {
+        let cc = MidiRange::new(0, 127);
+        for from in [MidiRange::new(0, 100), MidiRange::new(0, 0x32)] {
+            check_scaling(&from, |value| cc.relative_to_absolute(from.absolute_to_relative(value)));
+            check_scaling(&from, |value| from.spread_in_other_range(value, &cc));
+            // The two come to the same, and spread comes back exactly
+            for value in 0..=from.hi {
+                assert_eq!(from.spread_in_other_range(value, &cc), cc.relative_to_absolute(from.absolute_to_relative(value)));
+                assert_eq!(from.spread_from_other_range(from.spread_in_other_range(value, &cc), &cc), value);
+            }
+            // Onto an inverted range it is all the same, from the other end
+            for value in 0..=from.hi {
+                assert_eq!(from.spread_in_other_range(value, &cc.inverted()), 127 - from.spread_in_other_range(value, &cc));
+            }
+        }
+        // The biggest steps there are: 0-100 onto 0-127 only ever steps by
+        // 1 or 2, 0-50 by 2 or 3
+        let steps = |from: MidiRange| {
+            let values: Vec<MidiValue> = (0..=from.hi).map(|value| from.spread_in_other_range(value, &cc)).collect();
+            let steps: HashSet<MidiValue> = values.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            let mut steps: Vec<MidiValue> = steps.into_iter().collect();
+            steps.sort();
+            steps
+        };
+        assert_eq!(steps(MidiRange::new(0, 100)), [1, 2]);
+        assert_eq!(steps(MidiRange::new(0, 0x32)), [2, 3]);
+        // The relative positions themselves are clamped at the ends
+        assert_eq!([-1.0, 0.0, 0.5, 1.0, 2.0].map(|relative| cc.relative_to_absolute(relative)), [0, 0, 64, 127, 127]);
+    }
+
+    #[test]
+    fn maps_the_sliders_onto_their_ccs() {
+        // The built-in mapping sends on channel 2, Chorus Rate on CC 85 and
+        // P-ENV T1 on CC 104
+        let (mut mapper, sent) = mapper(Config::default());
+        for message in [CHORUS_RATE_0, CHORUS_RATE_50, CHORUS_RATE_100, T1_25] {
+            mapper.map(&message);
+        }
+        assert_eq!(sent.take(), [[0xb1, 85, 0], [0xb1, 85, 64], [0xb1, 85, 127], [0xb1, 104, 64]]);
+    }
+
+    #[test]
+    fn maps_the_partials_onto_nrpns() {
+        // Numbered by their address: Upper Partial 2 TVF Cutoff Frequency is
+        // 0x00004D, its Resonance (0-30, stepped) 0x00004E
+        let (mut mapper, sent) = mapper(Config::default());
+        mapper.map(&Pg1000SysExMessage::new(0x00004d, 50).to_bytes());
+        mapper.map(&Pg1000SysExMessage::new(0x00004e, 15).to_bytes());
+        mapper.map(&Pg1000SysExMessage::new(0x00004e, 30).to_bytes());
+        let nrpn = |number: u8, value: u8| [[0xb1, 0x63, 0], [0xb1, 0x62, number], [0xb1, 0x06, value]];
+        // The same parameter again only needs the data entry
+        assert_eq!(sent.take(), [&nrpn(0x4d, 64)[..], &nrpn(0x4e, 64), &[[0xb1, 0x06, 127]]].concat());
+    }
+
+    const PICKUP: &str = "sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 100]\n    pickup: true\n";
+
+    fn chorus_rate(value: MidiValue) -> Vec<u8> {
+        Pg1000SysExMessage::new(0x00012b, value).to_bytes()
+    }
+
+    #[test]
+    fn picks_up_at_once_on_the_last_value() {
+        let (mut mapper, sent) = mapper(Config::parse(PICKUP).unwrap());
+        mapper.map(&chorus_rate(50));
+        mapper.map(&[0xc0, 5]);
+        // Already where it was, so it takes over with the first value
+        mapper.map(&chorus_rate(50));
+        mapper.map(&chorus_rate(51));
+        assert_eq!(sent.take(), [&[0xb1, 85, 64][..], &[0xc0, 5], &[0xb1, 85, 64], &[0xb1, 85, 65]]);
+    }
+
+    #[test]
+    fn picks_up_once_across_the_last_value() {
+        let (mut mapper, sent) = mapper(Config::parse(PICKUP).unwrap());
+        mapper.map(&chorus_rate(50));
+        mapper.map(&[0xc0, 5]);
+        for value in [80, 70, 60, 40, 30] {
+            mapper.map(&chorus_rate(value));
+        }
+        assert_eq!(sent.take(), [&[0xb1, 85, 64][..], &[0xc0, 5], &[0xb1, 85, 51], &[0xb1, 85, 38]]);
+    }
+
+    #[test]
+    fn counts_send_errors_and_carries_on() {
+        logging::set_level(logging::Level::Error);
+        let recorder = Recorder::default();
+        let outputs: Vec<(String, Box<dyn MidiSink>)> = vec![("gone".to_string(), Box::new(Failing)), ("test".to_string(), Box::new(recorder.clone()))];
+        let mut mapper = Mapper::new(Config::default(), outputs).unwrap();
+        for value in [0, 50, 100] {
+            mapper.map(&chorus_rate(value));
+        }
+        mapper.map(&[0x90, 60, 100]);
+        assert_eq!(recorder.take(), [&[0xb1, 85, 0][..], &[0xb1, 85, 64], &[0xb1, 85, 127], &[0x90, 60, 100]]);
+        assert_eq!(mapper.send_errors(), 4);
+        let stats: Vec<(&str, u64, u64)> = mapper.output_stats().map(|(name, stats)| (name, stats.sent, stats.errors)).collect();
+        assert_eq!(stats, [("gone", 0, 4), ("test", 4, 0)]);
+    }
+
+    #[test]
+    fn tells_the_main_thread_once_all_outputs_fail() {
+        logging::set_level(logging::Level::Error);
+        let mut mapper = Mapper::new(Config::default(), vec![("gone".to_string(), Box::new(Failing))]).unwrap();
+        let (events, received) = std::sync::mpsc::channel();
+        mapper.set_events(events, false);
+        for value in 0..Mapper::MAX_CONSECUTIVE_SEND_ERRORS as MidiValue - 1 {
+            mapper.map(&chorus_rate(value * 5));
+        }
+        assert!(received.try_recv().is_err());
+        mapper.map(&chorus_rate(100));
+        assert!(matches!(received.try_recv(), Ok(status::Event::OutputFailing(_))));
+        // Once only, mapping carries on regardless
+        mapper.map(&chorus_rate(99));
+        assert!(received.try_recv().is_err());
+        assert_eq!(mapper.send_errors(), Mapper::MAX_CONSECUTIVE_SEND_ERRORS as u64 + 1);
+    }
+
+    // Chorus Rate to the first output only, Chorus Depth to all of them
+    const ROUTED: &str = "sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 100]\n    outputs: [1]\n  - sysex_id: 0x00012C\n    cc_id: 86\n    sysex_range: [0, 100]\n";
+
+    fn chorus_depth(value: MidiValue) -> Vec<u8> {
+        Pg1000SysExMessage::new(0x00012c, value).to_bytes()
+    }
+
+    #[test]
+    fn adds_and_removes_outputs_while_forwarding() {
+        let (mut routed, daw) = mapper(Config::parse(ROUTED).unwrap());
+        routed.map(&chorus_rate(50));
+        routed.map(&chorus_depth(100));
+        assert_eq!(daw.take(), [[0xb1, 85, 64], [0xb1, 86, 127]]);
+        // Started out with what isn't routed elsewhere
+        let synth = Recorder::default();
+        routed.add_output("synth".to_string(), Box::new(synth.clone()), true).unwrap();
+        assert_eq!(synth.take(), [[0xb1, 86, 127]]);
+        assert!(daw.take().is_empty());
+        assert!(routed.add_output("synth".to_string(), Box::new(Recorder::default()), false).is_err());
+        routed.map(&chorus_rate(0));
+        routed.map(&chorus_depth(0));
+        assert_eq!(daw.take(), [[0xb1, 85, 0], [0xb1, 86, 0]]);
+        assert_eq!(synth.take(), [[0xb1, 86, 0]]);
+        // Output 1 stays output 1, and goes nowhere
+        routed.remove_output("test").unwrap();
+        assert!(routed.remove_output("test").is_err());
+        routed.map(&chorus_rate(100));
+        routed.map(&chorus_depth(50));
+        assert!(daw.take().is_empty());
+        assert_eq!(synth.take(), [[0xb1, 86, 64]]);
+        let stats: Vec<(&str, u64)> = routed.output_stats().map(|(name, stats)| (name, stats.sent)).collect();
+        assert_eq!(stats, [("synth", 3)]);
+        // A new one with the old name comes after the others
+        let again = Recorder::default();
+        routed.add_output("test".to_string(), Box::new(again.clone()), false).unwrap();
+        routed.map(&chorus_rate(0));
+        assert!(again.take().is_empty());
+        routed.map(&chorus_depth(0));
+        assert_eq!(again.take(), [[0xb1, 86, 0]]);
+        while routed.outputs.len() < Config::MAX_OUTPUTS {
+            routed.add_output(format!("output {}", routed.outputs.len() + 1), Box::new(Recorder::default()), false).unwrap();
+        }
+        assert!(routed.add_output("one more".to_string(), Box::new(Recorder::default()), false).is_err());
+    }
+
+    #[test]
+    fn sends_what_is_held_back_to_the_outputs_there_are_by_then() {
+        let mut config = Config::parse(ROUTED).unwrap();
+        config.rate_limit = Some(Duration::from_millis(20));
+        let (mut held, daw) = mapper(config);
+        let clock = clocked(&mut held);
+        held.map(&chorus_depth(0));
+        held.map(&chorus_depth(100));
+        assert_eq!(daw.take(), [[0xb1, 86, 0]]);
+        // Added and removed while a value is held back
+        let synth = Recorder::default();
+        held.add_output("synth".to_string(), Box::new(synth.clone()), true).unwrap();
+        assert_eq!(synth.take(), [[0xb1, 86, 0]]);
+        held.remove_output("test").unwrap();
+        clock.advance(Duration::from_millis(20));
+        held.tick();
+        assert!(daw.take().is_empty());
+        assert_eq!(synth.take(), [[0xb1, 86, 127]]);
+    }
+
+    #[test]
+    fn tells_addresses_apart_by_their_high_byte() {
+        // 00 01 2B and 01 01 2B used to be the same 0x012B
+        let high = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x01, 0x01, 0x2b, 0x32, 0x21, 0xf7];
+        assert_eq!(Pg1000SysExMessage::from_bytes(&high).map(|sysex| sysex.id()), Ok(0x01012b));
+        let config = "sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 100]\n  - sysex_id: 0x01012B\n    cc_id: 86\n    sysex_range: [0, 100]\n";
+        let (mut both, sent) = mapper(Config::parse(config).unwrap());
+        both.map(&CHORUS_RATE_50);
+        both.map(&high);
+        assert_eq!(sent.take(), [[0xb1, 85, 64], [0xb1, 86, 64]]);
+        // Not Chorus Rate with the built-in mapping
+        let (mut builtin, sent) = mapper(Config::default());
+        builtin.map(&high);
+        assert_eq!(sent.take(), [high]);
+    }
+
+    #[test]
+    fn brings_values_outside_the_sysex_range_into_it() {
+        // Just below, just above, far below and far above, then the ends
+        let values = [9, 101, 0, 127, 10, 100];
+        for (range, expected) in [("[10, 100]", [0, 127, 0, 127, 0, 127]), ("[100, 10]", [127, 0, 127, 0, 127, 0])] {
+            for out_of_range in ["warn", "clamp", "drop"] {
+                let config = format!("out_of_range: {}\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: {}\n", out_of_range, range);
+                let (mut mapper, sent) = mapper(Config::parse(&config).unwrap());
+                for value in values {
+                    mapper.invalidate_sent(None);
+                    mapper.map(&chorus_rate(value));
+                }
+                let sent: Vec<u8> = sent.take().iter().map(|cc| cc[2]).collect();
+                // Dropping leaves only the ends
+                let expected = if out_of_range == "drop" { &expected[4..] } else { &expected[..] };
+                assert_eq!(sent, expected, "{} {}", range, out_of_range);
+                assert_eq!(mapper.stats.out_of_range, 4, "{} {}", range, out_of_range);
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_relative_changes() {
+        let changes = [1, 63, -1, -63, 0, 64, -64, 100, -100, 127, -127];
+        let twos_complement: [&[MidiValue]; 11] =
+            [&[1], &[63], &[127], &[65], &[], &[63, 1], &[65, 127], &[63, 37], &[65, 91], &[63, 63, 1], &[65, 65, 127]];
+        let offset: [&[MidiValue]; 11] =
+            [&[65], &[127], &[63], &[1], &[], &[127, 65], &[1, 63], &[127, 101], &[1, 27], &[127, 127, 65], &[1, 1, 63]];
+        for (relative, expected) in [(Relative::TwosComplement, twos_complement), (Relative::Offset, offset)] {
+            for (change, expected) in changes.iter().zip(expected) {
+                assert_eq!(relative.encode(*change), expected, "{:?} {}", relative, change);
+            }
+        }
+    }
+
+    #[test]
+    fn sends_the_changes_made_while_muted_after_unmuting() {
+        for (relative, down) in [("twos_complement", 128 - 30), ("offset", 64 - 30)] {
+            let config = format!("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 127]\n    relative: {}\n", relative);
+            let (mut mapper, sent) = mapper(Config::parse(&config).unwrap());
+            let (osc, packets) = osc::OscSender::queue();
+            mapper.set_osc(osc);
+            // The first move only sets where the changes are counted from
+            mapper.map(&chorus_rate(50));
+            assert_eq!(packets.try_iter().count(), 1, "{}", relative);
+            mapper.set_muted(true);
+            for value in [60, 70, 40] {
+                mapper.map(&chorus_rate(value));
+            }
+            assert!(sent.take().is_empty(), "{}", relative);
+            // Nor over OSC
+            assert_eq!(packets.try_iter().count(), 0, "{}", relative);
+            mapper.set_muted(false);
+            // 30 down from where the host was left
+            mapper.map(&chorus_rate(20));
+            assert_eq!(sent.take(), [[0xb1, 85, down]], "{}", relative);
+            assert_eq!(packets.try_iter().collect::<Vec<_>>(), [osc::message("/pg1000/upper/common/chorus_rate", 20.0 / 127.0)], "{}", relative);
+        }
+    }
+
+    const HYSTERESIS: &str = "sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 127]\n    hysteresis: 2\n";
+
+    #[test]
+    fn leaves_out_the_jitter_of_a_worn_slider() {
+        let (mut mapper, sent) = mapper(Config::parse(HYSTERESIS).unwrap());
+        for value in [50, 51, 49, 50, 51, 50, 49] {
+            mapper.map(&chorus_rate(value));
+        }
+        assert_eq!(sent.take(), [[0xb1, 85, 50]]);
+        // A real move gets through
+        mapper.map(&chorus_rate(52));
+        assert_eq!(sent.take(), [[0xb1, 85, 52]]);
+    }
+
+    #[test]
+    fn reaches_the_ends_moving_one_step_at_a_time() {
+        let (mut mapper, sent) = mapper(Config::parse(HYSTERESIS).unwrap());
+        for value in (0..=127).chain((0..127).rev()) {
+            mapper.map(&chorus_rate(value));
+        }
+        let sent: Vec<u8> = sent.take().iter().map(|cc| cc[2]).collect();
+        let up: Vec<u8> = (0..=126).step_by(2).chain([127]).collect();
+        let down: Vec<u8> = (1..=125).rev().step_by(2).chain([0]).collect();
+        assert_eq!(sent, [up, down].concat());
+    }
+
+    #[test]
+    fn sends_a_repeated_value_once() {
+        let (mut mapper, sent) = mapper(Config::default());
+        mapper.map(&CHORUS_RATE_50);
+        mapper.map(&CHORUS_RATE_50);
+        assert_eq!(sent.take(), [[0xb1, 85, 64]]);
+        assert_eq!(mapper.suppressed_duplicates(), 1);
+        assert!(mapper.stats_report().contains("1 duplicates suppressed"), "{}", mapper.stats_report());
+        mapper.reset_stats();
+        assert_eq!(mapper.suppressed_duplicates(), 0);
+    }
+
+    #[test]
+    fn counts_what_the_filter_drops_in_the_stats() {
+        let (mut mapper, sent) = mapper(Config::parse("filter: [realtime, notes]\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n").unwrap());
+        for message in [&[0xf8][..], &[0xf8], &[0xfe], &[0x90, 60, 100], &[0xb0, 7, 100]] {
+            mapper.map(message);
+        }
+        assert_eq!(sent.take(), [[0xb0, 7, 100]]);
+        assert!(mapper.stats_report().contains("Filtered: 1 notes, 2 clock, 1 activesense\n"), "{}", mapper.stats_report());
+        mapper.reset_stats();
+        assert!(mapper.filtered().is_empty());
+        assert!(!mapper.stats_report().contains("Filtered"));
+    }
+
+    #[test]
+    fn passes_everything_else_through_as_it_is() {
+        let (mut mapper, sent) = mapper(Config::default());
+        let messages: [&[u8]; 5] = [
+            // A note on and off from a keyboard on the same input
+            &[0x90, 60, 100],
+            &[0x80, 60, 0],
+            // MIDI clock
+            &[0xf8],
+            // The Lower tone's Chorus Rate, left to the synth with upper_lower: separate
+            &[0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x02, 0x6b, 0x05, 0x0e, 0xf7],
+            // Another maker's SysEx, as long as a DT1
+            &[0xf0, 0x43, 0x10, 0x4c, 0x02, 0x01, 0x00, 0x10, 0x00, 0x11, 0xf7],
+        ];
+        for message in messages {
+            mapper.map(message);
+        }
+        assert_eq!(sent.take(), messages);
+    }
+
+    #[test]
+    fn reassembles_sysex_split_anywhere() {
+        let whole = {
+            let (mut mapper, sent) = mapper(Config::default());
+            mapper.map(&[0xf8]);
+            mapper.map(&CHORUS_RATE_50);
+            sent.take()
+        };
+        assert_eq!(whole, [&[0xf8][..], &[0xb1, 85, 64]]);
+        for split in 1..CHORUS_RATE_50.len() {
+            let (first, second) = CHORUS_RATE_50.split_at(split);
+            // MIDI clock in the middle, at the end of the first piece and at
+            // the start of the second one
+            for (first, second) in [([first, &[0xf8]].concat(), second.to_vec()), (first.to_vec(), [&[0xf8], second].concat())] {
+                let (mut mapper, sent) = mapper(Config::default());
+                mapper.map_from(0, None, &first);
+                mapper.map_from(0, None, &second);
+                assert_eq!(sent.take(), whole, "split after {} bytes", split);
+            }
+        }
+        // One byte at a time
+        let (mut mapper, sent) = mapper(Config::default());
+        mapper.map(&[0xf8]);
+        for byte in CHORUS_RATE_50 {
+            mapper.map(&[byte]);
+        }
+        assert_eq!(sent.take(), whole);
+    }
+
+    #[test]
+    fn keeps_the_sysex_of_each_input_apart() {
+        let (mut mapper, sent) = mapper(Config::default());
+        mapper.set_inputs(vec!["pg1000".to_string(), "other".to_string()]);
+        let (first, second) = CHORUS_RATE_50.split_at(6);
+        mapper.map_from(0, None, first);
+        mapper.map_from(1, None, &[0x90, 60, 100]);
+        mapper.map_from(0, None, second);
+        assert_eq!(sent.take(), [[0x90, 60, 100], [0xb1, 85, 64]]);
+    }
+
+    #[test]
+    fn computes_the_virtual_params_from_their_sliders() {
+        let config = Config::parse(concat!(
+            "virtual_params:\n",
+            "  - name: brightness\n    cc_id: 70\n    formula: \"0.5 * rate + 0.5 * depth\"\n    defaults:\n      depth: 20\n",
+            "  - name: half\n    cc_id: 71\n    channel: 5\n    formula: \"brightness / 2\"\n",
+            "sliders:\n",
+            "  - sysex_id: 0x00012B\n    name: rate\n    cc_id: 85\n",
+            "  - sysex_id: 0x00012C\n    name: depth\n    cc_id: 86\n",
+            "  - sysex_id: 0x000116\n    cc_id: 87\n",
+        ))
+        .unwrap();
+        let (mut computing, sent) = mapper(config);
+        let sysex = |id: SysExId, value| Pg1000SysExMessage::new(id, value).to_bytes();
+        // Depth not moved yet, at its default: 25 + 10 percent, and half that
+        computing.map(&sysex(0x00012b, 50));
+        assert_eq!(sent.take(), [[0xb1, 70, 44], [0xb4, 71, 22], [0xb1, 85, 64]]);
+        // Only what changes is sent, and a slider they don't use changes nothing
+        computing.map(&sysex(0x00012c, 20));
+        computing.map(&sysex(0x000116, 50));
+        assert_eq!(sent.take(), [[0xb1, 86, 25], [0xb1, 87, 64]]);
+        computing.map(&sysex(0x00012c, 100));
+        assert_eq!(sent.take(), [[0xb1, 70, 95], [0xb4, 71, 48], [0xb1, 86, 127]]);
+        // Changed while muted, they are sent on a resend, which after
+        // unmuting has no sliders to send
+        computing.set_muted(true);
+        computing.map(&sysex(0x00012c, 0));
+        computing.set_muted(false);
+        assert!(sent.take().is_empty());
+        computing.resend();
+        assert_eq!(sent.take(), [[0xb1, 70, 32], [0xb4, 71, 16]]);
+        // A profile's own sliders have none
+        let config = Config::parse("virtual_params:\n  - name: v\n    cc_id: 70\n    formula: \"rate\"\nsliders:\n  - sysex_id: 0x00012B\n    name: rate\n    cc_id: 85\nprofiles:\n  \"other\":\n    sliders:\n      - sysex_id: 0x00012B\n        name: rate\n        cc_id: 85\n").unwrap();
+        let (mut profiled, sent) = mapper(config);
+        profiled.select_profile("other").unwrap();
+        profiled.map(&sysex(0x00012b, 100));
+        assert_eq!(sent.take(), [[0xb1, 85, 127]]);
+    }
+
+    #[test]
+    fn transforms_the_notes_of_their_input_only() {
+        let keyboard = |transpose: &str| {
+            let yaml = format!("note_transforms:\n  - input: 2\n    transpose: {}\n    velocity_curve: fixed(100)\n    note_range: [36, 96]\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n", transpose);
+            Config::parse(&yaml).unwrap()
+        };
+        let (mut mapper, sent) = mapper(keyboard("+12"));
+        mapper.set_inputs(vec!["pg1000".to_string(), "keyboard".to_string()]);
+        mapper.map_from(1, None, &[0x90, 60, 30]);
+        mapper.map_from(1, None, &[0x90, 30, 30]);
+        mapper.map_from(0, None, &[0x90, 60, 30]);
+        mapper.map_from(1, None, &CHORUS_RATE_50);
+        assert_eq!(sent.take(), [&[0x90, 72, 100][..], &[0x90, 60, 30], &[0xb1, 85, 64]]);
+        // Reloaded with another transposition while the notes are held,
+        // they are let go of as they were sent
+        mapper.reload(keyboard("-12")).unwrap();
+        mapper.map_from(1, None, &[0x80, 60, 0]);
+        mapper.map_from(1, None, &[0x80, 30, 0]);
+        mapper.map_from(0, None, &[0x80, 60, 0]);
+        mapper.map_from(1, None, &[0x90, 60, 30]);
+        assert_eq!(sent.take(), [[0x80, 72, 0], [0x80, 60, 0], [0x90, 48, 100]]);
+    }
+
+    #[test]
+    fn maps_the_ipr_of_a_pg300() {
+        let config = Config::parse("device: pg300\nchannel: 1\nsliders:\n  - sysex_id: 0x10\n    cc_id: 74\n    sysex_range: [0, 127]\n").unwrap();
+        let (mut mapper, sent) = mapper(config);
+        // VCF cutoff, as an Alpha Juno on channel 3 passes it on
+        mapper.map(&[0xf0, 0x41, 0x36, 0x02, 0x23, 0x20, 0x01, 0x10, 0x7f, 0xf7]);
+        // A PG-1000 DT1 is just SysEx here
+        mapper.map(&CHORUS_RATE_50);
+        assert_eq!(sent.take(), [&[0xb0, 74, 127][..], &CHORUS_RATE_50]);
+    }
+
+    #[test]
+    fn drops_corrupted_messages() {
+        let (mut mapper, sent) = mapper(Config::default());
+        let mut corrupted = CHORUS_RATE_50;
+        corrupted[9] ^= 0x01;
+        mapper.map(&corrupted);
+        assert!(sent.take().is_empty());
+        mapper.map(&CHORUS_RATE_50);
+        assert_eq!(sent.take(), [[0xb1, 85, 64]]);
+    }
+
+    const INTERLOCK: &str = "sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    sysex_range: [0, 127]\n    max_step: 4\n";
+
+    fn sent_values(sent: &Recorder) -> Vec<MidiValue> {
+        sent.take().iter().map(|cc| cc[2] as MidiValue).collect()
+    }
+
+    #[test]
+    fn never_jumps_more_than_the_max_step() {
+        let (mut mapper, sent) = mapper(Config::parse(INTERLOCK).unwrap());
+        // A burst of values all over the place, faster than it can follow
+        let mut seed = 1u32;
+        for _ in 0..200 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            mapper.map(&chorus_rate((seed >> 16) as MidiValue % 128));
+        }
+        let mut values = sent_values(&sent);
+        // Neither a resend nor unmuting gets around it
+        mapper.resend();
+        mapper.set_muted(true);
+        mapper.map(&chorus_rate(0));
+        mapper.set_muted(false);
+        mapper.map(&chorus_rate(127));
+        for _ in 0..100 {
+            mapper.tick();
+        }
+        values.extend(sent_values(&sent));
+        for pair in values.windows(2) {
+            assert!(pair[0].abs_diff(pair[1]) <= 4, "{:?}", pair);
+        }
+        assert_eq!(values.last(), Some(&127));
+        assert_eq!(mapper.tick_interval(), None);
+    }
+
+    #[test]
+    fn moves_at_most_max_steps_per_second() {
+        let config = INTERLOCK.replace("max_step: 4", "max_steps_per_second: 2000");
+        let (mut mapper, sent) = mapper(Config::parse(&config).unwrap());
+        let clock = clocked(&mut mapper);
+        mapper.map(&chorus_rate(0));
+        mapper.map(&chorus_rate(100));
+        let mut values = sent_values(&sent);
+        let mut ticks = 0;
+        while values.last() != Some(&100) && ticks < 1000 {
+            clock.advance(Duration::from_millis(1));
+            mapper.tick();
+            values.extend(sent_values(&sent));
+            ticks += 1;
+        }
+        // 2 steps a millisecond, 100 steps in 50 ms
+        assert_eq!(ticks, 50);
+        assert_eq!(values, (0..=100).step_by(2).collect::<Vec<MidiValue>>());
+    }
+
+    // Chorus Rate and Depth lighting the same pad, P-ENV T1 another one
+    const FEEDBACK: &str = "feedback:\n  hold_ms: 20\nsliders:\n  \
+        - sysex_id: 0x00012B\n    cc_id: 85\n    feedback: 36\n  \
+        - sysex_id: 0x00012C\n    cc_id: 86\n    feedback: 36\n  \
+        - sysex_id: 0x00010D\n    cc_id: 87\n    sysex_range: [0, 50]\n    feedback: 37\n";
+
+    fn is_feedback(message: &[u8]) -> bool {
+        message[0] & 0x0f == 15
+    }
+
+    #[test]
+    fn lights_the_feedback_once_and_takes_it_back_once() {
+        let (mut mapper, sent) = mapper(Config::parse(FEEDBACK).unwrap());
+        let clock = clocked(&mut mapper);
+        let mut all = vec![];
+        for round in 0..3 {
+            // All three sliders at once, faster than the hold
+            for value in 0..30 {
+                for id in [0x00012b, 0x00012c, 0x00010d] {
+                    mapper.map(&Pg1000SysExMessage::new(id, value + round).to_bytes());
+                }
+                mapper.tick();
+            }
+            let moved: Vec<Vec<u8>> = sent.take().into_iter().filter(|message| is_feedback(message)).collect();
+            assert_eq!(moved, [[0x9f, 36, 127], [0x9f, 37, 127]], "round {}", round);
+            assert!(mapper.tick_interval().is_some());
+            clock.advance(Duration::from_millis(20));
+            mapper.tick();
+            let mut rested = sent.take();
+            rested.sort();
+            assert_eq!(rested, [[0x8f, 36, 0], [0x8f, 37, 0]], "round {}", round);
+            assert_eq!(mapper.tick_interval(), None);
+            all.extend(moved.into_iter().chain(rested));
+        }
+        // Every pad went on and off in turn
+        for pad in [36, 37] {
+            let states: Vec<u8> = all.iter().filter(|message| message[1] == pad).map(|message| message[0]).collect();
+            assert_eq!(states, [0x9f, 0x8f].repeat(3), "pad {}", pad);
+        }
+        assert_eq!(mapper.stats.feedback, 12);
+    }
+
+    #[test]
+    fn records_at_the_timestamps_of_the_inputs() {
+        let (mut stamped, _) = mapper(Config::default());
+        let clock = clocked(&mut stamped);
+        stamped.set_inputs(vec!["pg1000".to_string(), "keyboard".to_string()]);
+        stamped.start_recording(true);
+        let rate = |value| Pg1000SysExMessage::new(0x00012b, value).to_bytes();
+        stamped.map_from(0, Some(5_000_000), &rate(10));
+        // Held up on the way by 300 ms, it is recorded 20 ms after the first
+        clock.advance(Duration::from_millis(300));
+        stamped.map_from(0, Some(5_020_000), &rate(20));
+        // Another input's timestamps count from a start of their own
+        stamped.map_from(1, Some(100), &[0x90, 60, 100]);
+        clock.advance(Duration::from_millis(100));
+        stamped.map_from(1, None, &[0x80, 60, 0]);
+        // And so do those of a reconnected input
+        stamped.input_reconnected(0);
+        clock.advance(Duration::from_millis(100));
+        stamped.map_from(0, Some(9_000_000), &rate(30));
+        let recording = stamped.take_recording().unwrap();
+        let times: Vec<u128> = smf::read(&recording.to_smf()).unwrap().iter().map(|(time, _)| (time.as_secs_f64() * 1000.0).round() as u128).collect();
+        assert_eq!(times, [0, 20, 300, 400, 500]);
+    }
+
+    #[test]
+    fn keeps_the_feedback_out_of_the_recording_and_the_ccs_sent() {
+        let (mut mapper, sent) = mapper(Config::parse(FEEDBACK).unwrap());
+        mapper.start_recording(false);
+        mapper.map(&CHORUS_RATE_50);
+        mapper.map(&T1_25);
+        // Flushing, e.g. on exit, leaves nothing lit
+        mapper.flush();
+        let messages = sent.take();
+        assert_eq!(messages.iter().filter(|message| is_feedback(message)).count(), 4);
+        assert_eq!(mapper.take_recording().map(|recording| recording.len()), Some(2));
+        assert_eq!(mapper.cc_events, 2);
+        assert!(mapper.stats_report().contains("4 feedback"));
+        // Nothing moves while muted, so nothing lights up
+        mapper.set_muted(true);
+        mapper.map(&CHORUS_RATE_0);
+        assert!(sent.take().is_empty());
+    }
+
+    #[test]
+    fn takes_the_feedback_back_before_it_changes() {
+        let (mut mapper, sent) = mapper(Config::parse(FEEDBACK).unwrap());
+        mapper.map(&CHORUS_RATE_50);
+        sent.take();
+        let cc = FEEDBACK.replace("feedback:\n", "feedback:\n  message: cc\n");
+        mapper.reload(Config::parse(&cc).unwrap()).unwrap();
+        mapper.map(&CHORUS_RATE_100);
+        assert_eq!(sent.take(), [[0x8f, 36, 0], [0xb1, 85, 127], [0xbf, 36, 127]]);
+    }
+
+    // The second output is the one recording, the first one is gone
+    const STARTUP: &str = "startup_sequence:\n  \
+        - sysex: \"F0 41 10 16 12 7F 00 00 01 00 F7\"\n    outputs: [2]\n  \
+        - program: 4\n    outputs: [1]\n    on_failure: continue\n  \
+        - delay_ms: 20\n  \
+        - cc: 7\n    value: 100\n\
+        sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n";
+    const WAKE_UP: [u8; 11] = [0xf0, 0x41, 0x10, 0x16, 0x12, 0x7f, 0x00, 0x00, 0x01, 0x00, 0xf7];
+
+    fn startup_mapper(source: &str) -> (Mapper, Recorder, ManualClock) {
+        logging::set_level(logging::Level::Error);
+        let recorder = Recorder::default();
+        let outputs: Vec<(String, Box<dyn MidiSink>)> = vec![("gone".to_string(), Box::new(Failing)), ("test".to_string(), Box::new(recorder.clone()))];
+        let mut mapper = Mapper::new(Config::parse(source).unwrap(), outputs).unwrap();
+        let clock = clocked(&mut mapper);
+        (mapper, recorder, clock)
+    }
+
+    /// Sends the startup sequence through to the end, delays and all.
+    fn finish_sequence(mapper: &mut Mapper, clock: &ManualClock) {
+        for _ in 0..100 {
+            if !mapper.sequence_running() {
+                return;
+            }
+            clock.advance(mapper.tick_interval().unwrap());
+            mapper.tick();
+        }
+        panic!("the startup sequence never ended");
+    }
+
+    #[test]
+    fn sends_the_startup_sequence_again_after_a_reconnect() {
+        let (mut mapper, sent, clock) = startup_mapper(STARTUP);
+        assert!(mapper.start_sequence());
+        // Up to the delay; the program change reaches no output, and
+        // carries on
+        assert_eq!(sent.take(), [WAKE_UP.to_vec()]);
+        assert!(mapper.sequence_running());
+        mapper.tick();
+        assert!(sent.take().is_empty());
+        finish_sequence(&mut mapper, &clock);
+        assert_eq!(sent.take(), [[0xb1, 7, 100]]);
+        // None of it counts as a value sent, and a slider value isn't held
+        // back by it
+        mapper.map(&CHORUS_RATE_100);
+        assert_eq!(sent.take(), [[0xb1, 85, 127]]);
+        // A reconnect starts over, even while muted, and the slider's value
+        // is still known to have been sent
+        mapper.set_muted(true);
+        mapper.input_reconnected(0);
+        finish_sequence(&mut mapper, &clock);
+        assert_eq!(sent.take(), [WAKE_UP.to_vec(), vec![0xb1, 7, 100]]);
+        mapper.set_muted(false);
+        mapper.map(&CHORUS_RATE_100);
+        assert_eq!(sent.take(), [[0xb1, 85, 127]]);
+        // ...as does one halfway through
+        mapper.input_reconnected(0);
+        mapper.input_reconnected(0);
+        finish_sequence(&mut mapper, &clock);
+        assert_eq!(sent.take(), [WAKE_UP.to_vec(), WAKE_UP.to_vec(), vec![0xb1, 7, 100]]);
+    }
+
+    #[test]
+    fn gives_up_on_the_startup_sequence_when_an_entry_fails() {
+        let (mut without, sent) = mapper(Config::default());
+        assert!(!without.start_sequence());
+        without.input_reconnected(0);
+        assert!(sent.take().is_empty());
+        let abort = STARTUP.replace("    on_failure: continue\n", "");
+        let (mut mapper, sent, _) = startup_mapper(&abort);
+        mapper.input_reconnected(0);
+        assert!(!mapper.sequence_running());
+        assert_eq!(sent.take(), [WAKE_UP.to_vec()]);
+        // A reload with another sequence leaves the old one behind
+        let (mut mapper, sent, clock) = startup_mapper(STARTUP);
+        mapper.start_sequence();
+        mapper.reload(Config::parse(&STARTUP.replace("value: 100", "value: 90")).unwrap()).unwrap();
+        assert!(!mapper.sequence_running());
+        mapper.start_sequence();
+        finish_sequence(&mut mapper, &clock);
+        assert_eq!(sent.take(), [WAKE_UP.to_vec(), WAKE_UP.to_vec(), vec![0xb1, 7, 90]]);
+    }
+
+    const SENSING: &str = "active_sensing:\n  outputs: [2]\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n";
+
+    #[test]
+    fn sends_active_sensing_of_its_own_until_the_end() {
+        logging::set_level(logging::Level::Error);
+        let (daw, synth) = (Recorder::default(), Recorder::default());
+        let outputs: Vec<(String, Box<dyn MidiSink>)> = vec![("daw".to_string(), Box::new(daw.clone())), ("synth".to_string(), Box::new(synth.clone()))];
+        let mut mapper = Mapper::new(Config::parse(SENSING).unwrap(), outputs).unwrap();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        mapper.sense(at(0));
+        mapper.sense(at(100));
+        mapper.sense(at(249));
+        assert_eq!(synth.take(), [[0xfe]]);
+        mapper.sense(at(250));
+        mapper.sense(at(600));
+        assert_eq!(synth.take(), [[0xfe], [0xfe]]);
+        // The input's own goes on to the other outputs only, muted or not
+        mapper.set_muted(true);
+        mapper.map(&[0xfe]);
+        assert_eq!(daw.take(), [[0xfe]]);
+        assert!(synth.take().is_empty());
+        mapper.flush();
+        mapper.sense(at(10_000));
+        assert!(synth.take().is_empty());
+        assert!(mapper.tick_interval().is_none());
+    }
+
+    #[test]
+    fn tells_when_an_input_stops_sending_active_sensing() {
+        let (mut mapper, _) = mapper(Config::parse(SENSING.replace("[2]", "[1]").as_str()).unwrap());
+        let clock = clocked(&mut mapper);
+        let (events, received) = std::sync::mpsc::channel();
+        mapper.set_events(events, true);
+        let messages = || received.try_iter().filter_map(|event| match event {
+            status::Event::Message(message) => Some(message),
+            _ => None,
+        }).collect::<Vec<String>>();
+        // Not watched before it has sent any
+        clock.advance(Duration::from_secs(10));
+        mapper.tick();
+        mapper.map(&[0xfe]);
+        clock.advance(Duration::from_millis(100));
+        mapper.tick();
+        assert!(messages().is_empty());
+        assert!(mapper.tick_interval().unwrap() <= Duration::from_millis(350));
+        clock.advance(Duration::from_millis(300));
+        mapper.tick();
+        clock.advance(Duration::from_millis(400));
+        mapper.tick();
+        assert_eq!(messages(), ["'input' stopped sending Active Sensing, check its cable and power"]);
+        mapper.map(&[0xfe]);
+        assert_eq!(messages(), ["'input' is sending Active Sensing again"]);
+        // A reconnected input starts over
+        mapper.input_reconnected(0);
+        clock.advance(Duration::from_secs(10));
+        mapper.tick();
+        assert!(messages().is_empty());
+    }
+
+    /// The messages the mapper had for the user since the last call.
+    fn notices(received: &std::sync::mpsc::Receiver<status::Event>) -> Vec<String> {
+        received
+            .try_iter()
+            .filter_map(|event| match event {
+                status::Event::Message(message) => Some(message),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_to_the_timers_exactly_at_their_thresholds() {
+        let millis = Duration::from_millis;
+        // The rate limit lets a value go once the interval is up
+        let (mut limited, sent) = mapper(Config::parse("rate_limit_ms: 20\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n").unwrap());
+        let clock = clocked(&mut limited);
+        limited.map(&CHORUS_RATE_0);
+        limited.map(&CHORUS_RATE_50);
+        clock.advance(millis(19));
+        limited.tick();
+        assert_eq!(sent.take(), [[0xb1, 85, 0]]);
+        clock.advance(millis(1));
+        limited.tick();
+        assert_eq!(sent.take(), [[0xb1, 85, 64]]);
+        clock.advance(millis(20));
+        limited.map(&CHORUS_RATE_100);
+        assert_eq!(sent.take(), [[0xb1, 85, 127]]);
+        // The feedback goes off once the hold is up
+        let (mut lit, sent) = mapper(Config::parse(FEEDBACK).unwrap());
+        let clock = clocked(&mut lit);
+        lit.map(&CHORUS_RATE_50);
+        clock.advance(millis(19));
+        lit.tick();
+        assert_eq!(sent.take(), [[0xb1, 85, 64], [0x9f, 36, 127]]);
+        clock.advance(millis(1));
+        lit.tick();
+        assert_eq!(sent.take(), [[0x8f, 36, 0]]);
+        // A CC from the DAW is an echo up to the end of the window only
+        let (mut echoed, _) = mapper(Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n").unwrap());
+        let clock = clocked(&mut echoed);
+        echoed.map(&CHORUS_RATE_50);
+        clock.advance(Mapper::ECHO_WINDOW - millis(1));
+        assert_eq!(echoed.reverse_map(&[0xb1, 85, 20]), None);
+        clock.advance(millis(1));
+        assert!(echoed.reverse_map(&[0xb1, 85, 20]).is_some());
+        // The D-50 has until just past the sync timeout to answer
+        let (mut syncing, _) = mapper(Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n").unwrap());
+        let clock = clocked(&mut syncing);
+        let (events, received) = std::sync::mpsc::channel();
+        syncing.set_events(events, true);
+        syncing.start_sync().unwrap();
+        clock.advance(Mapper::SYNC_TIMEOUT);
+        syncing.tick();
+        assert!(notices(&received).is_empty());
+        clock.advance(millis(1));
+        syncing.tick();
+        assert_eq!(notices(&received).len(), 1);
+    }
+
+    #[test]
+    fn runs_the_timers_due_at_once_in_order() {
+        let source = "rate_limit_ms: 20\nactive_sensing:\n  interval_ms: 250\nstartup_sequence:\n  - delay_ms: 250\n  - cc: 7\n    value: 100\n\
+            sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n  - sysex_id: 0x00012C\n    cc_id: 86\n    smoothing_ms: 100\n";
+        let (mut mapper, sent) = mapper(Config::parse(source).unwrap());
+        let clock = clocked(&mut mapper);
+        mapper.start_sequence();
+        mapper.tick();
+        mapper.map(&CHORUS_RATE_0);
+        mapper.map(&CHORUS_RATE_50);
+        mapper.map(&chorus_depth(0));
+        mapper.map(&chorus_depth(100));
+        assert_eq!(sent.take(), [vec![0xfe], vec![0xb1, 85, 0], vec![0xb1, 86, 0]]);
+        // The one due soonest says when to come back
+        assert_eq!(mapper.tick_interval(), Some(Mapper::RAMP_TICK));
+        // All of them at once: the sequence, Active Sensing, the held back
+        // value, then the end of the ramp
+        clock.advance(Duration::from_millis(250));
+        mapper.tick();
+        assert_eq!(sent.take(), [vec![0xb1, 7, 100], vec![0xfe], vec![0xb1, 85, 64], vec![0xb1, 86, 127]]);
+        assert!(!mapper.sequence_running());
+    }
+
+    #[test]
+    fn keeps_the_timers_going_over_a_reload() {
+        let three = "rate_limit_ms: 20\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n  - sysex_id: 0x00012C\n    cc_id: 86\n  - sysex_id: 0x00012D\n    cc_id: 87\n";
+        let two = three.replace("  - sysex_id: 0x00012D\n    cc_id: 87\n", "");
+        let (mut mapper, sent) = mapper(Config::parse(three).unwrap());
+        let clock = clocked(&mut mapper);
+        let (events, received) = std::sync::mpsc::channel();
+        mapper.set_events(events, true);
+        // A value held back, and a sync with one answer in
+        mapper.map(&CHORUS_RATE_0);
+        mapper.map(&CHORUS_RATE_50);
+        mapper.start_sync().unwrap();
+        mapper.map(&chorus_depth(100));
+        assert_eq!(sent.take(), [[0xb1, 85, 0], [0xb1, 86, 127]]);
+        mapper.reload(Config::parse(&two).unwrap()).unwrap();
+        notices(&received);
+        clock.advance(Duration::from_millis(20));
+        mapper.tick();
+        assert_eq!(sent.take(), [[0xb1, 85, 64]]);
+        // The sync only waits for the sliders still mapped
+        clock.advance(Mapper::SYNC_TIMEOUT);
+        mapper.tick();
+        assert_eq!(notices(&received), ["The D-50 only sent 1 of 2 sliders"]);
+        // ...and is done when none of those are left
+        mapper.start_sync().unwrap();
+        mapper.map(&CHORUS_RATE_0);
+        mapper.reload(Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n").unwrap()).unwrap();
+        assert!(notices(&received).contains(&"Synced 1 sliders from the D-50".to_string()));
+        clock.advance(Mapper::SYNC_TIMEOUT * 2);
+        mapper.tick();
+        assert!(notices(&received).is_empty());
+    }
+
+    #[test]
+    fn picks_up_at_the_values_a_loaded_project_sends_back() {
+        // Seven 7-bit CCs, a 14-bit CC, a 14-bit NRPN and a 7-bit one
+        let mut source = String::from("sliders:\n");
+        for i in 0..7 {
+            source += &format!("  - sysex_id: 0x{:06X}\n    cc_id: {}\n    pickup: true\n", 0x100 + i, 20 + i);
+        }
+        source += "  - sysex_id: 0x000107\n    cc_id: 1\n    cc_mode: 14bit\n    pickup: true\n";
+        source += "  - sysex_id: 0x000108\n    nrpn: 300\n    cc_mode: 14bit\n    pickup: true\n";
+        source += "  - sysex_id: 0x000109\n    nrpn: 301\n    pickup: true\n";
+        let (mut mapper, sent) = mapper(Config::parse(&source).unwrap());
+        // What the DAW sends on loading the project: CC 20-26 at 100, CC 1
+        // at half way, NRPN 300 (2, 44) at a quarter and NRPN 301 (2, 45)
+        // at 90
+        let mut feedback: Vec<Vec<u8>> = (0..7).map(|i| vec![0xb1, 20 + i, 100]).collect();
+        feedback.extend([[0xb1, 1, 64], [0xb1, 33, 0], [0xb1, 99, 2], [0xb1, 98, 44], [0xb1, 6, 32], [0xb1, 38, 0]].map(|cc| cc.to_vec()));
+        feedback.extend([[0xb1, 98, 45], [0xb1, 6, 90]].map(|cc| cc.to_vec()));
+        let to_d50: Vec<Vec<u8>> = feedback.iter().filter_map(|message| mapper.reverse_map(message)).collect();
+        let sysex = |id: SysExId, value| Pg1000SysExMessage::new(id, value).to_bytes();
+        let mut expected: Vec<Vec<u8>> = (0..7).map(|i| sysex(0x100 + i, 79)).collect();
+        expected.extend([sysex(0x107, 50), sysex(0x108, 25), sysex(0x109, 71)]);
+        assert_eq!(to_d50, expected);
+        assert!(sent.take().is_empty(), "nothing goes back to the DAW");
+        // Moving each slider up from the bottom, nothing goes out before it
+        // gets to the DAW's value
+        let targets: [(SysExId, u8, MidiValue); 10] =
+            [(0x100, 20, 100), (0x101, 21, 100), (0x102, 22, 100), (0x103, 23, 100), (0x104, 24, 100), (0x105, 25, 100), (0x106, 26, 100), (0x107, 1, 64), (0x108, 6, 32), (0x109, 6, 90)];
+        for (id, cc, at_least) in targets {
+            for value in 0..=100 {
+                mapper.map(&sysex(id, value));
+            }
+            let sent = sent.take();
+            let first = sent.iter().find(|message| message[1] == cc).unwrap_or_else(|| panic!("0x{:06X} never picked up", id));
+            assert!(first[2] as MidiValue >= at_least && first[2] as MidiValue <= at_least + 2, "0x{:06X}: {:X?}", id, sent);
+        }
+    }
+
+    #[test]
+    fn tells_its_own_echo_from_the_daw_feedback() {
+        let config = Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    pickup: true\n").unwrap();
+        let (mut mapper, sent) = mapper(config);
+        let clock = clocked(&mut mapper);
+        // The echoes of a quick move come back behind it
+        for value in [10, 20, 30] {
+            mapper.map(&chorus_rate(value));
+        }
+        assert_eq!(sent.take().len(), 3);
+        for value in [13, 25, 38] {
+            assert_eq!(mapper.reverse_map(&[0xb1, 85, value]), None);
+        }
+        mapper.map(&chorus_rate(31));
+        assert_eq!(sent.take(), [[0xb1, 85, 39]]);
+        // Later the same value is the DAW's, and picked up at
+        clock.advance(Mapper::ECHO_WINDOW);
+        assert_eq!(mapper.reverse_map(&[0xb1, 85, 39]), Some(chorus_rate(31)));
+        mapper.map(&chorus_rate(30));
+        assert!(sent.take().is_empty());
+        mapper.map(&chorus_rate(32));
+        assert_eq!(sent.take(), [[0xb1, 85, 41]]);
+        // Unmapped CCs, and data entry without an NRPN selected, change nothing
+        assert_eq!(mapper.reverse_map(&[0xb1, 86, 64]), None);
+        assert_eq!(mapper.reverse_map(&[0xb1, 6, 64]), None);
+    }
+
+    #[test]
+    fn hands_a_slider_over_between_the_daw_and_the_hand_on_it() {
+        for resume in [true, false] {
+            let source = format!("arbitration:\n  hold_ms: 500\n  resume: {}\nsliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n", resume);
+            let (mut mapper, sent) = mapper(Config::parse(&source).unwrap());
+            let clock = clocked(&mut mapper);
+            // The DAW's automation plays, until the slider is grabbed
+            assert_eq!(mapper.reverse_map(&[0xb1, 85, 20]), Some(chorus_rate(16)));
+            assert_eq!(mapper.owner(0x00012b), Some(Source::Reverse));
+            mapper.map(&chorus_rate(50));
+            assert_eq!(sent.take(), [[0xb1, 85, 64]]);
+            assert_eq!(mapper.owner(0x00012b), Some(Source::Physical));
+            clock.advance(Duration::from_millis(200));
+            assert_eq!(mapper.reverse_map(&[0xb1, 85, 30]), None, "resume: {}", resume);
+            // Let go of for the hold, the automation takes over again, or
+            // stays paused while it keeps playing
+            clock.advance(Duration::from_millis(300));
+            assert_eq!(mapper.owner(0x00012b), None);
+            let resumed = mapper.reverse_map(&[0xb1, 85, 40]);
+            assert_eq!(resumed.is_some(), resume, "resume: {}", resume);
+            if !resume {
+                for _ in 0..5 {
+                    clock.advance(Duration::from_millis(100));
+                    assert_eq!(mapper.reverse_map(&[0xb1, 85, 40]), None);
+                }
+                // ...until it is played again after a rest
+                clock.advance(Duration::from_millis(500));
+                assert_eq!(mapper.reverse_map(&[0xb1, 85, 40]), Some(chorus_rate(31)));
+            }
+            assert_eq!(mapper.owner(0x00012b), Some(Source::Reverse));
+            let held_off = if resume { "1 from reverse" } else { "7 from reverse" };
+            assert!(mapper.stats_report().contains(&format!("Held off by the arbitration: {}", held_off)), "{}", mapper.stats_report());
+        }
+    }
+
+    #[test]
+    fn holds_off_the_sources_after_the_one_moving_a_slider() {
+        // The sync answer comes after the slider being moved
+        let (mut synced, sent) = mapper(Config::parse(&format!("arbitration:\n  hold_ms: 500\n{}", ROUTED)).unwrap());
+        let clock = clocked(&mut synced);
+        let (events, received) = std::sync::mpsc::channel();
+        synced.set_events(events, true);
+        synced.map(&chorus_rate(50));
+        synced.start_sync().unwrap();
+        synced.map(&chorus_depth(100));
+        synced.map(&chorus_rate(80));
+        assert_eq!(sent.take(), [[0xb1, 85, 64], [0xb1, 86, 127]]);
+        let owners: Vec<(SysExId, Option<Source>)> = received
+            .try_iter()
+            .filter_map(|event| match event {
+                status::Event::Update(update) => Some((update.id, update.owner)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(owners, [(0x00012b, Some(Source::Physical)), (0x00012c, Some(Source::Sync))]);
+        assert!(synced.stats_report().contains("Held now: Upper Common Chorus Rate (physical), Upper Common Chorus Depth (sync)"), "{}", synced.stats_report());
+        // Once the hold is over the next sync is taken
+        clock.advance(Duration::from_millis(500));
+        synced.start_sync().unwrap();
+        synced.map(&chorus_rate(80));
+        assert_eq!(sent.take(), [[0xb1, 85, 102]]);
+        // With the DAW first the slider waits for it
+        let source = "sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n    arbitration:\n      order: [reverse, physical]\n  - sysex_id: 0x00012C\n    cc_id: 86\n";
+        let (mut waiting, sent) = mapper(Config::parse(source).unwrap());
+        let clock = clocked(&mut waiting);
+        assert!(waiting.reverse_map(&[0xb1, 85, 20]).is_some());
+        assert!(waiting.reverse_map(&[0xb1, 86, 20]).is_some());
+        clock.advance(Mapper::ECHO_WINDOW);
+        waiting.map(&chorus_rate(50));
+        waiting.map(&chorus_depth(50));
+        assert_eq!(sent.take(), [[0xb1, 86, 64]]);
+        clock.advance(Duration::from_millis(400));
+        waiting.map(&chorus_rate(51));
+        assert_eq!(sent.take(), [[0xb1, 85, 65]]);
+    }
+}
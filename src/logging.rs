@@ -0,0 +1,175 @@
+// Console output by verbosity level. Errors always go to stderr. The rest
+// goes to stdout, unless stdout is reserved for the --json event stream (or
+// the output of a replay), in which case it goes to stderr too. Once the
+// printer is started, that is written by a thread of its own, so that the
+// MIDI callback printing a line per event never waits on the terminal.
+
+use std::fmt;
+use std::io::{stderr, stdout, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only errors, with -q
+    Error = 0,
+    /// A line per translated event
+    #[default]
+    Info = 1,
+    /// Byte dumps of everything, with -v
+    Debug = 2,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static JSON: AtomicBool = AtomicBool::new(false);
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+static PRINTER: OnceLock<Sender<Printing>> = OnceLock::new();
+
+enum Printing {
+    Line(String),
+    // Answered once everything before it is printed
+    Flush(Sender<()>),
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Keeps everything but the --json events off stdout.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Where the human readable output goes.
+pub fn out() -> Box<dyn Write> {
+    if reserved() {
+        Box::new(stderr())
+    } else {
+        Box::new(stdout())
+    }
+}
+
+fn reserved() -> bool {
+    json() || STDOUT_RESERVED.load(Ordering::Relaxed)
+}
+
+/// Has the info and debug lines printed by a thread of their own from here
+/// on, into `to`, or where out() says when None. Only the first call
+/// starts one.
+pub fn start_printer(to: Option<Box<dyn Write + Send>>) {
+    let (lines, queue) = mpsc::channel();
+    if PRINTER.set(lines).is_err() {
+        return;
+    }
+    thread::spawn(move || {
+        let mut to = to;
+        for printing in queue {
+            let line = match printing {
+                Printing::Line(line) => line,
+                Printing::Flush(done) => {
+                    let _ = done.send(());
+                    continue;
+                }
+            };
+            let _ = match &mut to {
+                Some(to) => to.write_all(line.as_bytes()),
+                None if reserved() => stderr().write_all(line.as_bytes()),
+                None => stdout().write_all(line.as_bytes()),
+            };
+        }
+    });
+}
+
+/// Waits for the lines handed to the printer to be printed, e.g. before
+/// exiting or writing to the terminal directly.
+pub fn flush() {
+    if let Some(lines) = PRINTER.get() {
+        let (done, printed) = mpsc::channel();
+        if lines.send(Printing::Flush(done)).is_ok() {
+            let _ = printed.recv();
+        }
+    }
+}
+
+/// A line of info!() or debug!(), handed to the printer if there is one.
+#[doc(hidden)]
+pub fn print(args: fmt::Arguments) {
+    if let Some(lines) = PRINTER.get() {
+        let _ = lines.send(Printing::Line(format!("{}\n", args)));
+    } else if reserved() {
+        let _ = writeln!(stderr(), "{}", args);
+    } else {
+        let _ = writeln!(stdout(), "{}", args);
+    }
+}
+
+/// Prints one event of the --json stream. The event is only put together
+/// if there is a stream, as some come for every message.
+pub fn event(object: impl FnOnce() -> String) {
+    if json() {
+        println!("{}", object());
+    }
+}
+
+/// Seconds since the epoch with millisecond precision, for the events.
+pub fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:03}", now.as_secs(), now.subsec_millis())
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn json_bytes(bytes: &[u8]) -> String {
+    let items: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { eprintln!($($arg)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Info) {
+            $crate::logging::print(format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Debug) {
+            $crate::logging::print(format_args!($($arg)*));
+        }
+    };
+}
@@ -1,262 +1,890 @@
 extern crate midir;
 extern crate simple_error;
 
-use midir::{Ignore, MidiIO, MidiInput, MidiOutput,MidiOutputConnection};
-use simple_error::bail;
-use std::collections::HashMap;
-use std::error::Error;
-use std::io::{stdin, stdout, Write};
-use midir::os::unix::VirtualOutput;
-
-// TODO:
-// * read said slider config from a config file (yaml?)
+mod cli;
+mod console;
+mod detect;
+mod examples;
+mod learn;
+mod migrate;
+mod verify;
+mod replay;
+mod signals;
+#[cfg(target_arch = "wasm32")]
+mod web;
 
-type SysExId = u16;
-type CcId = u8;
-type MidiValue = i8;
+use midir::{MidiIO, MidiInput, MidiInputConnection, MidiInputPort, MidiOutput, MidiOutputConnection, SendError};
+use pg1000cc::config::Config;
+use pg1000cc::{debug, error, info, logging, osc, parameters, status, Mapper, MidiSink};
+use std::error::Error;
+use std::fs::File;
+use std::io::{stdin, stdout, IsTerminal, Write};
+#[cfg(unix)]
+use midir::os::unix::{VirtualInput, VirtualOutput};
+use cli::Options;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
-struct MidiRange {
-    lo : MidiValue,
-    hi : MidiValue,
+fn main() {
+    let result = run();
+    logging::flush();
+    if let Err(err) = result {
+        error!("Error: {}", err);
+        std::process::exit(1);
+    }
 }
 
-impl MidiRange {
-    pub fn new(lo : MidiValue, hi : MidiValue) -> Self {
-        Self {
-            lo,
-            hi
+#[cfg(not(target_arch = "wasm32"))]
+fn run() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args(std::env::args().skip(1))?;
+    if options.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    logging::set_level(options.level);
+    logging::set_json(options.json);
+    if options.list_ports {
+        let midi_in = MidiInput::new(options.client_name())?;
+        let midi_out = MidiOutput::new(options.client_name())?;
+        println!("Input ports:\n{}", list_ports(&midi_in, &midi_in.ports())?);
+        println!("Output ports:\n{}", list_ports(&midi_out, &midi_out.ports())?);
+        return Ok(());
+    }
+    if options.list_parameters {
+        for (id, name) in parameters::all() {
+            println!("0x{:06X}  {}", id, name);
+        }
+        return Ok(());
+    }
+    if options.examples {
+        match &options.example {
+            Some(name) => print!("{}", examples::find(name)?),
+            None => println!("Example configs, print one with examples copy <name>:\n{}", examples::list()),
+        }
+        return Ok(());
+    }
+    if let Some(path) = &options.migrate_legacy {
+        // The diff goes to stdout
+        logging::reserve_stdout();
+        return migrate::migrate_legacy(path.as_deref(), options.config.as_deref());
+    }
+    if options.legacy_compat && (options.learn || options.calibrate) {
+        return Err("--legacy-compat has no config file to save into, write one with migrate-legacy first".into());
+    }
+    if options.learn && options.non_interactive {
+        return Err("--learn asks questions, it can't be used with --non-interactive".into());
+    }
+    if options.verify_device && options.non_interactive {
+        return Err("verify-device asks questions, it can't be used with --non-interactive".into());
+    }
+    if options.replay {
+        let input = options.replay_in.as_deref().ok_or("replay needs a capture file, --in <path>")?;
+        return replay::replay(load_config(&options)?, options.profile.as_deref(), input, options.replay_out.as_deref(), options.replay_spacing);
+    }
+    if matches!(options.dump_config, Some(None)) {
+        logging::reserve_stdout();
+    }
+    let mut config = load_config(&options)?;
+    if options.check_config {
+        // One output is asked for when none is given
+        config.check(options.outputs.len().max(1))?;
+        match config.profiles.len() {
+            0 => println!("Config OK, {} sliders", config.sliders.len()),
+            profiles => println!("Config OK, {} sliders, {} more profiles", config.sliders.len(), profiles),
+        }
+        return Ok(());
+    }
+    if let Some(path) = &options.dump_config {
+        config.validate()?;
+        config.sort_sliders();
+        match path {
+            Some(path) => {
+                config.save(path)?;
+                info!("Wrote {} sliders to {}", config.sliders.len(), path.display());
+            }
+            None => print!("{}", config.to_yaml()),
+        }
+        return Ok(());
+    }
+    if let Some(name) = &options.profile {
+        config.profile(name)?;
+    }
+    if options.osc_only && config.osc_destination.is_none() {
+        return Err("--osc-only needs an osc_destination in the config".into());
+    }
+
+    info!("This program allows the use of a Roland PG-1000 as a MIDI controller sending CC messages.\n");
+    info!("NOTE: To work, set the PG-1000 in patch edit mode by pressing LOWER or UPPER button.\n");
+
+    let mut midi_in = MidiInput::new(&options.client("forwarding input"))?;
+    // Stays as it is over reloads, the connections are made with it
+    let config_filter = config.filter.clone();
+    // The inputs are watched for Active Sensing, even where it is dropped
+    let watch_sensing = config.active_sensing.is_some();
+    midi_in.ignore(config_filter.driver_ignore(watch_sensing));
+
+    let interactive = !options.non_interactive;
+    let detected = match (options.inputs.is_empty(), options.auto) {
+        (true, true) => detect::detect_input(&midi_in, &config.device, options.probe, &options.client("auto-detect"))?,
+        _ => None,
+    };
+    let in_ports = match detected {
+        Some(port) => vec![port],
+        None => {
+            if options.auto && options.inputs.is_empty() {
+                info!("Auto-detect found nothing");
+            }
+            select_inputs(&midi_in, &options.inputs, interactive)?
+        }
+    };
+    // The PG-1000 is expected on the first one, the others are merged in
+    let in_port = &in_ports[0];
+    println!();
+    if options.verify_device {
+        let checked = verify::verify(midi_in, in_port, &format!("{} verify", options.port_name()), &config)?;
+        println!("\n{}", verify::report(&checked));
+        if let Some(path) = &options.verify_report {
+            std::fs::write(path, verify::to_json(&checked)).map_err(|err| format!("Can't write {}: {}", path.display(), err))?;
+            println!("Wrote the report to {}", path.display());
+        }
+        if options.calibrate {
+            // Like learn, the file is saved without the command line overrides
+            let path = match options.config.clone().or_else(Config::default_path) {
+                Some(path) => path,
+                None => return Err("No config file to calibrate, give one with --config".into()),
+            };
+            let mut file_config = load_config_file(&options)?;
+            match verify::calibrate(&mut file_config, &checked) {
+                0 => println!("No slider to calibrate"),
+                calibrated => {
+                    file_config.validate()?;
+                    file_config.save(&path)?;
+                    println!("Saved the sysex_range of {} sliders to {}", calibrated, path.display());
+                }
+            }
+        }
+        return Ok(());
+    }
+    if options.learn {
+        // The file is saved without the command line overrides
+        let path = match options.config.clone().or_else(Config::default_path) {
+            Some(path) => path,
+            None => return Err("No config file to learn into, give one with --config".into()),
+        };
+        let mut file_config = load_config_file(&options)?;
+        // The addresses learned only mean something with the device they
+        // came from
+        file_config.device = config.device.clone();
+        midi_in = learn::learn(midi_in, in_port, &format!("{} learn", options.port_name()), &mut file_config, &path)?;
+        config = apply_overrides(file_config, &options);
+        println!();
+    }
+    let outputs: Vec<(String, Box<dyn MidiSink>)> = match &config.osc_destination {
+        Some(destination) if options.osc_only => vec![(format!("OSC {}", destination), Box::new(NoOutput))],
+        _ => {
+            // Without --output there is the one default output
+            let wanted: Vec<Option<&str>> = if options.outputs.is_empty() {
+                vec![None]
+            } else {
+                options.outputs.iter().map(|output| Some(output.as_str())).collect()
+            };
+            if wanted.len() > Config::MAX_OUTPUTS {
+                return Err(format!("At most {} outputs can be given", Config::MAX_OUTPUTS).into());
+            }
+            let mut outputs: Vec<(String, Box<dyn MidiSink>)> = vec![];
+            for wanted in wanted {
+                // Every connection needs a MidiOutput of its own
+                let midi_out = MidiOutput::new(&options.client("forwarding output"))?;
+                let (conn_out, out_port_name) = open_output(midi_out, wanted, &options)?;
+                outputs.push((out_port_name, Box::new(conn_out)));
+            }
+            outputs
+        }
+    };
+    let out_port_name = outputs.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join("', '");
+
+    // In bidirectional mode CCs coming back from the DAW are converted
+    // back into SysEx and sent to the D-50.
+    // With --sync-on-start (or the sync command) the D-50 is asked for its
+    // values through the same port.
+    let sysex_out = if options.bidirectional || options.sync_on_start {
+        println!();
+        let midi_sysex_out = MidiOutput::new(&options.client("sysex output"))?;
+        let wanted = options.sysex_output.as_deref();
+        let (sysex_out, sysex_out_name) = connect_output(midi_sysex_out, "where the D-50 is connected", wanted, interactive, options.port_name())?;
+        Some((Arc::new(Mutex::new(sysex_out)), sysex_out_name))
+    } else {
+        None
+    };
+    let reverse = if options.bidirectional {
+        let cc_in = MidiInput::new(&options.client("reverse input"))?;
+        let cc_in_port = select_cc_input(&cc_in, &options)?;
+        Some((cc_in, cc_in_port))
+    } else {
+        None
+    };
+
+    info!("\nOpening connections");
+    // From here on the line per event is printed from the MIDI callback
+    logging::start_printer(None);
+    let in_port_names = in_ports.iter().map(|port| midi_in.port_name(port)).collect::<Result<Vec<_>, _>>()?;
+    let in_port_name = in_port_names.join("', '");
+
+    let osc = match &config.osc_destination {
+        Some(destination) => {
+            let sender = osc::OscSender::connect(destination).map_err(|err| format!("Could not set up OSC to '{}': {}", destination, err))?;
+            info!("Sending OSC to '{}'", destination);
+            Some(sender)
+        }
+        None => None,
+    };
+    let mut mapper = Mapper::new(config, outputs)?;
+    if let Some(name) = &options.profile {
+        mapper.select_profile(name)?;
+    }
+    mapper.set_inputs(in_port_names.clone());
+    let mapper = Arc::new(Mutex::new(mapper));
+    if let Some(osc) = osc {
+        mapper.lock().unwrap().set_osc(osc);
+    }
+    // The file is created right away, so that a path that can't be
+    // written to is found out before the session rather than after it
+    let record = match &options.record {
+        Some(path) => {
+            let file = File::create(path).map_err(|err| format!("Could not create {}: {}", path.display(), err))?;
+            mapper.lock().unwrap().start_recording(options.record_passthrough);
+            Some((file, path))
+        }
+        None => None,
+    };
+
+    // Values held back by the rate limit, and the steps of smoothing ramps,
+    // are sent from here when nothing newer comes in. The interval is asked
+    // every time, as a config reload can change it.
+    let tick_mapper = Arc::clone(&mapper);
+    std::thread::spawn(move || loop {
+        let interval = tick_mapper.lock().unwrap().tick_interval();
+        std::thread::sleep(interval.unwrap_or(Duration::from_millis(100)));
+        tick_mapper.lock().unwrap().tick();
+    });
+
+    // _conns_in needs to be a named parameter, because the connections need to be kept alive until the end of the scope
+    let mut _conns_in = vec![];
+    let mut midi_in = Some(midi_in);
+    for (index, (port, name)) in in_ports.iter().zip(&in_port_names).enumerate() {
+        // Every connection needs a MidiInput of its own
+        let midi_in = match midi_in.take() {
+            Some(midi_in) => midi_in,
+            None => {
+                let mut midi_in = MidiInput::new(&options.client("forwarding input"))?;
+                midi_in.ignore(config_filter.driver_ignore(watch_sensing));
+                midi_in
+            }
+        };
+        let conn_in = connect_input(midi_in, port, options.port_name(), index, Arc::clone(&mapper)).map_err(|err| format!("{}: {}", name, err.kind()))?;
+        if options.no_reconnect {
+            _conns_in.push(conn_in);
+        } else {
+            // The watch thread owns the connection from here on, replacing
+            // it when the device comes back after disappearing. The other
+            // inputs carry on meanwhile.
+            let watch_mapper = Arc::clone(&mapper);
+            let name = name.clone();
+            let (watcher, port_name) = (options.client("watch"), options.port_name().to_string());
+            std::thread::spawn(move || watch_input(conn_in, index, name, watch_mapper, &watcher, &port_name));
         }
     }
 
-    pub fn width(&self) -> usize {
-        (self.hi - self.lo) as usize
+    // Likewise _conn_reverse
+    let _conn_reverse = match (reverse, &sysex_out) {
+        (Some((cc_in, cc_in_port)), Some((sysex_out, sysex_out_name))) => {
+            let cc_in_name = match &cc_in_port {
+                Some(port) => cc_in.port_name(port)?,
+                None => options.port_name().to_string(),
+            };
+            let conn = connect_reverse(cc_in, cc_in_port, options.port_name(), Arc::clone(&mapper), Arc::clone(sysex_out))?;
+            info!("Converting CCs from '{}' back to SysEx for '{}'", cc_in_name, sysex_out_name);
+            Some(conn)
+        }
+        _ => None,
+    };
+    let sysex_out = sysex_out.map(|(sysex_out, _)| sysex_out);
+    // Before anything that would need the devices awake
+    mapper.lock().unwrap().start_sequence();
+    if options.sync_on_start {
+        info!("{}", request_sync(&mapper, sysex_out.as_deref()));
     }
 
-    pub fn value_in_other_range(&self, value: MidiValue, other_range: &MidiRange) -> MidiValue {
-        let relative = (value - self.lo) as f64 / self.width() as f64;
-        other_range.relative_to_absolute(relative)
+    let how_to_stop = if options.daemon {
+        "stop with SIGINT or SIGTERM, SIGHUP reloads the config"
+    } else {
+        "type help for the commands, quit to exit"
+    };
+    info!("Connections open, forwarding from '{}' to '{}' ({}) ...", in_port_name, out_port_name, how_to_stop);
+
+    // Everything the main thread waits for comes through one channel: the
+    // lines typed on stdin, signals, and slider updates for the status display
+    let (events_tx, events) = mpsc::channel();
+    signals::forward(events_tx.clone()).map_err(|err| format!("Could not set up signal handling: {}", err))?;
+    if !options.daemon {
+        let input_tx = events_tx.clone();
+        std::thread::spawn(move || {
+            for line in stdin().lines().map_while(Result::ok) {
+                let event = match console::parse(&line) {
+                    Some(event) => event,
+                    None => continue,
+                };
+                if input_tx.send(event).is_err() {
+                    return;
+                }
+            }
+            let _ = input_tx.send(status::Event::InputClosed);
+        });
+    }
+    if let Some(interval) = options.stats_interval {
+        let stats_tx = events_tx.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            if stats_tx.send(status::Event::Stats).is_err() {
+                return;
+            }
+        });
+    }
+    let live_status = !options.plain && !options.json && stdout().is_terminal();
+    mapper.lock().unwrap().set_events(events_tx, live_status);
+    let mut view = if live_status {
+        let header = format!("Forwarding from '{}' to '{}'", in_port_name, out_port_name);
+        Some(status::StatusView::new(header))
+    } else {
+        None
+    };
+
+    let mut failure = None;
+
+    loop {
+        if let Some(view) = &mut view {
+            view.draw()?;
+        }
+        // A change that came too soon after the last redraw is drawn a bit later
+        let event = match &view {
+            Some(view) if view.is_dirty() => match events.recv_timeout(status::StatusView::REFRESH) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            _ => match events.recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+        match event {
+            status::Event::Update(update) => {
+                if let Some(view) = &mut view {
+                    view.update(update);
+                }
+            }
+            status::Event::Reload => {
+                // The connections stay open, only the mapping changes. A
+                // broken config leaves the old one in use.
+                let message = match load_config(&options).and_then(|config| Ok(mapper.lock().unwrap().reload(config)?)) {
+                    Ok(sliders) => format!("Reloaded the config, {} sliders", sliders),
+                    Err(err) => format!("Keeping the old config: {}", err),
+                };
+                match &mut view {
+                    Some(view) => view.set_message(message),
+                    None => info!("{}", message),
+                }
+            }
+            status::Event::Sync => {
+                let message = request_sync(&mapper, sysex_out.as_deref());
+                match &mut view {
+                    Some(view) => view.set_message(message),
+                    None => info!("{}", message),
+                }
+            }
+            status::Event::Resend => {
+                // The mapper tells how many there were
+                mapper.lock().unwrap().resend();
+            }
+            status::Event::Startup => {
+                // The mapper tells how it goes
+                if !mapper.lock().unwrap().start_sequence() {
+                    let message = "The config has no startup_sequence".to_string();
+                    match &mut view {
+                        Some(view) => view.set_message(message),
+                        None => info!("{}", message),
+                    }
+                }
+            }
+            status::Event::Profile(name) => {
+                // The mapper tells which profile is in use now
+                if let Err(err) = mapper.lock().unwrap().select_profile(&name) {
+                    match &mut view {
+                        Some(view) => view.set_message(err),
+                        None => error!("{}", err),
+                    }
+                }
+            }
+            status::Event::Stats => {
+                let report = mapper.lock().unwrap().stats_report();
+                match &mut view {
+                    Some(view) => view.set_message(report),
+                    None => info!("{}", report),
+                }
+            }
+            status::Event::ResetStats => {
+                mapper.lock().unwrap().reset_stats();
+                let message = "Reset the statistics".to_string();
+                match &mut view {
+                    Some(view) => view.set_message(message),
+                    None => info!("{}", message),
+                }
+            }
+            status::Event::Channel(channel) => {
+                // The mapper tells which channel is in use now
+                if let Err(err) = mapper.lock().unwrap().set_channel(channel) {
+                    match &mut view {
+                        Some(view) => view.set_message(err),
+                        None => error!("{}", err),
+                    }
+                }
+            }
+            status::Event::AddOutput { virtual_port, name, prime } => {
+                // The mapper tells when it is added
+                if let Err(err) = add_output(&mapper, &options, virtual_port, &name, prime) {
+                    let message = format!("Could not add output '{}': {}", name, err);
+                    match &mut view {
+                        Some(view) => view.set_message(message),
+                        None => error!("{}", message),
+                    }
+                }
+            }
+            status::Event::RemoveOutput(name) => {
+                if let Err(err) = mapper.lock().unwrap().remove_output(&name) {
+                    match &mut view {
+                        Some(view) => view.set_message(err),
+                        None => error!("{}", err),
+                    }
+                }
+            }
+            status::Event::Mute(muted) => mapper.lock().unwrap().set_muted(muted),
+            status::Event::Message(message) => match &mut view {
+                // The mapper's own messages only come here for the view,
+                // the ones from stdin always do
+                Some(view) => view.set_message(message),
+                None => info!("{}", message),
+            },
+            status::Event::OutputFailing(message) => {
+                failure = Some(message);
+                break;
+            }
+            status::Event::Signal(name) => {
+                info!("Got {}", name);
+                break;
+            }
+            status::Event::Quit | status::Event::InputClosed => break,
+        }
     }
 
-    pub fn relative_to_absolute(&self, relative :f64) -> MidiValue {
-        (self.lo as f64 + relative * self.width() as f64) as MidiValue
+    info!("Closing connections");
+    let mut mapper = mapper.lock().unwrap();
+    if failure.is_none() {
+        mapper.flush();
+    }
+    if let (Some((mut file, path)), Some(recording)) = (record, mapper.take_recording()) {
+        file.write_all(&recording.to_smf()).map_err(|err| format!("Could not write the recording to {}: {}", path.display(), err))?;
+        info!("Recorded {} messages to {}", recording.len(), path.display());
+    }
+    if let Some(message) = failure {
+        return Err(format!("Giving up on the output: {} ({} failed sends in total)", message, mapper.send_errors()).into());
+    }
+    for (name, stats) in mapper.input_stats() {
+        info!(
+            "From '{}': {} messages, {} translated, {} passed through, {} filtered",
+            name, stats.messages, stats.translated, stats.passed_through, stats.filtered
+        );
+    }
+    for (name, stats) in mapper.output_stats() {
+        info!("To '{}': {} sent, {} failed", name, stats.sent, stats.errors);
     }
+    info!("Suppressed {} duplicate values", mapper.suppressed_duplicates());
+    report_filtered(&mapper);
+    if mapper.send_errors() > 0 {
+        error!("{} messages could not be sent to the outputs", mapper.send_errors());
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct Slider {
-    sysex_id : SysExId,
-    cc_id : CcId,
-    sysex_range : MidiRange,
-    cc_range : MidiRange
+/// How many messages of each kind the passthrough filter dropped.
+fn report_filtered(mapper: &Mapper) {
+    if !mapper.filtered().is_empty() {
+        let counts: Vec<String> = mapper.filtered().iter().map(|(kind, count)| format!("{} {}", count, kind)).collect();
+        info!("Filtered {}", counts.join(", "));
+    }
 }
 
-impl Slider {
-    pub fn new(sysex_id : SysExId, cc_id : CcId, sysex_range: MidiRange, cc_range: MidiRange) -> Self {
-        Self {
-            sysex_id,
-            cc_id,
-            sysex_range,
-            cc_range
+/// Sends the D-50 a data request for the mapped sliders. The answer comes
+/// in on the input like the slider moves do.
+fn request_sync(mapper: &Mutex<Mapper>, sysex_out: Option<&Mutex<MidiOutputConnection>>) -> String {
+    let sysex_out = match sysex_out {
+        Some(sysex_out) => sysex_out,
+        None => return "Syncing needs the port the D-50 is connected to, start with --sync-on-start or --bidirectional".to_string(),
+    };
+    let request = match mapper.lock().unwrap().start_sync() {
+        Ok(request) => request,
+        Err(err) => return err,
+    };
+    match sysex_out.lock().unwrap().send(&request) {
+        Ok(()) => {
+            debug!("Sent {:X?} to the D-50", request);
+            "Asked the D-50 for its values".to_string()
+        }
+        Err(err) => {
+            mapper.lock().unwrap().cancel_sync();
+            format!("Could not send the data request to the D-50: {}", err)
         }
     }
+}
+
+/// The MIDI output with --osc-only: nowhere.
+struct NoOutput;
 
-    pub fn sysex_value_as_cc_value(&self, value: MidiValue) -> MidiValue {
-        self.sysex_range.value_in_other_range(value, &self.cc_range)
+impl MidiSink for NoOutput {
+    fn send(&mut self, _message: &[u8]) -> Result<(), SendError> {
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-struct ControlMessage {
-    cc: CcId,
-    value: MidiValue,
-    channel: u8,
+/// `index` tells the mapper which input the messages come from.
+fn connect_input(
+    midi_in: MidiInput,
+    port: &MidiInputPort,
+    port_name: &str,
+    index: usize,
+    mapper: Arc<Mutex<Mapper>>,
+) -> Result<MidiInputConnection<()>, midir::ConnectError<MidiInput>> {
+    midi_in.connect(
+        port,
+        port_name,
+        move |stamp, message, _| {
+            mapper.lock().unwrap().map_from(index, Some(stamp), message);
+        },
+        (),
+    )
 }
 
-impl ControlMessage {
-    fn new(cc: CcId, value: MidiValue, channel: u8) -> Self {
-        Self { cc, value, channel }
+// How often the input port is checked for, and then looked for again
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Port names are the same after replugging a device, apart from the ALSA
+/// client and port numbers at the end ("... 20:3") that can change.
+fn same_port_name(a: &str, b: &str) -> bool {
+    fn strip_address(name: &str) -> &str {
+        match name.rsplit_once(' ') {
+            Some((rest, address)) if address.contains(':') && address.chars().all(|c| c.is_ascii_digit() || c == ':') => rest,
+            _ => name,
+        }
     }
+    a == b || strip_address(a) == strip_address(b)
+}
 
-    fn to_bytes(&self) -> Vec<u8> {
-        // Besides the MIDI standard, here's a convenient page describing
-        // the protocol: https://www.songstuff.com/recording/article/midi_message_format/
-        let mut ret = vec![];
-        let status: u8 = 0xb0 | (self.channel & 0b00001111);
-        let data1 = self.cc & 0b01111111;
-        let data2 = self.value & 0b01111111;
-        ret.push(status);
-        ret.push(data1);
-        ret.push(data2 as u8);
-        return ret;
+/// Keeps the forwarding input connected: when the port disappears (the
+/// D-50 switched off, a USB cable pulled), waits for it to come back and
+/// reconnects. The output port and the mapper state stay as they are.
+/// `watcher` is the client name to look for the port with, `port_name` the
+/// name to connect with again.
+fn watch_input(conn: MidiInputConnection<()>, index: usize, name: String, mapper: Arc<Mutex<Mapper>>, watcher: &str, port_name: &str) {
+    let watcher = match MidiInput::new(watcher) {
+        Ok(watcher) => watcher,
+        Err(err) => return error!("Cannot watch the input port, reconnecting is off: {}", err),
+    };
+    let find = |midi_in: &MidiInput| {
+        midi_in.ports().into_iter().find(|port| midi_in.port_name(port).is_ok_and(|port_name| same_port_name(&port_name, &name)))
+    };
+    let mut conn = conn;
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        if find(&watcher).is_some() {
+            continue;
+        }
+        error!("Input '{}' disappeared, waiting for it to come back", name);
+        let (mut midi_in, ()) = conn.close();
+        let mut attempt = 1;
+        conn = loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            let port = match find(&midi_in) {
+                Some(port) => port,
+                None => {
+                    debug!("Reconnect attempt {}: '{}' not there yet", attempt, name);
+                    attempt += 1;
+                    continue;
+                }
+            };
+            mapper.lock().unwrap().input_reconnected(index);
+            match connect_input(midi_in, &port, port_name, index, Arc::clone(&mapper)) {
+                Ok(conn) => {
+                    info!("Reconnected to '{}' after {} attempts", name, attempt);
+                    break conn;
+                }
+                Err(err) => {
+                    error!("Reconnect attempt {} to '{}' failed: {}", attempt, name, err.kind());
+                    midi_in = err.into_inner();
+                    attempt += 1;
+                }
+            }
+        };
     }
 }
 
-#[derive(Debug, Clone)]
-struct Pg1000SysExMessage {
-    id: SysExId,
-    value: MidiValue,
+/// Virtual ports are only available on unix, so there the virtual port
+/// stays the default and an existing port is used only when asked for.
+#[cfg(unix)]
+fn open_output(midi_out: MidiOutput, wanted: Option<&str>, options: &Options) -> Result<(MidiOutputConnection, String), Box<dyn Error>> {
+    match (wanted, options.select_output) {
+        (Some("virtual"), _) | (None, false) => Ok((midi_out.create_virtual(options.port_name())?, options.port_name().to_string())),
+        (wanted, _) => connect_output(midi_out, "to send the CCs to", wanted, !options.non_interactive, options.port_name()),
+    }
 }
 
-impl Pg1000SysExMessage {
-    fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
-        if bytes.len() != 11 {
-            bail!("wrong length");
-        } else if bytes[0] != 0xf0 {
-            bail!("wrong status byte");
-        } else {
-            let id : u16 = (bytes[6] as u16) << 8 | bytes[7] as u16;
-            Ok(Self {
-                id: id as SysExId,
-                value: bytes[8] as MidiValue,
-            })
+/// Elsewhere (Windows) the OS has no virtual ports, so the CCs always go to
+/// an existing port, typically a loopback port created with e.g. loopMIDI.
+#[cfg(not(unix))]
+fn open_output(midi_out: MidiOutput, wanted: Option<&str>, options: &Options) -> Result<(MidiOutputConnection, String), Box<dyn Error>> {
+    const LOOPBACK_HINT: &str = "Virtual ports are not supported on this platform. Create a loopback port \
+        (e.g. with loopMIDI), select it as the output here and as the input in your DAW.";
+    match wanted {
+        Some("virtual") => Err(LOOPBACK_HINT.into()),
+        wanted => {
+            if wanted.is_none() {
+                println!("{}\n", LOOPBACK_HINT);
+            }
+            connect_output(midi_out, "to send the CCs to", wanted, !options.non_interactive, options.port_name())
         }
     }
 }
 
-struct Mapper {
-    sliders: HashMap<SysExId, Slider>,
-    channel: u8,
-    port:MidiOutputConnection
+/// Opens an output for the output add command, a virtual port named `name`
+/// or the existing port it matches, without asking which.
+fn add_output(mapper: &Mutex<Mapper>, options: &Options, virtual_port: bool, name: &str, prime: bool) -> Result<(), Box<dyn Error>> {
+    let midi_out = MidiOutput::new(&options.client("forwarding output"))?;
+    let (conn_out, out_port_name) = match virtual_port {
+        #[cfg(unix)]
+        true => (midi_out.create_virtual(name)?, name.to_string()),
+        #[cfg(not(unix))]
+        true => return Err("virtual ports are not supported on this platform".into()),
+        false => connect_output(midi_out, "to send the CCs to", Some(name), false, options.port_name())?,
+    };
+    mapper.lock().unwrap().add_output(out_port_name, Box::new(conn_out), prime)?;
+    Ok(())
 }
 
-impl Mapper {
-    // undefined CC's from MIDI standard:
-    const FREE_CCS: &'static [u8] = &[
-        3, 9, 14, 15, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 85, 86, 87, 88, 89, 90, 102,
-        103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119,
-    ];
-
-    pub fn new(channel:u8, port:MidiOutputConnection) -> Self {
-        // These are all the sliders on the PG-1000, that have values ranging from 0-100.
-        // The rest of the sliders have considerably smaller resolution,
-        // ranging e.g. 0-4. Seems their original purpose is to act as
-        // switches. I don't have use for those, but if you do, you could add
-        // them here as well.
-        //
-        // These assume patch edit mode (LOWER or UPPER led is lit under COMMON SELECT).
-        // Seems the sysex ids change when a partial has been selected. Probably could add
-        // mappings for them as well, but would run out of FREE_CCS...
-        
-        let mut sliders = HashMap::new();
-        let default_cc_range = MidiRange::new(0, 127);
-        let default_sysex_range = MidiRange::new(0, 100);
-        sliders.insert(0x0319, Slider::new(0x0319, Self::FREE_CCS[0], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0318, Slider::new(0x0318, Self::FREE_CCS[1], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0321, Slider::new(0x0321, Self::FREE_CCS[2], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x031C, Slider::new(0x031C, Self::FREE_CCS[3], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0323, Slider::new(0x0323, Self::FREE_CCS[4], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0324, Slider::new(0x0324, Self::FREE_CCS[5], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x012F, Slider::new(0x012F, Self::FREE_CCS[6], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0116, Slider::new(0x0116, Self::FREE_CCS[7], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0117, Slider::new(0x0117, Self::FREE_CCS[8], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0118, Slider::new(0x0118, Self::FREE_CCS[9], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x011A, Slider::new(0x011A, Self::FREE_CCS[10], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x011B, Slider::new(0x011B, Self::FREE_CCS[11], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x011E, Slider::new(0x011E, Self::FREE_CCS[12], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x011F, Slider::new(0x011F, Self::FREE_CCS[13], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0122, Slider::new(0x0122, Self::FREE_CCS[14], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0123, Slider::new(0x0123, Self::FREE_CCS[15], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x012B, Slider::new(0x012B, Self::FREE_CCS[16], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x012C, Slider::new(0x012C, Self::FREE_CCS[17], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0320, Slider::new(0x0320, Self::FREE_CCS[18], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0111, Slider::new(0x0111, Self::FREE_CCS[19], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0112, Slider::new(0x0112, Self::FREE_CCS[20], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0113, Slider::new(0x0113, Self::FREE_CCS[21], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0113, Slider::new(0x0113, Self::FREE_CCS[21], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0114, Slider::new(0x0114, Self::FREE_CCS[22], default_sysex_range.clone(), default_cc_range.clone()));
-        sliders.insert(0x0115, Slider::new(0x0115, Self::FREE_CCS[23], default_sysex_range.clone(), default_cc_range.clone()));
-
-        // T1 - T4 have a range of 0-50, use those as well.
-        sliders.insert(0x010D, Slider::new(0x010D, Self::FREE_CCS[24], MidiRange::new(0, 50), default_cc_range.clone()));
-        sliders.insert(0x010E, Slider::new(0x010E, Self::FREE_CCS[25], MidiRange::new(0, 50), default_cc_range.clone()));
-        sliders.insert(0x010F, Slider::new(0x010F, Self::FREE_CCS[26], MidiRange::new(0, 50), default_cc_range.clone()));
-        sliders.insert(0x0110, Slider::new(0x0110, Self::FREE_CCS[27], MidiRange::new(0, 50), default_cc_range.clone()));
-
-        Self {
-            sliders,
-            channel,
-            port,
-        }
-    }
+/// `port_name` is the name of our end of the connection.
+fn connect_output(
+    midi_out: MidiOutput,
+    purpose: &str,
+    wanted: Option<&str>,
+    interactive: bool,
+    port_name: &str,
+) -> Result<(MidiOutputConnection, String), Box<dyn Error>> {
+    let out_port = select_port(&midi_out, "output", purpose, wanted, interactive)?;
+    let out_port_name = midi_out.port_name(&out_port)?;
+    let conn_out = midi_out
+        .connect(&out_port, port_name)
+        .map_err(|err| format!("Could not connect to output port '{}': {}", out_port_name, err))?;
+    Ok((conn_out, out_port_name))
+}
 
-    pub fn map(&mut self, message: &[u8]) {
-        // If this is a Roland PG-1000 sysex message and we've got a
-        // mapping for it, then map...
-        Pg1000SysExMessage::from_bytes(message).ok().map(|sysex| {
-            self.sliders.get(&sysex.id).map(|slider| {
-                let cc = ControlMessage::new(slider.cc_id, slider.sysex_value_as_cc_value(sysex.value), self.channel);
-                self.port.send(&cc.to_bytes()).unwrap();
-                println!("{:X?}", cc.to_bytes());
-            });
-        });
+/// The port CCs are read from in bidirectional mode. On unix `None` means
+/// a virtual input port, created when connecting.
+#[cfg(unix)]
+fn select_cc_input(cc_in: &MidiInput, options: &Options) -> Result<Option<MidiInputPort>, Box<dyn Error>> {
+    match options.cc_input.as_deref() {
+        None | Some("virtual") => Ok(None),
+        wanted => Ok(Some(select_port(cc_in, "input", "to read the CCs from", wanted, !options.non_interactive)?)),
     }
 }
 
-fn main() {
-    match run() {
-        Ok(_) => (),
-        Err(err) => println!("Error: {}", err),
-    }
+#[cfg(not(unix))]
+fn select_cc_input(cc_in: &MidiInput, options: &Options) -> Result<Option<MidiInputPort>, Box<dyn Error>> {
+    let wanted = options.cc_input.as_deref();
+    Ok(Some(select_port(cc_in, "input", "to read the CCs from", wanted, !options.non_interactive)?))
 }
 
-#[cfg(not(target_arch = "wasm32"))] // conn_out is not `Send` in Web MIDI, which means it cannot be passed to connect
-fn run() -> Result<(), Box<dyn Error>> {
-    let mut midi_in = MidiInput::new("pg1000cc forwarding input")?;
-    midi_in.ignore(Ignore::None);
-    let midi_out = MidiOutput::new("pg1000cc forwarding output")?;
+fn connect_reverse(
+    cc_in: MidiInput,
+    cc_in_port: Option<MidiInputPort>,
+    port_name: &str,
+    mapper: Arc<Mutex<Mapper>>,
+    sysex_out: Arc<Mutex<MidiOutputConnection>>,
+) -> Result<MidiInputConnection<()>, Box<dyn Error>> {
+    let callback = move |_: u64, message: &[u8], _: &mut ()| {
+        if let Some(bytes) = mapper.lock().unwrap().reverse_map(message) {
+            match sysex_out.lock().unwrap().send(&bytes) {
+                Ok(()) => debug!("Sent {:X?} to the D-50", bytes),
+                Err(err) => error!("Could not send {:X?} to the D-50: {}", bytes, err),
+            }
+        }
+    };
+    match cc_in_port {
+        Some(port) => Ok(cc_in.connect(&port, port_name, callback, ())?),
+        #[cfg(unix)]
+        None => Ok(cc_in.create_virtual(port_name, callback, ())?),
+        #[cfg(not(unix))]
+        None => unreachable!("select_cc_input always picks a port here"),
+    }
+}
 
-    let in_port = select_port(&midi_in, "input")?;
-    println!();
-    let conn_out = midi_out.create_virtual("pg1000cc")?;
+/// The config file (or the built-in mapping) with the command line
+/// overrides applied.
+fn load_config(options: &Options) -> Result<Config, Box<dyn Error>> {
+    let config = load_config_file(options)?;
+    Ok(apply_overrides(config, options))
+}
 
-    println!("\nOpening connections");
-    let in_port_name = midi_in.port_name(&in_port)?;
+/// The config file as it is, without the command line overrides. An
+/// explicitly given config file must exist. Otherwise the default location
+/// is tried, falling back to the built-in slider table. With
+/// --legacy-compat no file is read, it is the mapping of the first version.
+fn load_config_file(options: &Options) -> Result<Config, Box<dyn Error>> {
+    if options.legacy_compat {
+        if options.config.is_some() {
+            return Err("--legacy-compat replaces the config file, it can't be used with --config".into());
+        }
+        info!("Using the mapping of the first version (--legacy-compat)");
+        return Ok(Config::legacy());
+    }
+    let path = match &options.config {
+        Some(path) => Some(path.clone()),
+        None => Config::default_path().filter(|path| path.exists()),
+    };
+    match path {
+        Some(path) => {
+            let config = Config::load(&path)?;
+            info!("Using config {}", path.display());
+            Ok(config)
+        }
+        None => {
+            // Without a config file the first version always had the same
+            // mapping, which the built-in one isn't any more
+            info!("No config file, using the built-in mapping. If a setup was made with the first version of pg1000cc, --legacy-compat keeps what it sent and migrate-legacy writes that to a config file");
+            Ok(Config::default())
+        }
+    }
+}
 
-    let mut mapper = Mapper::new(1, conn_out);
+fn apply_overrides(mut config: Config, options: &Options) -> Config {
+    if let Some(channel) = options.channel {
+        config.channel = channel;
+        for profile in &mut config.profiles {
+            profile.channel = channel;
+        }
+    }
+    if let Some(rate_limit) = options.rate_limit {
+        config.rate_limit = rate_limit;
+    }
+    if let Some(filter) = &options.filter {
+        config.filter = filter.clone();
+    }
+    if let Some(channels) = &options.passthrough_channels {
+        let drop_unmapped = config.passthrough_channels.drop_unmapped;
+        config.passthrough_channels = channels.clone();
+        config.passthrough_channels.drop_unmapped = drop_unmapped;
+    }
+    if options.drop_unmapped_channels {
+        config.passthrough_channels.drop_unmapped = true;
+    }
+    if let Some(device) = &options.device {
+        config.device = device.clone();
+    }
+    config
+}
 
-    // _conn_in needs to be a named parameter, because it needs to be kept alive until the end of the scope
-    let _conn_in = midi_in.connect(
-        &in_port,
-        "pg1000cc",
-        move |_, message, _| {
-            mapper.map(message);
-        },
-        (),
-    )?;
+/// Picks the port given on the command line (by index or by name), or
+/// prompts for one when nothing was given and prompting is allowed.
+fn select_port<T: MidiIO>(midi_io: &T, descr: &str, purpose: &str, wanted: Option<&str>, interactive: bool) -> Result<T::Port, Box<dyn Error>> {
+    let midi_ports = midi_io.ports();
+    if let Some(wanted) = wanted {
+        return match find_port(midi_io, &midi_ports, wanted) {
+            PortMatch::One(port) => Ok(port),
+            PortMatch::None => Err(format!("No {} port '{}'. Available {} ports:\n{}", descr, wanted, descr, list_ports(midi_io, &midi_ports)?).into()),
+            PortMatch::Ambiguous(names) => {
+                Err(format!("'{}' matches more than one {} port, give more of the name:\n{}", wanted, descr, names.join("\n")).into())
+            }
+        };
+    }
+    match prompt_ports(midi_io, descr, purpose, interactive)?.as_slice() {
+        [port] => Ok(port.clone()),
+        _ => Err(format!("Only one {} port can be used here", descr).into()),
+    }
+}
 
-    println!(
-        "Connections open, forwarding from '{}' to 'pg1000cc' (press enter to exit) ...",
-        in_port_name
-    );
+/// The input ports, each given with --input, or picked at the prompt.
+fn select_inputs(midi_in: &MidiInput, wanted: &[String], interactive: bool) -> Result<Vec<MidiInputPort>, Box<dyn Error>> {
+    if wanted.is_empty() {
+        return prompt_ports(midi_in, "input", "where PG-1000 is connected (more separated by commas)", interactive);
+    }
+    wanted.iter().map(|wanted| select_port(midi_in, "input", "", Some(wanted), interactive)).collect()
+}
 
+/// Asks for one or more ports by their numbers, separated by commas.
+fn prompt_ports<T: MidiIO>(midi_io: &T, descr: &str, purpose: &str, interactive: bool) -> Result<Vec<T::Port>, Box<dyn Error>> {
+    let midi_ports = midi_io.ports();
+    if !interactive {
+        return Err(format!("No {} port given. Available {} ports:\n{}", descr, descr, list_ports(midi_io, &midi_ports)?).into());
+    }
+    // Prompts are shown whatever the verbosity
+    let mut out = logging::out();
+    writeln!(out, "Available {} ports:", descr)?;
+    write!(out, "{}", list_ports(midi_io, &midi_ports)?)?;
+    write!(out, "Please select {} port {}: ", descr, purpose)?;
+    out.flush()?;
     let mut input = String::new();
-    stdin().read_line(&mut input)?; // wait for next enter key press
+    stdin().read_line(&mut input)?;
+    input
+        .split(',')
+        .map(|number| Ok(midi_ports.get(number.trim().parse::<usize>()?).ok_or("Invalid port number")?.clone()))
+        .collect()
+}
 
-    println!("Closing connections");
-    Ok(())
+enum PortMatch<P> {
+    One(P),
+    None,
+    Ambiguous(Vec<String>),
 }
 
-fn select_port<T: MidiIO>(midi_io: &T, descr: &str) -> Result<T::Port, Box<dyn Error>> {
-    println!("This program allows the use of a Roland PG-1000 as a MIDI controller sending CC messages.\n");
-    println!("NOTE: To work, set the PG-1000 in patch edit mode by pressing LOWER or UPPER button.\n");
-    println!("Available {} ports:", descr);
-    let midi_ports = midi_io.ports();
+/// Finds a port by index, by its full name, or failing those by a part of
+/// the name, ignoring case: "um-one" finds "UM-ONE:UM-ONE MIDI 1 24:0".
+fn find_port<T: MidiIO>(midi_io: &T, midi_ports: &[T::Port], wanted: &str) -> PortMatch<T::Port> {
+    if let Ok(index) = wanted.parse::<usize>() {
+        return match midi_ports.get(index) {
+            Some(port) => PortMatch::One(port.clone()),
+            None => PortMatch::None,
+        };
+    }
+    let named: Vec<(&T::Port, String)> = midi_ports.iter().filter_map(|p| Some((p, midi_io.port_name(p).ok()?))).collect();
+    if let Some((port, _)) = named.iter().find(|(_, name)| name == wanted) {
+        return PortMatch::One((*port).clone());
+    }
+    let wanted = wanted.to_lowercase();
+    let mut matches: Vec<_> = named.into_iter().filter(|(_, name)| name.to_lowercase().contains(&wanted)).collect();
+    match matches.len() {
+        0 => PortMatch::None,
+        1 => PortMatch::One(matches.remove(0).0.clone()),
+        _ => PortMatch::Ambiguous(matches.into_iter().map(|(_, name)| name).collect()),
+    }
+}
+
+fn list_ports<T: MidiIO>(midi_io: &T, midi_ports: &[T::Port]) -> Result<String, Box<dyn Error>> {
+    let mut list = String::new();
     for (i, p) in midi_ports.iter().enumerate() {
-        println!("{}: {}", i, midi_io.port_name(p)?);
+        list += &format!("{}: {}\n", i, midi_io.port_name(p)?);
     }
-    print!("Please select {} port where PG-1000 is connected: ", descr);
-    stdout().flush()?;
-    let mut input = String::new();
-    stdin().read_line(&mut input)?;
-    let port = midi_ports
-        .get(input.trim().parse::<usize>()?)
-        .ok_or("Invalid port number")?;
-    Ok(port.clone())
+    Ok(list)
 }
 
+// The page drives the browser build, through the functions in web
 #[cfg(target_arch = "wasm32")]
 fn run() -> Result<(), Box<dyn Error>> {
-    println!("pg1000cc cannot run on Web MIDI");
     Ok(())
 }
@@ -0,0 +1,104 @@
+// Moving off --legacy-compat: the mapping of the first version written out
+// as a config file, to be changed deliberately from there, with what sets it
+// apart from the built-in mapping printed as a diff of the two config files.
+
+use pg1000cc::config::Config;
+use pg1000cc::info;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// The lines around each change that are printed with it
+const CONTEXT: usize = 2;
+
+/// Writes the legacy mapping to `path` (or the config file), which must
+/// not exist yet, and prints the diff from the built-in mapping.
+pub fn migrate_legacy(path: Option<&Path>, config: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let path: PathBuf = match path.or(config).map(Path::to_path_buf).or_else(Config::default_path) {
+        Some(path) => path,
+        None => return Err("Nowhere to write the config file to, give migrate-legacy a path".into()),
+    };
+    if path.exists() {
+        return Err(format!("{} is there already, give migrate-legacy the path of a new file", path.display()).into());
+    }
+    let legacy = Config::legacy();
+    legacy.save(&path)?;
+    info!("Wrote the legacy mapping to {}, with --config it does what --legacy-compat does", path.display());
+    print!("{}", diff("built-in", &Config::default().to_yaml(), "legacy", &legacy.to_yaml()));
+    Ok(())
+}
+
+/// The lines of `old` and `new` that differ, as a unified diff.
+pub fn diff(old_name: &str, old: &str, new_name: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // The length of the longest common subsequence of the ends from each
+    // pair of lines on
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    // Each line with where it is in both, ' ' for in both, '-' or '+'
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i], i, j));
+            (i, j) = (i + 1, j + 1);
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+    let changed: Vec<usize> = (0..lines.len()).filter(|&k| lines[k].0 != ' ').collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let mut k = 0;
+    while k < changed.len() {
+        // Changes closer together than twice the context share a hunk
+        let mut last = k;
+        while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+        let start = changed[k].saturating_sub(CONTEXT);
+        let end = (changed[last] + CONTEXT + 1).min(lines.len());
+        let hunk = &lines[start..end];
+        let count = |kind: char| hunk.iter().filter(|line| line.0 == ' ' || line.0 == kind).count();
+        let (_, _, old_at, new_at) = hunk[0];
+        out += &format!("@@ -{},{} +{},{} @@\n", old_at + 1, count('-'), new_at + 1, count('+'));
+        for (kind, line, _, _) in hunk {
+            out += &format!("{}{}\n", kind, line);
+        }
+        k = last + 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_the_changed_lines_with_their_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(diff("old", old, "new", new), "--- old\n+++ new\n@@ -1,4 +1,4 @@\n a\n-b\n+B\n c\n d\n@@ -9,2 +9,3 @@\n i\n j\n+k\n");
+        assert_eq!(diff("old", old, "new", old), "");
+    }
+
+    #[test]
+    fn shows_what_the_legacy_mapping_changes() {
+        let diff = diff("built-in", &Config::default().to_yaml(), "legacy", &Config::legacy().to_yaml());
+        for line in ["+send_repeats: true", "+out_of_range: clamp", "+  address: [6, 2]", "+    rounding: truncate", "-    stepped: true"] {
+            assert!(diff.lines().any(|changed| changed == line), "{} is missing:\n{}", line, diff);
+        }
+        // Both on channel 2
+        assert!(!diff.lines().any(|line| line.starts_with("-channel") || line.starts_with("+channel")), "{}", diff);
+    }
+}
@@ -0,0 +1,88 @@
+// OSC output over UDP, for setups that speak OSC rather than MIDI. Each
+// slider value goes out as a message with a single float argument, 0.0-1.0
+// over the slider's cc_range. The MIDI callback only queues the packets;
+// a thread of its own does the sending.
+
+use crate::device::Device;
+use crate::parameters;
+use crate::SysExId;
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Sender};
+
+pub struct OscSender {
+    packets: Sender<Vec<u8>>,
+}
+
+impl OscSender {
+    /// `destination` is host:port, e.g. 127.0.0.1:9000.
+    pub fn connect(destination: &str) -> io::Result<Self> {
+        let address = destination
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no address for '{}'", destination)))?;
+        let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.connect(address)?;
+        let (packets, queue) = mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            for packet in queue {
+                // Nobody listening is not worth more than a note, UDP
+                // being what it is
+                if let Err(err) = socket.send(&packet) {
+                    debug!("Could not send OSC to {}: {}", address, err);
+                }
+            }
+        });
+        Ok(Self { packets })
+    }
+
+    /// One that hands the packets to the receiver, for tests.
+    #[cfg(test)]
+    pub(crate) fn queue() -> (Self, mpsc::Receiver<Vec<u8>>) {
+        let (packets, queue) = mpsc::channel();
+        (Self { packets }, queue)
+    }
+
+    pub fn send(&self, address: &str, value: f32) {
+        let _ = self.packets.send(message(address, value));
+    }
+}
+
+/// The address for a slider without an osc_address, made of the D-50
+/// parameter name, e.g. /pg1000/upper/partial_1/tvf_cutoff_frequency, or of
+/// the SysEx address for a parameter without a name (or on another device
+/// than the PG-1000), e.g. /pg1000/0x000116 or /pg800/0x000012.
+pub fn default_address(device: &Device, id: SysExId) -> String {
+    let (block, name) = match parameters::parts(id).filter(|_| device.d50) {
+        Some(parts) => parts,
+        None => return format!("/{}/0x{:06X}", path_segment(&device.name), id),
+    };
+    // "Upper Partial 1" is upper/partial_1, "Patch" just patch
+    let block = match block.split_once(' ') {
+        Some((half, rest)) => format!("{}/{}", half, rest),
+        None => block.to_string(),
+    };
+    format!("/{}/{}/{}", path_segment(&device.name), path_segment(&block), path_segment(name))
+}
+
+fn path_segment(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '/' { c } else { '_' })
+        .collect()
+}
+
+/// An OSC message with one float argument.
+pub fn message(address: &str, value: f32) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(address.len() + 12);
+    push_string(&mut packet, address);
+    push_string(&mut packet, ",f");
+    packet.extend_from_slice(&value.to_be_bytes());
+    packet
+}
+
+// OSC strings end with a NUL and are padded with more to a multiple of 4
+fn push_string(packet: &mut Vec<u8>, text: &str) {
+    packet.extend_from_slice(text.as_bytes());
+    packet.extend(std::iter::repeat_n(0, 4 - text.len() % 4));
+}
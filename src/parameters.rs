@@ -0,0 +1,193 @@
+// Names of the D-50 parameters by their SysEx address, as listed in the
+// D-50 owner's manual. The PG-1000 sends the same addresses.
+
+use crate::SysExId;
+
+const PARTIAL: &[&str] = &[
+    "WG Pitch Coarse",
+    "WG Pitch Fine",
+    "WG Pitch Keyfollow",
+    "WG Mod LFO Mode",
+    "WG Mod P-ENV Mode",
+    "WG Mod Bender Mode",
+    "WG Waveform",
+    "WG PCM Wave No",
+    "WG Pulse Width",
+    "WG PW Velocity Range",
+    "WG PW LFO Select",
+    "WG PW LFO Depth",
+    "WG PW Aftertouch Range",
+    "TVF Cutoff Frequency",
+    "TVF Resonance",
+    "TVF Keyfollow",
+    "TVF Bias Point",
+    "TVF Bias Level",
+    "TVF ENV Depth",
+    "TVF ENV Velocity Range",
+    "TVF ENV Depth Keyfollow",
+    "TVF ENV Time Keyfollow",
+    "TVF ENV Time 1",
+    "TVF ENV Time 2",
+    "TVF ENV Time 3",
+    "TVF ENV Time 4",
+    "TVF ENV Time 5",
+    "TVF ENV Level 1",
+    "TVF ENV Level 2",
+    "TVF ENV Level 3",
+    "TVF ENV Sustain Level",
+    "TVF ENV End Level",
+    "TVF Mod LFO Select",
+    "TVF Mod LFO Depth",
+    "TVF Mod Aftertouch Range",
+    "TVA Level",
+    "TVA Velocity Range",
+    "TVA Bias Point",
+    "TVA Bias Level",
+    "TVA ENV Time 1",
+    "TVA ENV Time 2",
+    "TVA ENV Time 3",
+    "TVA ENV Time 4",
+    "TVA ENV Time 5",
+    "TVA ENV Level 1",
+    "TVA ENV Level 2",
+    "TVA ENV Level 3",
+    "TVA ENV Sustain Level",
+    "TVA ENV End Level",
+    "TVA ENV T1 Velocity Follow",
+    "TVA ENV Time Keyfollow",
+    "TVA Mod LFO Select",
+    "TVA Mod LFO Depth",
+    "TVA Mod Aftertouch Range",
+];
+
+// Starts at offset 0x0A, after the tone name
+const COMMON: &[&str] = &[
+    "Structure",
+    "P-ENV Velocity Range",
+    "P-ENV Time Keyfollow",
+    "P-ENV Time 1",
+    "P-ENV Time 2",
+    "P-ENV Time 3",
+    "P-ENV Time 4",
+    "P-ENV Level 0",
+    "P-ENV Level 1",
+    "P-ENV Level 2",
+    "P-ENV Sustain Level",
+    "P-ENV End Level",
+    "Pitch Mod LFO Depth",
+    "Pitch Mod Lever",
+    "Pitch Mod Aftertouch",
+    "LFO-1 Waveform",
+    "LFO-1 Rate",
+    "LFO-1 Delay Time",
+    "LFO-1 Sync",
+    "LFO-2 Waveform",
+    "LFO-2 Rate",
+    "LFO-2 Delay Time",
+    "LFO-2 Sync",
+    "LFO-3 Waveform",
+    "LFO-3 Rate",
+    "LFO-3 Delay Time",
+    "LFO-3 Sync",
+    "Low EQ Frequency",
+    "Low EQ Gain",
+    "High EQ Frequency",
+    "High EQ Q",
+    "High EQ Gain",
+    "Chorus Type",
+    "Chorus Rate",
+    "Chorus Depth",
+    "Chorus Balance",
+    "Partial Mute",
+    "Partial Balance",
+];
+const COMMON_OFFSET: SysExId = 0x0a;
+
+// Starts at offset 0x12, after the patch name
+const PATCH: &[&str] = &[
+    "Key Mode",
+    "Split Point",
+    "Portamento Mode",
+    "Hold Mode",
+    "Upper Tone Key Shift",
+    "Lower Tone Key Shift",
+    "Upper Tone Fine Tune",
+    "Lower Tone Fine Tune",
+    "Bender Range",
+    "Aftertouch Bend Range",
+    "Portamento Time",
+    "Output Mode",
+    "Reverb Type",
+    "Reverb Balance",
+    "Total Volume",
+    "Tone Balance",
+    "Chase Mode",
+    "Chase Level",
+    "Chase Time",
+];
+const PATCH_OFFSET: SysExId = 0x12;
+
+// Where each block starts, with its offset to the first named parameter
+const BLOCKS: &[(SysExId, &str, &[&str], SysExId)] = &[
+    (0x000000, "Upper Partial 1", PARTIAL, 0),
+    (0x000040, "Upper Partial 2", PARTIAL, 0),
+    (0x000100, "Upper Common", COMMON, COMMON_OFFSET),
+    (0x000140, "Lower Partial 1", PARTIAL, 0),
+    (0x000200, "Lower Partial 2", PARTIAL, 0),
+    (0x000240, "Lower Common", COMMON, COMMON_OFFSET),
+    (0x000300, "Patch", PATCH, PATCH_OFFSET),
+];
+
+// The blocks of the Upper tone with those of the Lower one, which the
+// PG-1000 sends to depending on the UPPER/LOWER selection. Each is 0x40
+// addresses long.
+const TONES: &[(SysExId, SysExId)] = &[(0x000000, 0x000140), (0x000040, 0x000200), (0x000100, 0x000240)];
+const TONE_BLOCK: SysExId = 0x40;
+
+/// The same parameter of the other tone, e.g. Lower Common Chorus Rate
+/// for Upper Common Chorus Rate. None for the patch parameters.
+pub fn other_tone(id: SysExId) -> Option<SysExId> {
+    TONES.iter().find_map(|&(upper, lower)| match id {
+        _ if (upper..upper + TONE_BLOCK).contains(&id) => Some(id - upper + lower),
+        _ if (lower..lower + TONE_BLOCK).contains(&id) => Some(id - lower + upper),
+        _ => None,
+    })
+}
+
+/// Whether the address is one of the Upper tone's.
+pub fn is_upper(id: SysExId) -> bool {
+    TONES.iter().any(|&(upper, _)| (upper..upper + TONE_BLOCK).contains(&id))
+}
+
+/// E.g. "Upper Common Chorus Rate" for 0x00012B.
+pub fn name(id: SysExId) -> Option<String> {
+    parts(id).map(|(block, name)| format!("{} {}", block, name))
+}
+
+/// The block and the parameter name separately, e.g. ("Upper Common", "Chorus Rate").
+pub fn parts(id: SysExId) -> Option<(&'static str, &'static str)> {
+    BLOCKS.iter().find_map(|&(base, block, names, offset)| {
+        let index = id.checked_sub(base + offset)? as usize;
+        names.get(index).map(|name| (block, *name))
+    })
+}
+
+/// The address for a name as given by `name`, ignoring case and extra spaces.
+pub fn id(wanted: &str) -> Option<SysExId> {
+    let wanted = normalize(wanted);
+    all().into_iter().find(|(_, name)| normalize(name) == wanted).map(|(id, _)| id)
+}
+
+/// Every named parameter in address order.
+pub fn all() -> Vec<(SysExId, String)> {
+    BLOCKS
+        .iter()
+        .flat_map(|&(base, block, names, offset)| {
+            names.iter().enumerate().map(move |(i, name)| (base + offset + i as SysExId, format!("{} {}", block, name)))
+        })
+        .collect()
+}
+
+fn normalize(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
@@ -0,0 +1,143 @@
+// The channel of the channel messages passed through from the input (notes,
+// CCs, program changes, pressure and pitch bend), rewritten for a synth that
+// listens on another channel than the keyboard sends on. System messages,
+// SysEx included, have no channel and always go through as they are.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelMap {
+    // For each channel as on the wire, 0-15, the one to send on instead
+    to: [Option<u8>; 16],
+    /// Whether the messages on the channels not in the map are dropped,
+    /// instead of passed through unchanged.
+    pub drop_unmapped: bool,
+}
+
+impl ChannelMap {
+    /// A channel 1-16 to send everything on, or comma separated pairs of
+    /// channels like "1->3, 2->4".
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid channel map '{}', expected a channel 1-16 or pairs like 1->3, 2->4", text);
+        let channel = |text: &str| match text.trim().parse::<u8>() {
+            Ok(channel @ 1..=16) => Ok(channel - 1),
+            _ => Err(invalid()),
+        };
+        let mut map = Self::default();
+        if !text.contains("->") {
+            map.to = [Some(channel(text)?); 16];
+            return Ok(map);
+        }
+        for pair in text.split(',') {
+            let (from, to) = pair.split_once("->").ok_or_else(invalid)?;
+            let from = channel(from)?;
+            if map.to[from as usize].replace(channel(to)?).is_some() {
+                return Err(format!("channel {} is in the channel map twice", from + 1));
+            }
+        }
+        Ok(map)
+    }
+
+    /// Whether a channel is mapped to another one (or the same).
+    pub fn has_channels(&self) -> bool {
+        self.to.iter().any(Option::is_some)
+    }
+
+    /// The message on the channel it is mapped to, written into `buffer`
+    /// (channel messages are 3 bytes at most), or as it is. None to drop it.
+    /// A note on with velocity 0 is a note off and is mapped the same way as
+    /// the note on before it.
+    pub fn apply<'a>(&self, message: &'a [u8], buffer: &'a mut [u8; 3]) -> Option<&'a [u8]> {
+        let status = match message.first() {
+            Some(&status @ 0x80..=0xef) if message.len() <= buffer.len() => status,
+            _ => return Some(message),
+        };
+        match self.to[(status & 0x0f) as usize] {
+            Some(channel) => {
+                let bytes = &mut buffer[..message.len()];
+                bytes.copy_from_slice(message);
+                bytes[0] = status & 0xf0 | channel;
+                Some(bytes)
+            }
+            None if self.drop_unmapped => None,
+            None => Some(message),
+        }
+    }
+}
+
+/// The channels as written in the config, without the drop_unmapped.
+impl fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to {
+            [Some(channel), ..] if self.to.iter().all(|to| *to == Some(channel)) => write!(f, "{}", channel + 1),
+            _ => {
+                let pairs: Vec<String> = (0..16)
+                    .filter_map(|from| self.to[from].map(|to| format!("{}->{}", from + 1, to + 1)))
+                    .collect();
+                write!(f, "{}", pairs.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(map: &ChannelMap, message: &[u8]) -> Option<Vec<u8>> {
+        map.apply(message, &mut [0; 3]).map(<[u8]>::to_vec)
+    }
+
+    #[test]
+    fn moves_every_kind_of_channel_message() {
+        let map = ChannelMap::parse("1->3, 2->4").unwrap();
+        // Each kind on channel 1, and what it becomes on 3
+        let table: [(&[u8], &[u8]); 8] = [
+            (&[0x80, 60, 64], &[0x82, 60, 64]),
+            (&[0x90, 60, 100], &[0x92, 60, 100]),
+            // A note on with velocity 0 is a note off, on the same channel
+            (&[0x90, 60, 0], &[0x92, 60, 0]),
+            (&[0xa0, 60, 30], &[0xa2, 60, 30]),
+            (&[0xb0, 7, 100], &[0xb2, 7, 100]),
+            (&[0xc0, 5], &[0xc2, 5]),
+            (&[0xd0, 40], &[0xd2, 40]),
+            (&[0xe0, 0x00, 0x40], &[0xe2, 0x00, 0x40]),
+        ];
+        for (message, expected) in table {
+            assert_eq!(applied(&map, message).as_deref(), Some(expected), "{:X?}", message);
+            // Channel 2 goes to 4, channel 5 isn't in the map
+            let mut on_2 = message.to_vec();
+            on_2[0] |= 0x01;
+            let mut on_4 = expected.to_vec();
+            on_4[0] = on_4[0] & 0xf0 | 0x03;
+            assert_eq!(applied(&map, &on_2), Some(on_4), "{:X?}", on_2);
+            let mut on_5 = message.to_vec();
+            on_5[0] |= 0x04;
+            assert_eq!(applied(&map, &on_5).as_ref(), Some(&on_5), "{:X?}", on_5);
+            let dropping = ChannelMap { drop_unmapped: true, ..map.clone() };
+            assert_eq!(applied(&dropping, &on_5), None, "{:X?}", on_5);
+        }
+    }
+
+    #[test]
+    fn leaves_system_messages_alone() {
+        let map = ChannelMap { drop_unmapped: true, ..ChannelMap::parse("16").unwrap() };
+        let messages: [&[u8]; 4] = [&[0xf8], &[0xfe], &[0xf2, 0x10, 0x00], &[0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x2b, 0x32, 0x22, 0xf7]];
+        for message in messages {
+            assert_eq!(applied(&map, message).as_deref(), Some(message), "{:X?}", message);
+        }
+    }
+
+    #[test]
+    fn parses_what_it_prints() {
+        for text in ["3", "1->3, 2->4", "16->1"] {
+            assert_eq!(ChannelMap::parse(text).unwrap().to_string(), text);
+        }
+        let everything = ChannelMap::parse("10").unwrap();
+        assert_eq!(applied(&everything, &[0x9f, 60, 100]), Some(vec![0x99, 60, 100]));
+        for invalid in ["0", "17", "1->", "a->b", "1->3, 1->4"] {
+            assert!(ChannelMap::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(!ChannelMap::default().has_channels());
+    }
+}
@@ -0,0 +1,234 @@
+// Offline replay: a capture of what the PG-1000 sent is read from a file
+// and run through the mapper, and whatever the mapper sends goes to a file
+// (or stdout) instead of a MIDI port. Handy for checking a mapping without
+// the hardware, and for keeping a capture and its expected output around
+// to diff against.
+//
+// The capture is either raw MIDI bytes (e.g. a .syx file), text with the
+// bytes in hex, separated by whitespace, with # starting a comment, or a
+// MIDI file. The output is written in the same format, in text one message
+// per line, and a MIDI file gives text. An output file ending in .syx is raw
+// bytes whatever the capture, and one ending in .mid is a MIDI file, the
+// conversion of a capture into automation for a DAW.
+//
+// Nothing but the mapper runs, no MIDI ports are opened, so this works on
+// a machine without any MIDI at all.
+
+use pg1000cc::config::Config;
+use pg1000cc::smf::{self, Recording};
+use pg1000cc::{error, info, logging, Mapper, MidiSink, Slider};
+use midir::SendError;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{stdout, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How long a byte takes on a MIDI cable, 10 bits at 31250 baud: the
+// spacing of the messages of a capture that has no times of its own
+const BYTE_TIME: Duration = Duration::from_micros(320);
+
+// A large capture says how far along it is every this many messages
+const PROGRESS_EVERY: usize = 100_000;
+
+struct FileSink {
+    out: Box<dyn Write + Send>,
+    hex: bool,
+}
+
+impl MidiSink for FileSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        let result = if self.hex {
+            writeln!(self.out, "{}", to_hex(message))
+        } else {
+            self.out.write_all(message)
+        };
+        result.and_then(|()| self.out.flush()).map_err(|_| SendError::Other("could not write the replay output"))
+    }
+}
+
+/// Where the replay is in the capture, and what has been sent by then.
+struct Timeline {
+    now: Duration,
+    recording: Recording,
+}
+
+/// Records into a MIDI file at the time of the message being replayed.
+struct SmfSink(Arc<Mutex<Timeline>>);
+
+impl MidiSink for SmfSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        let mut timeline = self.0.lock().unwrap();
+        let now = timeline.now;
+        timeline.recording.record_at(now, message);
+        Ok(())
+    }
+}
+
+/// `spacing` puts the messages that far apart instead of the times they
+/// have in a MIDI file, or the time they take on a MIDI cable.
+pub fn replay(mut config: Config, profile: Option<&str>, input: &Path, output: Option<&Path>, spacing: Option<Duration>) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(input).map_err(|err| format!("Could not read {}: {}", input.display(), err))?;
+    let (messages, hex) = if bytes.starts_with(b"MThd") {
+        let events = smf::read(&bytes).map_err(|err| format!("Could not read {}: {}", input.display(), err))?;
+        (events, true)
+    } else {
+        let (bytes, hex) = match parse_hex(&bytes) {
+            Some(parsed) => (parsed, true),
+            None => (bytes, false),
+        };
+        let mut now = Duration::ZERO;
+        let mut timed = vec![];
+        for message in split_messages(&bytes) {
+            timed.push((now, message));
+            now += BYTE_TIME * timed[timed.len() - 1].1.len() as u32;
+        }
+        (timed, hex)
+    };
+    let messages: Vec<(Duration, Vec<u8>)> = match spacing {
+        Some(spacing) => messages.into_iter().enumerate().map(|(i, (_, message))| (spacing * i as u32, message)).collect(),
+        None => messages,
+    };
+    let has_extension = |name| output.is_some_and(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(name)));
+    let smf_out = has_extension("mid");
+    let hex = hex && !has_extension("syx");
+    let out: Box<dyn Write + Send> = match output {
+        Some(path) => Box::new(File::create(path).map_err(|err| format!("Could not create {}: {}", path.display(), err))?),
+        None => {
+            if logging::json() {
+                return Err("replay with --json needs --out, stdout is taken by the events".into());
+            }
+            logging::reserve_stdout();
+            Box::new(stdout())
+        }
+    };
+
+    // The replay runs as fast as it can, so anything timing dependent
+    // would only make the output differ from one run to the next. There is
+    // only the one output, so everything goes there.
+    config.rate_limit = None;
+    config.passthrough_outputs = None;
+    config.tee_outputs = None;
+    let offline = |slider: Slider| slider.with_smoothing(None).with_outputs(None);
+    config.sliders = config.sliders.into_iter().map(offline).collect();
+    for profile in &mut config.profiles {
+        profile.sliders = profile.sliders.drain(..).map(offline).collect();
+    }
+
+    // Everything is recorded into the MIDI file, the messages passed
+    // through included
+    let timeline = Arc::new(Mutex::new(Timeline { now: Duration::ZERO, recording: Recording::new(true) }));
+    let (sink, mut smf_file): (Box<dyn MidiSink>, _) = match smf_out {
+        true => (Box::new(SmfSink(Arc::clone(&timeline))), Some(out)),
+        false => (Box::new(FileSink { out, hex }), None),
+    };
+    let mut mapper = Mapper::new(config, vec![("replay".to_string(), sink)])?;
+    if let Some(name) = profile {
+        mapper.select_profile(name)?;
+    }
+    for (i, (time, message)) in messages.iter().enumerate() {
+        timeline.lock().unwrap().now = *time;
+        mapper.map(message);
+        if messages.len() >= PROGRESS_EVERY && (i + 1) % PROGRESS_EVERY == 0 {
+            info!("Replayed {} of {} messages", i + 1, messages.len());
+        }
+    }
+    mapper.flush();
+    info!("Replayed {} messages", messages.len());
+    crate::report_filtered(&mapper);
+    if let Some(out) = &mut smf_file {
+        let recording = &timeline.lock().unwrap().recording;
+        out.write_all(&recording.to_smf()).map_err(|err| format!("Could not write the MIDI file: {}", err))?;
+        info!("Wrote {} messages into the MIDI file", recording.len());
+    }
+    match mapper.send_errors() {
+        0 => Ok(()),
+        errors => Err(format!("{} messages could not be written", errors).into()),
+    }
+}
+
+/// The bytes of a hex text capture, or None if it isn't one.
+fn parse_hex(contents: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        for token in line.split_whitespace() {
+            let token = token.trim_start_matches("0x");
+            if token.len() > 2 {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(token, 16).ok()?);
+        }
+    }
+    Some(bytes)
+}
+
+fn to_hex(message: &[u8]) -> String {
+    let bytes: Vec<String> = message.iter().map(|byte| format!("{:02X}", byte)).collect();
+    bytes.join(" ")
+}
+
+/// Splits a byte stream into messages the way a MIDI driver hands them
+/// over: SysEx from F0 to F7, channel messages by their status byte
+/// (running status included), and real-time bytes on their own, even in
+/// the middle of another message.
+fn split_messages(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut current = Vec::new();
+    let mut running_status = None;
+    let mut in_sysex = false;
+    for &byte in bytes {
+        match byte {
+            0xf8..=0xff => {
+                messages.push(vec![byte]);
+                continue;
+            }
+            0xf0 => {
+                current = vec![byte];
+                in_sysex = true;
+                running_status = None;
+            }
+            0xf7 if in_sysex => {
+                current.push(byte);
+                messages.push(std::mem::take(&mut current));
+                in_sysex = false;
+                continue;
+            }
+            0x80..=0xf7 => {
+                if in_sysex {
+                    error!("Replay: SysEx cut short by {:02X}", byte);
+                }
+                current = vec![byte];
+                in_sysex = false;
+                running_status = (byte < 0xf0).then_some(byte);
+            }
+            _ if in_sysex => current.push(byte),
+            _ => {
+                if current.is_empty() {
+                    match running_status {
+                        Some(status) => current.push(status),
+                        None => continue,
+                    }
+                }
+                current.push(byte);
+            }
+        }
+        if !in_sysex && current.len() == 1 + data_length(current[0]) {
+            messages.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        error!("Replay: dropping the unfinished message at the end, {:X?}", current);
+    }
+    messages
+}
+
+fn data_length(status: u8) -> usize {
+    match status {
+        0x80..=0xbf | 0xe0..=0xef | 0xf2 => 2,
+        0xc0..=0xdf | 0xf1 | 0xf3 => 1,
+        _ => 0,
+    }
+}
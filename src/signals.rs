@@ -0,0 +1,70 @@
+// SIGINT and SIGTERM end forwarding the same way as typing quit does,
+// so that the connections are closed properly, and SIGHUP reloads the
+// config. A signal handler can do next to nothing safely, so it only writes
+// the signal number into a pipe, and a thread passes it on from there to the
+// main thread as an event.
+
+use pg1000cc::status::Event;
+use std::io;
+use std::sync::mpsc::Sender;
+
+#[cfg(unix)]
+pub fn forward(events: Sender<Event>) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle(signal: libc::c_int) {
+        let byte = signal as u8;
+        // Nothing to be done if this fails, and write is one of the few
+        // calls that are allowed in a signal handler
+        unsafe { libc::write(PIPE.load(Ordering::Relaxed), &byte as *const u8 as *const libc::c_void, 1) };
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    PIPE.store(fds[1], Ordering::Relaxed);
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // Reading stdin on the other thread just carries on
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    let mut pipe = unsafe { File::from_raw_fd(fds[0]) };
+    std::thread::spawn(move || {
+        let mut byte = [0u8];
+        while pipe.read_exact(&mut byte).is_ok() {
+            let event = match byte[0] as libc::c_int {
+                libc::SIGHUP => Event::Reload,
+                signal => {
+                    // Should closing the connections hang, a second one
+                    // kills the process the usual way
+                    unsafe { libc::signal(signal, libc::SIG_DFL) };
+                    Event::Signal(if signal == libc::SIGINT { "SIGINT" } else { "SIGTERM" })
+                }
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Elsewhere Ctrl-C just ends the process.
+#[cfg(not(unix))]
+pub fn forward(_events: Sender<Event>) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,297 @@
+// Recording what is sent to the output into a Standard MIDI File (type 0,
+// a single track). The events are kept in memory with the time they were
+// sent and written out in one go at the end. Files are read back too, for
+// replaying a recording of the PG-1000 with its timing.
+
+use crate::clock::Instant;
+use std::time::Duration;
+
+// 120 bpm at 480 ticks per quarter note makes a tick just over a millisecond
+const TICKS_PER_QUARTER: u16 = 480;
+const MICROS_PER_QUARTER: u32 = 500_000;
+
+pub struct Recording {
+    start: Instant,
+    /// Whether the messages passed through are recorded too, besides the
+    /// translated ones.
+    pub passthrough: bool,
+    events: Vec<(Duration, Vec<u8>)>,
+}
+
+impl Recording {
+    pub fn new(passthrough: bool) -> Self {
+        Self::started(Instant::now(), passthrough)
+    }
+
+    /// A recording whose times count from `start`.
+    pub fn started(start: Instant, passthrough: bool) -> Self {
+        Self { start, passthrough, events: vec![] }
+    }
+
+    /// Keeps a message with the time it was sent, `now`. Real-time and
+    /// system common messages have no place in a file, so they are left out.
+    /// A time before that of the last message kept is taken as that one,
+    /// as messages stamped when they came in can be sent after others.
+    pub fn record(&mut self, now: Instant, message: &[u8]) {
+        let last = self.events.last().map_or(Duration::ZERO, |(time, _)| *time);
+        self.record_at(now.saturating_duration_since(self.start).max(last), message);
+    }
+
+    /// The same at a given time from the start, for a replay that has a
+    /// timing of its own. The times have to come in order.
+    pub fn record_at(&mut self, time: Duration, message: &[u8]) {
+        match message.first() {
+            Some(0xf1..=0xf6 | 0xf8..=0xff) | None => (),
+            Some(_) => self.events.push((time, message.to_vec())),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn to_smf(&self) -> Vec<u8> {
+        let mut track = vec![];
+        // Tempo, so that the ticks mean what they were recorded as
+        track.extend_from_slice(&[0x00, 0xff, 0x51, 0x03]);
+        track.extend_from_slice(&MICROS_PER_QUARTER.to_be_bytes()[1..]);
+        let mut last_tick = 0;
+        for (time, message) in &self.events {
+            let tick = ticks(*time);
+            push_varlen(&mut track, tick - last_tick);
+            last_tick = tick;
+            match message[0] {
+                // A SysEx event has its length after the F0. A piece of
+                // one passed through in parts goes in an F7 escape event,
+                // which is the same but keeps the byte that follows
+                0xf0 => {
+                    track.push(0xf0);
+                    push_varlen(&mut track, message.len() as u64 - 1);
+                    track.extend_from_slice(&message[1..]);
+                }
+                0x00..=0x7f | 0xf7 => {
+                    track.push(0xf7);
+                    push_varlen(&mut track, message.len() as u64);
+                    track.extend_from_slice(message);
+                }
+                _ => track.extend_from_slice(message),
+            }
+        }
+        track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        let mut smf = b"MThd".to_vec();
+        smf.extend_from_slice(&6u32.to_be_bytes());
+        smf.extend_from_slice(&0u16.to_be_bytes());
+        smf.extend_from_slice(&1u16.to_be_bytes());
+        smf.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        smf.extend(track);
+        smf
+    }
+}
+
+/// The MIDI messages of a file, all tracks merged, with their time from the
+/// start. Meta events are left out, apart from the tempo changes that the
+/// times are worked out with.
+pub fn read(bytes: &[u8]) -> Result<Vec<(Duration, Vec<u8>)>, String> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err("not a MIDI file".to_string());
+    }
+    let header_length = reader.u32()? as usize;
+    let header = reader.take(header_length)?;
+    if header.len() < 6 {
+        return Err("the MIDI file header is too short".to_string());
+    }
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 {
+        return Err("MIDI files with SMPTE time aren't supported".to_string());
+    }
+    // (tick, order in the file, message), and where the tempo changes
+    let mut events = vec![];
+    let mut tempos = vec![];
+    while reader.at < bytes.len() {
+        let id = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let track = reader.take(length)?;
+        if id == b"MTrk" {
+            read_track(track, &mut events, &mut tempos)?;
+        }
+    }
+    // Stable, so that what happens at the same tick stays in file order
+    events.sort_by_key(|(tick, _)| *tick);
+    tempos.sort_by_key(|(tick, _)| *tick);
+
+    let mut tempo = (0, Duration::ZERO, MICROS_PER_QUARTER);
+    let mut tempos = tempos.into_iter().peekable();
+    let mut timed = vec![];
+    for (tick, message) in events {
+        while let Some((start, micros)) = tempos.next_if(|(start, _)| *start <= tick) {
+            tempo = (start, at_tick(tempo, start, division), micros);
+        }
+        timed.push((at_tick(tempo, tick, division), message));
+    }
+    Ok(timed)
+}
+
+/// The time of a tick, from the last tempo change before it.
+fn at_tick((start, time, micros): (u64, Duration, u32), tick: u64, division: u16) -> Duration {
+    time + Duration::from_micros((tick - start) * micros as u64 / division.max(1) as u64)
+}
+
+fn read_track(track: &[u8], events: &mut Vec<(u64, Vec<u8>)>, tempos: &mut Vec<(u64, u32)>) -> Result<(), String> {
+    let mut reader = Reader { bytes: track, at: 0 };
+    let mut tick = 0;
+    let mut running_status = None;
+    while reader.at < track.len() {
+        tick += reader.varlen()?;
+        let status = match reader.peek()? {
+            status @ 0x80..=0xff => {
+                reader.at += 1;
+                status
+            }
+            _ => running_status.ok_or("running status without a status byte before it")?,
+        };
+        match status {
+            0xff => {
+                let kind = reader.take(1)?[0];
+                let length = reader.varlen()? as usize;
+                let data = reader.take(length)?;
+                if kind == 0x51 && length == 3 {
+                    tempos.push((tick, u32::from_be_bytes([0, data[0], data[1], data[2]])));
+                }
+            }
+            0xf0 | 0xf7 => {
+                let length = reader.varlen()? as usize;
+                let data = reader.take(length)?;
+                let mut message = if status == 0xf0 { vec![0xf0] } else { vec![] };
+                message.extend_from_slice(data);
+                events.push((tick, message));
+            }
+            0x80..=0xef => {
+                running_status = Some(status);
+                let data = reader.take(if (0xc0..=0xdf).contains(&status) { 1 } else { 2 })?;
+                events.push((tick, [&[status], data].concat()));
+            }
+            _ => return Err(format!("unexpected status byte {:02X} in a track", status)),
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.at..self.at + count).ok_or("the MIDI file ends in the middle of a chunk")?;
+        self.at += count;
+        Ok(bytes)
+    }
+
+    fn peek(&self) -> Result<u8, String> {
+        self.bytes.get(self.at).copied().ok_or_else(|| "the MIDI file ends in the middle of a chunk".to_string())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn varlen(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.take(1)?[0];
+            value = value << 7 | (byte & 0x7f) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("a variable length number in the MIDI file is too long".to_string())
+    }
+}
+
+fn ticks(time: Duration) -> u64 {
+    (time.as_micros() * TICKS_PER_QUARTER as u128 / MICROS_PER_QUARTER as u128) as u64
+}
+
+/// The variable length quantity of MIDI files: 7 bits per byte, most
+/// significant first, the top bit set on all but the last byte.
+fn push_varlen(out: &mut Vec<u8>, value: u64) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHORUS_RATE_50: [u8; 11] = [0xf0, 0x41, 0x00, 0x14, 0x12, 0x00, 0x01, 0x2b, 0x32, 0x22, 0xf7];
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let mut recording = Recording::new(true);
+        let events: [(u64, &[u8]); 5] = [
+            (0, &[0xb1, 85, 64]),
+            (0, &CHORUS_RATE_50),
+            (250, &[0x90, 60, 100]),
+            (1000, &[0x90, 60, 0]),
+            // A piece of a long SysEx passed through
+            (1500, &[0x01, 0x02, 0xf7]),
+        ];
+        for (millis, message) in events {
+            recording.record_at(Duration::from_millis(millis), message);
+        }
+        // Clock has no place in a file
+        recording.record_at(Duration::from_millis(1500), &[0xf8]);
+        let read = read(&recording.to_smf()).unwrap();
+        let expected: Vec<(Duration, Vec<u8>)> = events.iter().map(|&(millis, message)| (Duration::from_millis(millis), message.to_vec())).collect();
+        // A tick is just over a millisecond, so the times can come out a
+        // bit earlier
+        assert_eq!(read.len(), expected.len());
+        for ((time, message), (expected_time, expected_message)) in read.iter().zip(&expected) {
+            assert_eq!(message, expected_message);
+            assert!(*expected_time - *time < Duration::from_millis(2), "{:?} for {:?}", time, expected_time);
+        }
+    }
+
+    #[test]
+    fn merges_tracks_with_running_status_and_tempo_changes() {
+        // Type 1, 96 ticks per quarter. The first track has the tempo: 120
+        // bpm (500 ms a quarter), then 60 bpm from tick 96 on.
+        let mut smf = b"MThd\0\0\0\x06\0\x01\0\x02\0\x60".to_vec();
+        let tempo_track: &[u8] = &[0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, 0x60, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, 0x00, 0xff, 0x2f, 0x00];
+        // A note on at tick 48 (250 ms), its note off in running status at
+        // 144 (500 ms for the first quarter, 500 for the half after it), a
+        // program change at 192
+        let notes: &[u8] = &[0x30, 0x90, 60, 100, 0x60, 60, 0, 0x30, 0xc0, 5, 0x00, 0xff, 0x2f, 0x00];
+        for track in [tempo_track, notes] {
+            smf.extend_from_slice(b"MTrk");
+            smf.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            smf.extend_from_slice(track);
+        }
+        let read = read(&smf).unwrap();
+        let millis = |ms| Duration::from_millis(ms);
+        assert_eq!(read, [(millis(250), vec![0x90, 60, 100]), (millis(1000), vec![0x90, 60, 0]), (millis(1500), vec![0xc0, 5])]);
+    }
+
+    #[test]
+    fn refuses_what_it_cant_read() {
+        assert!(read(b"RIFF").is_err());
+        assert!(read(b"MThd\0\0\0\x06\0\0\0\x01\xe7\x28").is_err());
+        // A track longer than the file
+        assert!(read(b"MThd\0\0\0\x06\0\0\0\x01\x01\xe0MTrk\0\0\0\x10\0\x90").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+// Statistics of what has gone through the mapper since it started, or since
+// they were last reset: each address received with the range of its values,
+// each CC and pitch bend sent, what the arbitration held off, the repeated
+// values not sent again, what the filter dropped, and what went wrong. Only
+// counting happens
+// in the MIDI callback, the table is put together when asked for.
+
+use crate::clock::Instant;
+use crate::config::Source;
+use crate::filter;
+use crate::{CcId, ControlMessage, MidiValue, PitchBendMessage, SysExId};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// What has arrived for one SysEx address.
+#[derive(Debug, Clone, Copy)]
+pub struct Received {
+    pub count: u64,
+    pub min: MidiValue,
+    pub max: MidiValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
+    since: Instant,
+    received: BTreeMap<SysExId, Received>,
+    // By channel and CC, and pitch bends by channel
+    ccs: BTreeMap<(u8, CcId), u64>,
+    pitch_bends: BTreeMap<u8, u64>,
+    pub passed_through: u64,
+    /// SysEx sent on as well as translated, with tee.
+    pub teed: u64,
+    /// Feedback lit and taken back, not counted with the CCs sent.
+    pub feedback: u64,
+    /// Values outside the slider's sysex_range.
+    pub out_of_range: u64,
+    /// Values held off by the arbitration, by the source they came from.
+    pub suppressed: BTreeMap<Source, u64>,
+    /// Values not sent again because the slider's CC already had them.
+    pub duplicates: u64,
+    /// Messages dropped by the passthrough filter, by their kind.
+    pub filtered: BTreeMap<filter::Kind, u64>,
+    pub parse_failures: u64,
+    pub send_errors: u64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::since(Instant::now())
+    }
+
+    /// Statistics starting at the given time.
+    pub fn since(since: Instant) -> Self {
+        Self {
+            since,
+            received: BTreeMap::new(),
+            ccs: BTreeMap::new(),
+            pitch_bends: BTreeMap::new(),
+            passed_through: 0,
+            teed: 0,
+            feedback: 0,
+            out_of_range: 0,
+            suppressed: BTreeMap::new(),
+            duplicates: 0,
+            filtered: BTreeMap::new(),
+            parse_failures: 0,
+            send_errors: 0,
+        }
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.since)
+    }
+
+    pub fn receive(&mut self, id: SysExId, value: MidiValue) {
+        let received = self.received.entry(id).or_insert(Received { count: 0, min: value, max: value });
+        received.count += 1;
+        received.min = received.min.min(value);
+        received.max = received.max.max(value);
+    }
+
+    /// Counts a CC or pitch bend that went to an output, other messages
+    /// aren't counted.
+    pub fn sent(&mut self, message: &[u8]) {
+        if let Some(cc) = ControlMessage::from_bytes(message) {
+            *self.ccs.entry((cc.channel(), cc.cc())).or_default() += 1;
+        } else if let Some(bend) = PitchBendMessage::from_bytes(message) {
+            *self.pitch_bends.entry(bend.channel()).or_default() += 1;
+        }
+    }
+
+    /// The statistics up to `now` as a table, with the addresses named by
+    /// `label`.
+    pub fn report(&self, now: Instant, label: impl Fn(SysExId) -> String) -> String {
+        let elapsed = self.elapsed(now).as_secs();
+        let mut out = format!("Statistics of the last {}m {}s\n", elapsed / 60, elapsed % 60);
+        if !self.received.is_empty() {
+            let rows: Vec<(SysExId, String, &Received)> = self.received.iter().map(|(id, received)| (*id, label(*id), received)).collect();
+            let width = rows.iter().map(|(_, label, _)| label.chars().count()).chain(["Parameter".len()]).max().unwrap_or_default();
+            out += &format!("{:<8}  {:<width$}  {:>8}  {:>5}  {:>5}\n", "SysEx", "Parameter", "Received", "Min", "Max");
+            for (id, label, received) in rows {
+                out += &format!("0x{:06X}  {:<width$}  {:>8}  {:>5}  {:>5}\n", id, label, received.count, received.min, received.max);
+            }
+        }
+        if !self.ccs.is_empty() || !self.pitch_bends.is_empty() {
+            out += &format!("{:<7}  {:<10}  {:>8}\n", "Channel", "Sent", "Messages");
+            for ((channel, cc), count) in &self.ccs {
+                out += &format!("{:<7}  {:<10}  {:>8}\n", channel + 1, format!("CC {}", cc), count);
+            }
+            for (channel, count) in &self.pitch_bends {
+                out += &format!("{:<7}  {:<10}  {:>8}\n", channel + 1, "pitch bend", count);
+            }
+        }
+        if !self.suppressed.is_empty() {
+            let sources: Vec<String> = self.suppressed.iter().map(|(source, count)| format!("{} from {}", count, source)).collect();
+            out += &format!("Held off by the arbitration: {}\n", sources.join(", "));
+        }
+        if !self.filtered.is_empty() {
+            let kinds: Vec<String> = self.filtered.iter().map(|(kind, count)| format!("{} {}", count, kind)).collect();
+            out += &format!("Filtered: {}\n", kinds.join(", "));
+        }
+        out += &format!(
+            "{} passed through, {} teed, {} feedback, {} out of range, {} duplicates suppressed, {} parse failures, {} send errors",
+            self.passed_through, self.teed, self.feedback, self.out_of_range, self.duplicates, self.parse_failures, self.send_errors
+        );
+        out
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
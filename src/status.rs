@@ -0,0 +1,131 @@
+// Live status display: the mapped sliders that have moved, with their last
+// values, redrawn in place with ANSI escapes. The MIDI callback only sends
+// updates down a channel; drawing happens on the main thread.
+
+use crate::clock::Instant;
+use crate::config::Source;
+use crate::{MidiValue, SysExId};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct SliderUpdate {
+    pub id: SysExId,
+    pub label: String,
+    pub sysex_value: MidiValue,
+    pub cc_value: MidiValue,
+    /// The source holding the slider, with arbitration.
+    pub owner: Option<Source>,
+    // Totals so far, over all sliders
+    pub sysex_events: u64,
+    pub cc_events: u64,
+}
+
+/// What the main thread waits for.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Update(SliderUpdate),
+    /// A "quit" typed on stdin, which ends forwarding.
+    Quit,
+    InputClosed,
+    /// A "reload" typed on stdin, or SIGHUP.
+    Reload,
+    /// SIGINT or SIGTERM, by name.
+    Signal(&'static str),
+    /// Something to tell the user.
+    Message(String),
+    /// A "sync" typed on stdin.
+    Sync,
+    /// A "resend" typed on stdin.
+    Resend,
+    /// A "startup" typed on stdin.
+    Startup,
+    /// A "profile <name>" typed on stdin.
+    Profile(String),
+    /// A "channel <n>" typed on stdin, as sent on the wire.
+    Channel(u8),
+    /// An "output add" typed on stdin: a virtual port with the name, or
+    /// the existing port it matches, and whether to prime it.
+    AddOutput { virtual_port: bool, name: String, prime: bool },
+    /// An "output remove <name>" typed on stdin.
+    RemoveOutput(String),
+    /// A "mute" (true) or "unmute" typed on stdin.
+    Mute(bool),
+    /// A "stats" typed on stdin, or the time for the next --stats-interval.
+    Stats,
+    /// A "stats reset" typed on stdin.
+    ResetStats,
+    /// Sending to the output port keeps failing.
+    OutputFailing(String),
+}
+
+pub struct StatusView {
+    header: String,
+    rows: BTreeMap<SysExId, SliderUpdate>,
+    sysex_events: u64,
+    cc_events: u64,
+    message: String,
+    dirty: bool,
+    last_draw: Option<Instant>,
+}
+
+impl StatusView {
+    // Drawing more often than this would only cost time
+    pub const REFRESH: Duration = Duration::from_millis(50);
+
+    pub fn new(header: String) -> Self {
+        Self {
+            header,
+            rows: BTreeMap::new(),
+            sysex_events: 0,
+            cc_events: 0,
+            message: String::new(),
+            dirty: true,
+            last_draw: None,
+        }
+    }
+
+    pub fn update(&mut self, update: SliderUpdate) {
+        self.sysex_events = update.sysex_events;
+        self.cc_events = update.cc_events;
+        self.rows.insert(update.id, update);
+        self.dirty = true;
+    }
+
+    /// A line shown under the table, e.g. the result of a reload.
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+        self.dirty = true;
+    }
+
+    /// Whether there is something to draw that had to wait for `REFRESH`.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Redraws if something changed and the last redraw was long enough ago.
+    pub fn draw(&mut self) -> io::Result<()> {
+        if !self.dirty || self.last_draw.is_some_and(|at| at.elapsed() < Self::REFRESH) {
+            return Ok(());
+        }
+        let mut out = String::from("\x1b[H\x1b[J");
+        out += &format!("{}\n\n", self.header);
+        out += &format!("{:<8} {:<40} {:>5} {:>6}  {}\n", "SysEx", "Parameter", "Value", "CC", "Owner");
+        for row in self.rows.values() {
+            let owner = row.owner.map_or("", |owner| owner.name());
+            out += &format!("0x{:06X} {:<40} {:>5} {:>6}  {}\n", row.id, row.label, row.sysex_value, row.cc_value, owner);
+        }
+        out += &format!("\n{} SysEx messages in, {} CCs out\n", self.sysex_events, self.cc_events);
+        if !self.message.is_empty() {
+            out += &format!("{}\n", self.message);
+        }
+        out += "Type help for the commands, or quit to exit\n";
+        let mut stdout = io::stdout();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()?;
+        self.dirty = false;
+        self.last_draw = Some(Instant::now());
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+// The notes of a keyboard merged in from another input, changed on their
+// way through: transposed, with their velocity reshaped, and those outside
+// a span of keys dropped. Only note ons and note offs are touched, and only
+// those passed through, so never what the PG-1000 sends.
+
+use std::fmt;
+
+/// What is done to the note on velocities of an input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VelocityCurve {
+    /// Softly played notes come out louder.
+    Soft,
+    /// It takes playing harder to get loud.
+    Hard,
+    /// Every note on gets this velocity, 1-127.
+    Fixed(u8),
+}
+
+impl VelocityCurve {
+    /// soft, hard or fixed(n).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid velocity_curve '{}', expected soft, hard or fixed(1-127)", text);
+        match text {
+            "soft" => Ok(Self::Soft),
+            "hard" => Ok(Self::Hard),
+            _ => match text.strip_prefix("fixed(").and_then(|rest| rest.strip_suffix(')')).map(|n| n.trim().parse::<u8>()) {
+                Some(Ok(velocity @ 1..=127)) => Ok(Self::Fixed(velocity)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+
+    /// The velocity of a note on, never 0, which would make it a note off.
+    pub fn apply(&self, velocity: u8) -> u8 {
+        let exponent = match self {
+            Self::Soft => 0.5,
+            Self::Hard => 2.0,
+            Self::Fixed(velocity) => return *velocity,
+        };
+        let shaped = 127.0 * (velocity as f64 / 127.0).powf(exponent);
+        (shaped.round() as u8).clamp(1, 127)
+    }
+}
+
+impl fmt::Display for VelocityCurve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Soft => write!(f, "soft"),
+            Self::Hard => write!(f, "hard"),
+            Self::Fixed(velocity) => write!(f, "fixed({})", velocity),
+        }
+    }
+}
+
+/// The changes made to the notes of one input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteTransform {
+    /// The input, an index from 0 into the order of the --input options.
+    pub input: usize,
+    /// Semitones, down when negative.
+    pub transpose: i8,
+    pub velocity_curve: Option<VelocityCurve>,
+    /// The lowest and highest key played that is passed through, before
+    /// transposing. None for all of them.
+    pub note_range: Option<(u8, u8)>,
+}
+
+impl NoteTransform {
+    pub fn new(input: usize) -> Self {
+        Self { input, transpose: 0, velocity_curve: None, note_range: None }
+    }
+
+    // The note a key is sent as, None to drop it
+    fn note(&self, key: u8) -> Option<u8> {
+        if let Some((lo, hi)) = self.note_range {
+            if key < lo || key > hi {
+                return None;
+            }
+        }
+        u8::try_from(key as i16 + self.transpose as i16).ok().filter(|note| *note <= 127)
+    }
+}
+
+/// The notes of one input that are sounding, with what their note on was
+/// sent as, so that each note off goes out as its note on did even when the
+/// transform has changed in between.
+#[derive(Debug, Clone)]
+pub struct SoundingNotes {
+    // For each channel and key, the note sent for it, Some(None) when its
+    // note on was dropped, and None when it isn't sounding
+    sent: [[Option<Option<u8>>; 128]; 16],
+}
+
+impl Default for SoundingNotes {
+    fn default() -> Self {
+        Self { sent: [[None; 128]; 16] }
+    }
+}
+
+impl SoundingNotes {
+    /// The message with `transform` applied, written into `buffer`, or as
+    /// it is when it isn't a note on or off. None to drop it. A note off
+    /// goes out on the note its note on went out on, or is dropped with it;
+    /// one without a note on seen gets the transform as it is now.
+    pub fn apply<'a>(&mut self, transform: Option<&NoteTransform>, message: &'a [u8], buffer: &'a mut [u8; 3]) -> Option<&'a [u8]> {
+        let (status, key, velocity) = match *message {
+            [status @ 0x80..=0x9f, key @ 0..=0x7f, velocity @ 0..=0x7f] => (status, key, velocity),
+            _ => return Some(message),
+        };
+        let sent = &mut self.sent[(status & 0x0f) as usize][key as usize];
+        let note_on = status & 0xf0 == 0x90 && velocity != 0;
+        let (note, velocity) = match transform {
+            _ if !note_on && sent.is_some() => (sent.take().flatten(), velocity),
+            None => {
+                *sent = note_on.then_some(Some(key));
+                return Some(message);
+            }
+            Some(transform) => {
+                let note = transform.note(key);
+                let velocity = match (note_on, transform.velocity_curve) {
+                    (true, Some(curve)) => curve.apply(velocity),
+                    _ => velocity,
+                };
+                if note_on {
+                    *sent = Some(note);
+                }
+                (note, velocity)
+            }
+        };
+        *buffer = [status, note?, velocity];
+        Some(&buffer[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(notes: &mut SoundingNotes, transform: &NoteTransform, message: &[u8]) -> Option<Vec<u8>> {
+        notes.apply(Some(transform), message, &mut [0; 3]).map(<[u8]>::to_vec)
+    }
+
+    #[test]
+    fn sends_a_held_note_off_as_its_note_on_went_out() {
+        let mut notes = SoundingNotes::default();
+        let mut transform = NoteTransform { transpose: 12, ..NoteTransform::new(1) };
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 60, 100]), Some(vec![0x90, 72, 100]));
+        assert_eq!(applied(&mut notes, &transform, &[0x91, 60, 100]), Some(vec![0x91, 72, 100]));
+        // Transposed the other way while both are held
+        transform.transpose = -5;
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 60, 64]), Some(vec![0x80, 72, 64]));
+        // A note on with velocity 0 is a note off too
+        assert_eq!(applied(&mut notes, &transform, &[0x91, 60, 0]), Some(vec![0x91, 72, 0]));
+        // Played again, it's the new transposition, and so is its note off
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 60, 100]), Some(vec![0x90, 55, 100]));
+        transform.transpose = 0;
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 60, 0]), Some(vec![0x80, 55, 0]));
+        // A note off without a note on seen gets the transform as it is
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 61, 0]), Some(vec![0x80, 61, 0]));
+    }
+
+    #[test]
+    fn pairs_the_notes_held_when_a_transform_comes_or_goes() {
+        let mut notes = SoundingNotes::default();
+        let transform = NoteTransform { transpose: 7, ..NoteTransform::new(0) };
+        assert_eq!(notes.apply(None, &[0x90, 60, 100], &mut [0; 3]), Some(&[0x90, 60, 100][..]));
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 60, 0]), Some(vec![0x80, 60, 0]));
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 62, 100]), Some(vec![0x90, 69, 100]));
+        assert_eq!(notes.apply(None, &[0x80, 62, 0], &mut [0; 3]), Some(&[0x80, 69, 0][..]));
+    }
+
+    #[test]
+    fn drops_the_notes_outside_the_range_with_their_note_offs() {
+        let mut notes = SoundingNotes::default();
+        let mut transform = NoteTransform { transpose: 12, note_range: Some((36, 96)), ..NoteTransform::new(0) };
+        // The range is of the keys played, before transposing
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 96, 100]), Some(vec![0x90, 108, 100]));
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 97, 100]), None);
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 35, 100]), None);
+        // Its note off goes with it, even once the range takes it in
+        transform.note_range = None;
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 97, 0]), None);
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 96, 0]), Some(vec![0x80, 108, 0]));
+        // Transposed off the end of the MIDI notes
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 120, 100]), None);
+        let down = NoteTransform { transpose: -12, ..NoteTransform::new(0) };
+        assert_eq!(applied(&mut notes, &down, &[0x90, 11, 100]), None);
+        assert_eq!(applied(&mut notes, &down, &[0x90, 12, 100]), Some(vec![0x90, 0, 100]));
+    }
+
+    #[test]
+    fn keeps_the_ends_of_the_velocity_curves() {
+        for curve in [VelocityCurve::Soft, VelocityCurve::Hard] {
+            assert_eq!(curve.apply(127), 127, "{}", curve);
+        }
+        // Never 0, a note off
+        assert_eq!(VelocityCurve::Soft.apply(1), 11);
+        assert_eq!(VelocityCurve::Hard.apply(1), 1);
+        assert_eq!(VelocityCurve::Soft.apply(64), 90);
+        assert_eq!(VelocityCurve::Hard.apply(64), 32);
+        assert_eq!(VelocityCurve::Fixed(100).apply(1), 100);
+        assert_eq!(VelocityCurve::Fixed(100).apply(127), 100);
+        // Only on note ons, a note off's release velocity is left alone
+        let mut notes = SoundingNotes::default();
+        let transform = NoteTransform { velocity_curve: Some(VelocityCurve::Fixed(100)), ..NoteTransform::new(0) };
+        assert_eq!(applied(&mut notes, &transform, &[0x90, 60, 1]), Some(vec![0x90, 60, 100]));
+        assert_eq!(applied(&mut notes, &transform, &[0x80, 60, 20]), Some(vec![0x80, 60, 20]));
+        // Nor anything that isn't a note
+        assert_eq!(applied(&mut notes, &transform, &[0xa0, 60, 1]), Some(vec![0xa0, 60, 1]));
+    }
+
+    #[test]
+    fn parses_what_it_prints() {
+        for text in ["soft", "hard", "fixed(1)", "fixed(127)"] {
+            assert_eq!(VelocityCurve::parse(text).unwrap().to_string(), text);
+        }
+        for invalid in ["fixed(0)", "fixed(128)", "fixed", "medium"] {
+            assert!(VelocityCurve::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
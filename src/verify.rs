@@ -0,0 +1,279 @@
+// Verify mode: steps through the mapped controls by name, checking that
+// moving each one on the PG-1000 sends the address it should, over the
+// whole of its sysex_range. A worn slider shows up as not reaching an end,
+// and the range it does reach can be saved as its sysex_range.
+
+use pg1000cc::config::Config;
+use pg1000cc::logging::json_string;
+use pg1000cc::{parameters, MidiRange, MidiValue, Slider, SysExId, DATA_MAX};
+use midir::{MidiInput, MidiInputPort};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{stdin, stdout, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How long each control waits for enter before the next one comes by itself
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The values that came from one address while a control was moved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Seen {
+    lo: MidiValue,
+    hi: MidiValue,
+    count: usize,
+}
+
+impl Seen {
+    fn merge(self, other: Seen) -> Self {
+        Self { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi), count: self.count + other.count }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Ok,
+    /// Nothing came in while it was to be moved.
+    NoEvents,
+    /// Only other addresses came in, this one the most.
+    WrongAddress(SysExId),
+    /// It only got this far of its sysex_range.
+    ShortRange(MidiValue, MidiValue),
+    Skipped,
+}
+
+/// One control and how it did.
+#[derive(Debug, Clone)]
+pub struct Checked {
+    slider: Slider,
+    name: String,
+    seen: Option<Seen>,
+    verdict: Verdict,
+    timed_out: bool,
+}
+
+/// Runs verify mode on the given input, connecting as `port_name`, over
+/// the sliders of `config` in their order.
+pub fn verify(midi_in: MidiInput, port: &MidiInputPort, port_name: &str, config: &Config) -> Result<Vec<Checked>, Box<dyn Error>> {
+    let captured = Arc::new(Mutex::new(BTreeMap::<SysExId, Seen>::new()));
+    let callback_captured = Arc::clone(&captured);
+    let device = config.device.clone();
+    let _conn = midi_in.connect(
+        port,
+        port_name,
+        move |_, message, _| {
+            if let Ok(sysex) = device.parse(message) {
+                let value = sysex.value();
+                let seen = Seen { lo: value, hi: value, count: 1 };
+                callback_captured.lock().unwrap().entry(sysex.id()).and_modify(|old| *old = old.merge(seen)).or_insert(seen);
+            }
+        },
+        (),
+    )?;
+    // Read apart, so that a step can time out waiting for it
+    let (lines_tx, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stdin().lines().map_while(Result::ok) {
+            if lines_tx.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    println!("Verify mode, {} controls", config.sliders.len());
+    println!("Move each control on the {} from end to end, then press enter.", config.device);
+    println!("s skips a control, r starts it over, q ends here.\n");
+    let mut checked = vec![];
+    let mut quit = false;
+    for (i, slider) in config.sliders.iter().enumerate() {
+        let name = slider.label(&config.device);
+        // The PG-1000 sends the other tone's address with LOWER selected
+        let mut ids = vec![slider.sysex_id()];
+        ids.extend(parameters::other_tone(slider.sysex_id()).filter(|_| config.device.d50));
+        let mut step = Checked { slider: slider.clone(), name, seen: None, verdict: Verdict::Skipped, timed_out: false };
+        while !quit {
+            captured.lock().unwrap().clear();
+            print!("[{}/{}] {} (0x{:06X}): ", i + 1, config.sliders.len(), step.name, slider.sysex_id());
+            stdout().flush()?;
+            match lines.recv_timeout(STEP_TIMEOUT) {
+                Ok(line) => match line.trim() {
+                    "s" => break,
+                    "r" => continue,
+                    "q" => quit = true,
+                    _ => (),
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    println!("timed out");
+                    step.timed_out = true;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err("standard input closed".into()),
+            }
+            if quit {
+                break;
+            }
+            (step.verdict, step.seen) = verdict(&ids, slider.sysex_range(), &captured.lock().unwrap());
+            println!("  {}", describe(&step));
+            break;
+        }
+        checked.push(step);
+    }
+    Ok(checked)
+}
+
+/// What the values seen while moving the control of `ids` (its address,
+/// and the other tone's) say about it, with what came from it.
+fn verdict(ids: &[SysExId], range: &MidiRange, seen: &BTreeMap<SysExId, Seen>) -> (Verdict, Option<Seen>) {
+    let own = ids.iter().filter_map(|id| seen.get(id).copied()).reduce(Seen::merge);
+    // The ends of the range, whichever way round it runs
+    let (lo, hi) = (range.clamp(0), range.clamp(DATA_MAX));
+    let verdict = match own {
+        Some(own) if own.lo <= lo && own.hi >= hi => Verdict::Ok,
+        Some(own) => Verdict::ShortRange(own.lo, own.hi),
+        None => match seen.iter().max_by_key(|(_, seen)| seen.count) {
+            Some((other, _)) => Verdict::WrongAddress(*other),
+            None => Verdict::NoEvents,
+        },
+    };
+    (verdict, own)
+}
+
+fn describe(step: &Checked) -> String {
+    let range = step.slider.sysex_range();
+    let (lo, hi) = (range.clamp(0), range.clamp(DATA_MAX));
+    let timed_out = if step.timed_out { " (timed out)" } else { "" };
+    match &step.verdict {
+        Verdict::Ok => format!("OK, {}-{}", lo, hi),
+        Verdict::NoEvents => format!("nothing came from it{}", timed_out),
+        Verdict::WrongAddress(other) => format!("sent 0x{:06X} instead", other),
+        Verdict::ShortRange(seen_lo, seen_hi) => format!("only reached {}-{} of {}-{}{}", seen_lo, seen_hi, lo, hi, timed_out),
+        Verdict::Skipped => "skipped".to_string(),
+    }
+}
+
+/// The controls one per line with how they did, and how many need a look.
+pub fn report(checked: &[Checked]) -> String {
+    let mut out = String::new();
+    for step in checked {
+        out += &format!("  0x{:06X} {:<36} {}\n", step.slider.sysex_id(), step.name, describe(step));
+    }
+    let ok = checked.iter().filter(|step| step.verdict == Verdict::Ok).count();
+    let skipped = checked.iter().filter(|step| step.verdict == Verdict::Skipped).count();
+    out += &format!("{} of {} controls OK, {} to look at, {} skipped", ok, checked.len(), checked.len() - ok - skipped, skipped);
+    out
+}
+
+/// The same as a JSON object, for keeping or comparing later.
+pub fn to_json(checked: &[Checked]) -> String {
+    let controls: Vec<String> = checked
+        .iter()
+        .map(|step| {
+            let range = step.slider.sysex_range();
+            let (verdict, address) = match step.verdict {
+                Verdict::Ok => ("ok", None),
+                Verdict::NoEvents => ("no_events", None),
+                Verdict::WrongAddress(other) => ("wrong_address", Some(other)),
+                Verdict::ShortRange(..) => ("short_range", None),
+                Verdict::Skipped => ("skipped", None),
+            };
+            let mut out = format!(
+                "{{\"sysex_id\":\"0x{:06X}\",\"name\":{},\"verdict\":\"{}\",\"expected\":[{},{}]",
+                step.slider.sysex_id(),
+                json_string(&step.name),
+                verdict,
+                range.clamp(0),
+                range.clamp(DATA_MAX)
+            );
+            if let Some(seen) = step.seen {
+                out += &format!(",\"reached\":[{},{}],\"messages\":{}", seen.lo, seen.hi, seen.count);
+            }
+            if let Some(address) = address {
+                out += &format!(",\"address\":\"0x{:06X}\"", address);
+            }
+            if step.timed_out {
+                out += ",\"timed_out\":true";
+            }
+            out + "}"
+        })
+        .collect();
+    format!("{{\"controls\":[{}]}}\n", controls.join(","))
+}
+
+/// Narrows the sysex_range of each slider of `config` that didn't reach an
+/// end to what it did reach, so that its CCs still go end to end. Returns
+/// how many there were.
+pub fn calibrate(config: &mut Config, checked: &[Checked]) -> usize {
+    let mut calibrated = 0;
+    for step in checked {
+        let (lo, hi) = match step.verdict {
+            Verdict::ShortRange(lo, hi) if lo < hi => (lo, hi),
+            _ => continue,
+        };
+        if let Some(slider) = config.sliders.iter_mut().find(|slider| slider.sysex_id() == step.slider.sysex_id()) {
+            let range = if slider.sysex_range().is_inverted() { MidiRange::new(hi, lo) } else { MidiRange::new(lo, hi) };
+            *slider = slider.clone().with_sysex_range(range);
+            calibrated += 1;
+        }
+    }
+    calibrated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHORUS_RATE: SysExId = 0x00012b;
+    const LOWER_CHORUS_RATE: SysExId = 0x01012b;
+
+    fn seen(values: &[(SysExId, MidiValue, MidiValue, usize)]) -> BTreeMap<SysExId, Seen> {
+        values.iter().map(|&(id, lo, hi, count)| (id, Seen { lo, hi, count })).collect()
+    }
+
+    fn checked(verdict: Verdict, seen: Option<Seen>) -> Checked {
+        let slider = Slider::new(CHORUS_RATE, 85, MidiRange::new(0, 100), MidiRange::new(0, 127));
+        Checked { slider, name: "Upper Common Chorus Rate".to_string(), seen, verdict, timed_out: false }
+    }
+
+    #[test]
+    fn tells_what_is_wrong_with_a_control() {
+        let ids = [CHORUS_RATE, LOWER_CHORUS_RATE];
+        let range = MidiRange::new(0, 100);
+        let cases = [
+            (seen(&[(CHORUS_RATE, 0, 100, 40)]), Verdict::Ok),
+            // Past the ends is fine, as is half of it with LOWER selected
+            (seen(&[(CHORUS_RATE, 0, 60, 20), (LOWER_CHORUS_RATE, 50, 127, 20)]), Verdict::Ok),
+            (seen(&[(CHORUS_RATE, 3, 97, 40)]), Verdict::ShortRange(3, 97)),
+            (seen(&[]), Verdict::NoEvents),
+            (seen(&[(0x000110, 0, 100, 5), (0x000111, 0, 100, 30)]), Verdict::WrongAddress(0x000111)),
+            // Another control touched on the way doesn't matter
+            (seen(&[(CHORUS_RATE, 0, 100, 40), (0x000111, 10, 12, 3)]), Verdict::Ok),
+        ];
+        for (seen, expected) in cases {
+            assert_eq!(verdict(&ids, &range, &seen).0, expected, "{:?}", seen);
+        }
+        // An inverted range has the same ends
+        assert_eq!(verdict(&ids, &range.inverted(), &seen(&[(CHORUS_RATE, 0, 99, 40)])).0, Verdict::ShortRange(0, 99));
+        assert_eq!(verdict(&ids, &range, &seen(&[(CHORUS_RATE, 3, 97, 40)])).1, Some(Seen { lo: 3, hi: 97, count: 40 }));
+    }
+
+    #[test]
+    fn reports_and_calibrates_what_it_found() {
+        let short = checked(Verdict::ShortRange(3, 97), Some(Seen { lo: 3, hi: 97, count: 40 }));
+        let gone = Checked { timed_out: true, ..checked(Verdict::NoEvents, None) };
+        let steps = [short, gone, checked(Verdict::Ok, Some(Seen { lo: 0, hi: 100, count: 9 })), checked(Verdict::Skipped, None)];
+        assert_eq!(report(&steps).lines().last(), Some("1 of 4 controls OK, 2 to look at, 1 skipped"));
+        assert!(report(&steps).contains("only reached 3-97 of 0-100"));
+        assert!(report(&steps).contains("nothing came from it (timed out)"));
+        let json = to_json(&steps[..2]);
+        assert_eq!(
+            json,
+            "{\"controls\":[{\"sysex_id\":\"0x00012B\",\"name\":\"Upper Common Chorus Rate\",\"verdict\":\"short_range\",\"expected\":[0,100],\"reached\":[3,97],\"messages\":40},\
+             {\"sysex_id\":\"0x00012B\",\"name\":\"Upper Common Chorus Rate\",\"verdict\":\"no_events\",\"expected\":[0,100],\"timed_out\":true}]}\n"
+        );
+        let mut config = Config::parse("sliders:\n  - sysex_id: 0x00012B\n    cc_id: 85\n  - sysex_id: 0x00012C\n    cc_id: 86\n").unwrap();
+        assert_eq!(calibrate(&mut config, &steps), 1);
+        assert_eq!(config.sliders[0].sysex_range(), &MidiRange::new(3, 97));
+        assert_eq!(config.sliders[1].sysex_range(), &MidiRange::new(0, 100));
+        config.validate().unwrap();
+    }
+}
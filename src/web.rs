@@ -0,0 +1,103 @@
+// The browser build, on Web MIDI. There is no stdin to pick the ports
+// from, so the page lists them with `ports` once the browser has granted
+// MIDI access, and calls `start` with the ones to use and the config.
+//
+// The output connection isn't Send in the browser, so it can't move into
+// the input callback along with the mapper. Everything happens on the one
+// thread anyway, so both stay in thread locals there, and the mapper
+// reaches the connection through `PageOutput`. There are no threads for
+// the rate limit and smoothing either, the page calls `tick` for them.
+
+use crate::select_port;
+use midir::{MidiIO, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection, SendError};
+use pg1000cc::config::Config;
+use pg1000cc::{Mapper, MidiSink};
+use std::cell::RefCell;
+use std::fmt::Display;
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    static OUTPUT: RefCell<Option<MidiOutputConnection>> = const { RefCell::new(None) };
+    static MAPPER: RefCell<Option<Mapper>> = const { RefCell::new(None) };
+    static INPUT: RefCell<Option<MidiInputConnection<()>>> = const { RefCell::new(None) };
+}
+
+struct PageOutput;
+
+impl MidiSink for PageOutput {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        OUTPUT.with(|output| match output.borrow_mut().as_mut() {
+            Some(output) => output.send(message),
+            None => Err(SendError::Other("the output port is closed")),
+        })
+    }
+}
+
+fn js_error(err: impl Display) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn port_names<T: MidiIO>(midi_io: &T) -> Vec<String> {
+    midi_io.ports().iter().filter_map(|port| midi_io.port_name(port).ok()).collect()
+}
+
+/// The input port names, a tab, and the output port names, each separated
+/// by newlines. Both are empty until the browser has granted MIDI access,
+/// which the first call asks for.
+#[wasm_bindgen]
+pub fn ports() -> Result<String, JsValue> {
+    let inputs = port_names(&MidiInput::new("pg1000cc").map_err(js_error)?);
+    let outputs = port_names(&MidiOutput::new("pg1000cc").map_err(js_error)?);
+    Ok(format!("{}\t{}", inputs.join("\n"), outputs.join("\n")))
+}
+
+/// Starts translating from the input port to the output port, both given
+/// by name or a unique part of it, with the config in YAML (the built-in
+/// mapping if empty). A second call replaces the first.
+#[wasm_bindgen]
+pub fn start(input: &str, output: &str, config: &str) -> Result<(), JsValue> {
+    let config = match config.trim() {
+        "" => Config::default(),
+        yaml => Config::parse(yaml).map_err(js_error)?,
+    };
+    stop();
+    let midi_out = MidiOutput::new("pg1000cc").map_err(js_error)?;
+    let out_port = select_port(&midi_out, "output", "", Some(output), false).map_err(js_error)?;
+    let out_name = midi_out.port_name(&out_port).map_err(js_error)?;
+    let conn_out = midi_out.connect(&out_port, "pg1000cc").map_err(|err| js_error(err.kind()))?;
+    OUTPUT.with(|output| *output.borrow_mut() = Some(conn_out));
+
+    let mapper = Mapper::new(config, vec![(out_name, Box::new(PageOutput))]).map_err(js_error)?;
+    MAPPER.with(|cell| *cell.borrow_mut() = Some(mapper));
+    let midi_in = MidiInput::new("pg1000cc").map_err(js_error)?;
+    let in_port = select_port(&midi_in, "input", "", Some(input), false).map_err(js_error)?;
+    let conn_in = midi_in
+        .connect(&in_port, "pg1000cc", |_, message, _| with_mapper(|mapper| mapper.map(message)), ())
+        .map_err(|err| js_error(err.kind()))?;
+    INPUT.with(|input| *input.borrow_mut() = Some(conn_in));
+    Ok(())
+}
+
+fn with_mapper(f: impl FnOnce(&mut Mapper)) {
+    MAPPER.with(|cell| {
+        if let Some(mapper) = cell.borrow_mut().as_mut() {
+            f(mapper);
+        }
+    });
+}
+
+/// Sends what the rate limit and smoothing held back, to be called every
+/// few milliseconds.
+#[wasm_bindgen]
+pub fn tick() {
+    with_mapper(Mapper::tick);
+}
+
+/// Sends what is still held back and closes the connections.
+#[wasm_bindgen]
+pub fn stop() {
+    INPUT.with(|input| input.borrow_mut().take());
+    with_mapper(Mapper::flush);
+    MAPPER.with(|cell| cell.borrow_mut().take());
+    OUTPUT.with(|output| output.borrow_mut().take());
+}
@@ -0,0 +1,255 @@
+// A deliberately small YAML subset, enough for the config file:
+// block mappings, block sequences, flow sequences of scalars ("[0, 100]"),
+// plain and quoted scalars and '#' comments. Every node remembers the line
+// it came from so config errors can point at it.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    Null,
+    Scalar(String),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub line: usize,
+    pub kind: NodeKind,
+}
+
+impl Node {
+    fn new(line: usize, kind: NodeKind) -> Self {
+        Self { line, kind }
+    }
+
+    pub fn as_map(&self) -> Option<&[(String, Node)]> {
+        match &self.kind {
+            NodeKind::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Node]> {
+        match &self.kind {
+            NodeKind::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_scalar(&self) -> Option<&str> {
+        match &self.kind {
+            NodeKind::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone)]
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+pub fn parse(source: &str) -> Result<Node, ParseError> {
+    let mut lines = vec![];
+    for (i, raw) in source.lines().enumerate() {
+        let number = i + 1;
+        if raw[..raw.len() - raw.trim_start().len()].contains('\t') {
+            return Err(ParseError { line: number, message: "tabs are not allowed for indentation".into() });
+        }
+        let text = strip_comment(raw).trim_end().to_string();
+        if text.trim().is_empty() || text.trim() == "---" {
+            continue;
+        }
+        let indent = text.len() - text.trim_start().len();
+        lines.push(Line { number, indent, text: text.trim_start().to_string() });
+    }
+    if lines.is_empty() {
+        return Ok(Node::new(1, NodeKind::Null));
+    }
+    let mut parser = Parser { lines, pos: 0 };
+    let indent = parser.lines[0].indent;
+    let node = parser.block(indent)?;
+    if let Some(line) = parser.lines.get(parser.pos) {
+        return Err(ParseError { line: line.number, message: "unexpected indentation".into() });
+    }
+    Ok(node)
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Line> {
+        self.lines.get(self.pos)
+    }
+
+    fn block(&mut self, indent: usize) -> Result<Node, ParseError> {
+        let first = self.peek().cloned().expect("block() called past the end");
+        if is_sequence_item(&first.text) {
+            self.sequence(indent)
+        } else {
+            self.mapping(indent)
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Node, ParseError> {
+        let start = self.peek().map(|l| l.number).unwrap_or(1);
+        let mut items = vec![];
+        while let Some(line) = self.peek().cloned() {
+            if line.indent != indent || !is_sequence_item(&line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start();
+            let offset = line.text.len() - rest.len();
+            if rest.is_empty() {
+                // "-" alone: the item is the indented block that follows
+                self.pos += 1;
+                match self.peek() {
+                    Some(next) if next.indent > indent => {
+                        let child = next.indent;
+                        items.push(self.block(child)?);
+                    }
+                    _ => items.push(Node::new(line.number, NodeKind::Null)),
+                }
+            } else if split_key(rest).is_some() || is_sequence_item(rest) {
+                // "- key: value" starts a mapping nested at the column after the dash
+                self.lines[self.pos] = Line { number: line.number, indent: indent + offset, text: rest.to_string() };
+                items.push(self.block(indent + offset)?);
+            } else {
+                self.pos += 1;
+                items.push(parse_inline(rest, line.number)?);
+            }
+        }
+        Ok(Node::new(start, NodeKind::List(items)))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Node, ParseError> {
+        let start = self.peek().map(|l| l.number).unwrap_or(1);
+        let mut entries: Vec<(String, Node)> = vec![];
+        while let Some(line) = self.peek().cloned() {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(ParseError { line: line.number, message: "unexpected indentation".into() });
+            }
+            if is_sequence_item(&line.text) {
+                break;
+            }
+            let (key, value) = split_key(&line.text).ok_or_else(|| ParseError {
+                line: line.number,
+                message: format!("expected 'key: value', found '{}'", line.text),
+            })?;
+            if entries.iter().any(|(k, _)| *k == key) {
+                return Err(ParseError { line: line.number, message: format!("duplicate key '{}'", key) });
+            }
+            self.pos += 1;
+            let node = if value.is_empty() {
+                match self.peek() {
+                    Some(next) if next.indent > indent => {
+                        let child = next.indent;
+                        self.block(child)?
+                    }
+                    // YAML allows a sequence at the same indentation as its key
+                    Some(next) if next.indent == indent && is_sequence_item(&next.text) => self.sequence(indent)?,
+                    _ => Node::new(line.number, NodeKind::Null),
+                }
+            } else {
+                parse_inline(value, line.number)?
+            };
+            entries.push((key, node));
+        }
+        Ok(Node::new(start, NodeKind::Map(entries)))
+    }
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+fn split_key(text: &str) -> Option<(String, &str)> {
+    let (key, rest) = if text.starts_with('"') || text.starts_with('\'') {
+        let quote = text.chars().next().unwrap();
+        let end = text[1..].find(quote)? + 1;
+        (text[1..end].to_string(), &text[end + 1..])
+    } else {
+        let colon = text.find(": ").or_else(|| text.strip_suffix(':').map(|t| t.len()))?;
+        (text[..colon].trim_end().to_string(), &text[colon..])
+    };
+    let value = rest.strip_prefix(':')?;
+    if !value.is_empty() && !value.starts_with(' ') {
+        return None;
+    }
+    if key.is_empty() {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+fn parse_inline(text: &str, line: usize) -> Result<Node, ParseError> {
+    if let Some(inner) = text.strip_prefix('[') {
+        let inner = inner.strip_suffix(']').ok_or_else(|| ParseError { line, message: "unterminated '['".into() })?;
+        let items = if inner.trim().is_empty() {
+            vec![]
+        } else {
+            inner
+                .split(',')
+                .map(|item| parse_scalar(item.trim(), line).map(|s| Node::new(line, NodeKind::Scalar(s))))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        return Ok(Node::new(line, NodeKind::List(items)));
+    }
+    if text == "~" || text == "null" {
+        return Ok(Node::new(line, NodeKind::Null));
+    }
+    Ok(Node::new(line, NodeKind::Scalar(parse_scalar(text, line)?)))
+}
+
+fn parse_scalar(text: &str, line: usize) -> Result<String, ParseError> {
+    for quote in ['"', '\''] {
+        if let Some(inner) = text.strip_prefix(quote) {
+            return inner
+                .strip_suffix(quote)
+                .map(|s| s.to_string())
+                .ok_or_else(|| ParseError { line, message: format!("unterminated {} string", quote) });
+        }
+    }
+    Ok(text.to_string())
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return &line[..i],
+            None => (),
+        }
+        prev = c;
+    }
+    line
+}
@@ -0,0 +1,175 @@
+// The checked-in capture in tests/replay run through `pg1000cc replay` with
+// the built-in mapping, and with each example config in configs/, against
+// the output it has to give. After a change to the mapping that is meant to
+// change it, write it again with
+// `pg1000cc replay --in tests/replay/capture.txt --out tests/replay/expected.txt`
+// (and `--config configs/<name>.yaml --out tests/replay/<name>.expected.txt`)
+// and check the diff. The legacy*.expected.txt are what the first version
+// of pg1000cc sent for the captures, they don't change with the mapping.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/replay");
+
+// A scratch directory of its own for each test, with no config file in it
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pg1000cc-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn replay(scratch: &Path, config: Option<&Path>, input: &Path, output: &Path) {
+    replay_with(scratch, config, input, output, &[]);
+}
+
+fn replay_with(scratch: &Path, config: Option<&Path>, input: &Path, output: &Path, options: &[&str]) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pg1000cc"));
+    command.args(options);
+    if let Some(config) = config {
+        command.arg("--config").arg(config);
+    }
+    let result = command
+        .arg("replay")
+        .arg("--in")
+        .arg(input)
+        .arg("--out")
+        .arg(output)
+        // Not the user's own config, the built-in mapping or the one given
+        .env("XDG_CONFIG_HOME", scratch)
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "replay failed: {}", String::from_utf8_lossy(&result.stderr));
+}
+
+// The bytes of a hex capture, without the comments
+fn hex_bytes(text: &str) -> Vec<u8> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(str::split_whitespace)
+        .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn replays_the_capture() {
+    let scratch = scratch("hex");
+    let output = scratch.join("output.txt");
+    replay(&scratch, None, &Path::new(DIR).join("capture.txt"), &output);
+    let expected = fs::read_to_string(Path::new(DIR).join("expected.txt")).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+}
+
+#[test]
+fn replays_the_capture_as_raw_bytes() {
+    let scratch = scratch("raw");
+    let input = scratch.join("capture.syx");
+    fs::write(&input, hex_bytes(&fs::read_to_string(Path::new(DIR).join("capture.txt")).unwrap())).unwrap();
+    let output = scratch.join("output.bin");
+    replay(&scratch, None, &input, &output);
+    let expected = hex_bytes(&fs::read_to_string(Path::new(DIR).join("expected.txt")).unwrap());
+    assert_eq!(fs::read(&output).unwrap(), expected);
+}
+
+#[test]
+fn replays_the_capture_with_the_examples() {
+    let scratch = scratch("examples");
+    let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("configs");
+    let mut replayed = 0;
+    for entry in fs::read_dir(&configs).unwrap() {
+        let config = entry.unwrap().path();
+        let name = config.file_stem().unwrap().to_str().unwrap().to_string();
+        let output = scratch.join(format!("{}.txt", name));
+        replay(&scratch, Some(&config), &Path::new(DIR).join("capture.txt"), &output);
+        let expected = fs::read_to_string(Path::new(DIR).join(format!("{}.expected.txt", name))).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_eq!(fs::read_to_string(&output).unwrap(), expected, "{}", name);
+        replayed += 1;
+    }
+    assert_eq!(replayed, 4);
+}
+
+#[test]
+fn sample_config_replays_as_the_builtin_mapping() {
+    let scratch = scratch("sample");
+    let output = scratch.join("output.txt");
+    let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("pg1000cc.sample.yaml");
+    replay(&scratch, Some(&sample), &Path::new(DIR).join("capture.txt"), &output);
+    let expected = fs::read_to_string(Path::new(DIR).join("expected.txt")).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+}
+
+#[test]
+fn converts_into_a_midi_file_and_back() {
+    let scratch = scratch("smf");
+    let smf = scratch.join("automation.mid");
+    replay(&scratch, None, &Path::new(DIR).join("capture.txt"), &smf);
+    // Replayed again, what was translated the first time passes through as
+    // it is. Clock has no place in a MIDI file.
+    let output = scratch.join("output.txt");
+    replay(&scratch, None, &smf, &output);
+    let expected = fs::read_to_string(Path::new(DIR).join("expected.txt")).unwrap().replace("F8\n", "");
+    assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+    // The same as raw bytes
+    let syx = scratch.join("output.syx");
+    replay(&scratch, None, &smf, &syx);
+    assert_eq!(fs::read(&syx).unwrap(), hex_bytes(&expected));
+}
+
+#[test]
+fn spaces_the_messages_as_asked() {
+    let scratch = scratch("spacing");
+    let smf = scratch.join("automation.mid");
+    let input = scratch.join("capture.txt");
+    // Chorus Rate at 0, 50 and 100
+    fs::write(&input, "F0 41 00 14 12 00 01 2B 00 54 F7\nF0 41 00 14 12 00 01 2B 32 22 F7\nF0 41 00 14 12 00 01 2B 64 70 F7\n").unwrap();
+    let events = |options: &[&str]| {
+        replay_with(&scratch, None, &input, &smf, options);
+        pg1000cc::smf::read(&fs::read(&smf).unwrap()).unwrap()
+    };
+    let messages = [vec![0xb1, 85, 0], vec![0xb1, 85, 64], vec![0xb1, 85, 127]];
+    let spaced = events(&["--spacing", "250"]);
+    let millis: Vec<u128> = spaced.iter().map(|(time, _)| time.as_millis()).collect();
+    // A tick is just over a millisecond, so the times can come out a bit earlier
+    assert!(millis == [0, 250, 500] || millis == [0, 249, 499], "{:?}", millis);
+    assert_eq!(spaced.into_iter().map(|(_, message)| message).collect::<Vec<_>>(), messages);
+    // Without, as long as the 11 bytes of each take on a MIDI cable, 3.5 ms,
+    // down to the tick
+    let millis: Vec<u128> = events(&[]).iter().map(|(time, _)| time.as_millis()).collect();
+    assert_eq!(millis, [0, 3, 6]);
+}
+
+// The captures with each through --legacy-compat and through the config
+// migrate-legacy writes, against what the first version sent for them
+fn replays_as_the_first_version(scratch: &Path, config: Option<&Path>, options: &[&str]) {
+    for (capture, expected) in [("capture.txt", "legacy.expected.txt"), ("legacy.capture.txt", "legacy.quirks.expected.txt")] {
+        let output = scratch.join(expected);
+        replay_with(scratch, config, &Path::new(DIR).join(capture), &output, options);
+        let expected = fs::read_to_string(Path::new(DIR).join(expected)).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), expected, "{}", capture);
+    }
+}
+
+#[test]
+fn replays_as_the_first_version_with_legacy_compat() {
+    let scratch = scratch("legacy");
+    replays_as_the_first_version(&scratch, None, &["--legacy-compat"]);
+}
+
+#[test]
+fn migrates_off_legacy_compat() {
+    let scratch = scratch("migrate");
+    let config = scratch.join("legacy.yaml");
+    let migrate = || Command::new(env!("CARGO_BIN_EXE_pg1000cc")).arg("migrate-legacy").arg(&config).env("XDG_CONFIG_HOME", &scratch).output().unwrap();
+    let result = migrate();
+    assert!(result.status.success(), "migrate-legacy failed: {}", String::from_utf8_lossy(&result.stderr));
+    let diff = String::from_utf8(result.stdout).unwrap();
+    assert!(diff.starts_with("--- built-in\n+++ legacy\n@@ "), "{}", diff);
+    assert!(diff.lines().any(|line| line == "+send_repeats: true"), "{}", diff);
+    replays_as_the_first_version(&scratch, Some(&config), &[]);
+    // The file written isn't written over
+    let written = fs::read_to_string(&config).unwrap();
+    assert!(!migrate().status.success());
+    assert_eq!(fs::read_to_string(&config).unwrap(), written);
+}